@@ -0,0 +1,352 @@
+use screeps::constants::{
+    Part, CONTROLLER_MAX_UPGRADE_PER_TICK, ENERGY_REGEN_TIME, HARVEST_POWER, MAX_CREEP_SIZE,
+    SOURCE_ENERGY_CAPACITY, UPGRADE_CONTROLLER_POWER,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::SumParts;
+
+// enough work parts to empty a source right as it regenerates
+pub const MINER_WORK_PARTS: u32 = SOURCE_ENERGY_CAPACITY / ENERGY_REGEN_TIME / HARVEST_POWER;
+// enough work parts to upgrade a level 8 controller as fast as it allows
+pub const STATIC_UPGRADER_WORK_PARTS: u32 =
+    CONTROLLER_MAX_UPGRADE_PER_TICK / UPGRADE_CONTROLLER_POWER;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Role {
+    // mines its own energy and keeps spawns, extensions and towers filled before doing
+    // anything else with it
+    Harvester,
+    // mines its own energy and puts all of it into the controller
+    Upgrader,
+    // mines its own energy and works on construction sites before anything else
+    Builder,
+    // heads for the room marked by a claim flag and reserves or claims its controller
+    Claimer,
+    // wanders through the rooms around ours, noting down what's in them
+    Scout,
+    // sits on a source's container and does nothing but harvest into it, or drops what it
+    // harvests on the ground next to a source that hasn't got a container yet
+    Miner,
+    // carries energy from source containers, or the piles miners drop, to spawns,
+    // extensions and towers
+    Hauler,
+    // shoots from range, backing off from anything that hits in melee and healing itself
+    RangedAttacker,
+    // stands by the controller's container or link in a level 8 room, upgrading out of it
+    // and never going anywhere else
+    StaticUpgrader,
+    // walks up to hostiles in our rooms and hits them
+    Defender,
+    // takes apart structures marked with a dismantle flag, spending the energy they give
+    // back like any other worker
+    Dismantler,
+}
+
+impl Role {
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Harvester => "harvester",
+            Role::Upgrader => "upgrader",
+            Role::Builder => "builder",
+            Role::Claimer => "claimer",
+            Role::Scout => "scout",
+            Role::Miner => "miner",
+            Role::Hauler => "hauler",
+            Role::RangedAttacker => "ranged",
+            Role::StaticUpgrader => "static",
+            Role::Defender => "defender",
+            Role::Dismantler => "dismantler",
+        }
+    }
+
+    // creeps carry their role as the first part of their name. Creeps from before roles
+    // existed did a bit of everything, which is closest to what a harvester does
+    pub fn of_creep(name: &str) -> Role {
+        match name.split('-').next() {
+            Some("upgrader") => Role::Upgrader,
+            Some("builder") => Role::Builder,
+            Some("claimer") => Role::Claimer,
+            Some("scout") => Role::Scout,
+            Some("miner") => Role::Miner,
+            Some("hauler") => Role::Hauler,
+            Some("ranged") => Role::RangedAttacker,
+            Some("static") => Role::StaticUpgrader,
+            Some("defender") => Role::Defender,
+            Some("dismantler") => Role::Dismantler,
+            _ => Role::Harvester,
+        }
+    }
+
+    // the order creeps get run in, lowest first. Whatever keeps energy coming in goes
+    // ahead of whatever spends it
+    pub fn priority(self) -> u8 {
+        match self {
+            Role::Harvester => 0,
+            Role::Miner => 1,
+            Role::Hauler => 2,
+            Role::RangedAttacker | Role::Defender => 3,
+            Role::Upgrader | Role::StaticUpgrader => 4,
+            Role::Builder | Role::Dismantler => 5,
+            Role::Claimer => 6,
+            Role::Scout => 7,
+        }
+    }
+
+    // roles that fetch their own energy and then go spend it, switching between the two
+    // as their store fills and empties. Dismantlers get theirs out of what they take apart
+    pub fn gathers_energy(self) -> bool {
+        matches!(
+            self,
+            Role::Harvester | Role::Upgrader | Role::Builder | Role::Hauler | Role::Dismantler
+        )
+    }
+
+    // roles that leave home to do their job, and so can't be counted by which room
+    // they're in
+    pub fn roams(self) -> bool {
+        matches!(self, Role::Claimer | Role::Scout | Role::Dismantler)
+    }
+
+    // a scout only ever needs to get somewhere, and a miner or static upgrader past the
+    // work parts it can keep busy would just sit idle, so extra repeats wouldn't help either
+    fn max_repeats(self) -> u32 {
+        match self {
+            Role::Scout => 1,
+            Role::Miner => MINER_WORK_PARTS,
+            Role::StaticUpgrader => STATIC_UPGRADER_WORK_PARTS,
+            _ => u32::MAX,
+        }
+    }
+
+    // parts every body of the role gets exactly once, ahead of the repeated pattern
+    fn base(self) -> &'static [Part] {
+        match self {
+            Role::Miner => &[Part::Move],
+            Role::StaticUpgrader => &[Part::Carry, Part::Move],
+            _ => &[],
+        }
+    }
+
+    // the chunk of parts a body is built up from, repeated as many times as energy allows
+    fn pattern(self) -> &'static [Part] {
+        match self {
+            Role::Harvester | Role::Upgrader | Role::Builder => {
+                &[Part::Work, Part::Carry, Part::Move]
+            }
+            Role::Claimer => &[Part::Claim, Part::Move],
+            Role::Scout => &[Part::Move],
+            Role::Miner | Role::StaticUpgrader => &[Part::Work],
+            Role::Hauler => &[Part::Carry, Part::Carry, Part::Move],
+            Role::RangedAttacker => &[
+                Part::Tough,
+                Part::Move,
+                Part::RangedAttack,
+                Part::Move,
+                Part::Heal,
+            ],
+            Role::Defender => &[Part::Tough, Part::Move, Part::Attack, Part::Move],
+            Role::Dismantler => &[Part::Work, Part::Work, Part::Carry, Part::Move],
+        }
+    }
+}
+
+// why a body can't be spawned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BodyError {
+    Empty,
+    TooManyParts { parts: usize, cost: u32 },
+}
+
+impl std::fmt::Display for BodyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BodyError::Empty => write!(f, "body has no parts"),
+            BodyError::TooManyParts { parts, cost } => write!(
+                f,
+                "body has {parts} parts costing {cost}, over the {MAX_CREEP_SIZE} part limit"
+            ),
+        }
+    }
+}
+
+// the checks the game would otherwise only answer with InvalidArgs
+pub fn validate_body(parts: &[Part]) -> Result<(), BodyError> {
+    if parts.is_empty() {
+        return Err(BodyError::Empty);
+    }
+    if parts.len() > MAX_CREEP_SIZE as usize {
+        return Err(BodyError::TooManyParts {
+            parts: parts.len(),
+            cost: parts.sum_parts(),
+        });
+    }
+    Ok(())
+}
+
+// the role's base parts followed by its pattern, repeated as many times as `capacity`
+// energy can pay for without going past the 50 part limit. Comes back empty if not even a
+// single repeat is affordable
+pub fn build_body(role: Role, capacity: u32) -> Vec<Part> {
+    let base = role.base();
+    let pattern = role.pattern();
+    let Some(capacity) = capacity.checked_sub(base.sum_parts()) else {
+        return Vec::new();
+    };
+
+    let repeats = (capacity / pattern.sum_parts())
+        .min((MAX_CREEP_SIZE - base.len() as u32) / pattern.len() as u32)
+        .min(role.max_repeats());
+    if repeats == 0 {
+        return Vec::new();
+    }
+
+    base.iter()
+        .copied()
+        .chain(
+            pattern
+                .iter()
+                .copied()
+                .cycle()
+                .take(repeats as usize * pattern.len()),
+        )
+        .collect()
+}
+
+// how many move parts keep a body with `other` non-move parts moving a tile every tick
+// when fully loaded. Each move part takes off 2 fatigue a tick, and every other part adds
+// 1 a tile on roads or 2 off them
+pub fn moves_needed(other: u32, roads: bool) -> u32 {
+    if roads {
+        other.div_ceil(2)
+    } else {
+        other
+    }
+}
+
+// the most carry parts `capacity` energy buys along with the moves to keep them at full
+// speed, off roads or on them, within the 50 part limit. Comes back empty if not even one
+// carry is affordable
+pub fn build_hauler_body(capacity: u32, roads: bool) -> Vec<Part> {
+    (1..=MAX_CREEP_SIZE)
+        .rev()
+        .map(|carries| hauler_body(carries, roads))
+        .find(|body| body.len() <= MAX_CREEP_SIZE as usize && body.sum_parts() <= capacity)
+        .unwrap_or_default()
+}
+
+// `carries` carry parts and the moves they need
+fn hauler_body(carries: u32, roads: bool) -> Vec<Part> {
+    std::iter::repeat(Part::Carry)
+        .take(carries as usize)
+        .chain(std::iter::repeat(Part::Move).take(moves_needed(carries, roads) as usize))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starter_room_gets_one_repeat() {
+        assert_eq!(
+            build_body(Role::Harvester, 300),
+            vec![Part::Work, Part::Carry, Part::Move]
+        );
+        assert_eq!(build_body(Role::Builder, 300).sum_parts(), 200);
+    }
+
+    #[test]
+    fn too_little_energy_gets_nothing() {
+        assert!(build_body(Role::Harvester, 199).is_empty());
+        assert!(build_body(Role::Miner, 100).is_empty());
+    }
+
+    #[test]
+    fn bodies_stop_at_fifty_parts() {
+        for role in [
+            Role::Harvester,
+            Role::Hauler,
+            Role::Defender,
+            Role::Dismantler,
+        ] {
+            let body = build_body(role, 1_000_000);
+            assert!(body.len() <= MAX_CREEP_SIZE as usize, "{role:?}");
+            assert!(body.len() + role.pattern().len() > MAX_CREEP_SIZE as usize);
+        }
+        assert_eq!(build_body(Role::Harvester, 1_000_000).len(), 48);
+    }
+
+    #[test]
+    fn capped_roles_stop_early() {
+        let miner = build_body(Role::Miner, 1_000_000);
+        assert_eq!(miner.len() as u32, 1 + MINER_WORK_PARTS);
+        assert_eq!(build_body(Role::Scout, 1_000_000), vec![Part::Move]);
+    }
+
+    #[test]
+    fn roads_halve_the_moves() {
+        assert_eq!(moves_needed(0, false), 0);
+        assert_eq!(moves_needed(1, false), 1);
+        assert_eq!(moves_needed(4, false), 4);
+        assert_eq!(moves_needed(1, true), 1);
+        assert_eq!(moves_needed(3, true), 2);
+        assert_eq!(moves_needed(4, true), 2);
+    }
+
+    #[test]
+    fn hauler_bodies_fit_the_budget() {
+        let count = |body: &[Part], part| body.iter().filter(|&&p| p == part).count();
+
+        let off_road = build_hauler_body(550, false);
+        assert_eq!(
+            (count(&off_road, Part::Carry), count(&off_road, Part::Move)),
+            (5, 5)
+        );
+        let on_road = build_hauler_body(550, true);
+        assert_eq!(
+            (count(&on_road, Part::Carry), count(&on_road, Part::Move)),
+            (7, 4)
+        );
+        assert!(on_road.sum_parts() <= 550);
+    }
+
+    #[test]
+    fn hauler_bodies_stop_at_fifty_parts() {
+        let off_road = build_hauler_body(1_000_000, false);
+        assert_eq!(off_road.len(), 50);
+        assert_eq!(off_road.iter().filter(|&&p| p == Part::Carry).count(), 25);
+
+        let on_road = build_hauler_body(1_000_000, true);
+        assert_eq!(on_road.len(), 50);
+        assert_eq!(on_road.iter().filter(|&&p| p == Part::Carry).count(), 33);
+    }
+
+    #[test]
+    fn haulers_need_a_hundred_energy() {
+        assert!(build_hauler_body(99, false).is_empty());
+        assert!(build_hauler_body(99, true).is_empty());
+        assert_eq!(build_hauler_body(100, false), vec![Part::Carry, Part::Move]);
+    }
+
+    #[test]
+    fn empty_bodies_are_invalid() {
+        assert_eq!(validate_body(&[]), Err(BodyError::Empty));
+    }
+
+    #[test]
+    fn fifty_parts_is_the_limit() {
+        let fifty = vec![Part::Move; MAX_CREEP_SIZE as usize];
+        assert_eq!(validate_body(&fifty), Ok(()));
+        assert_eq!(validate_body(&[Part::Move]), Ok(()));
+
+        let fifty_one = vec![Part::Move; MAX_CREEP_SIZE as usize + 1];
+        assert_eq!(
+            validate_body(&fifty_one),
+            Err(BodyError::TooManyParts {
+                parts: 51,
+                cost: 51 * Part::Move.cost(),
+            })
+        );
+    }
+}
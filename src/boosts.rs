@@ -0,0 +1,129 @@
+use js_sys::Reflect;
+use log::*;
+use screeps::{
+    constants::{Part, ResourceType, LAB_BOOST_ENERGY, LAB_BOOST_MINERAL},
+    enums::StructureObject,
+    objects::{Creep, StructureLab},
+    prelude::*,
+};
+use wasm_bindgen::JsValue;
+
+use crate::body::Role;
+use crate::movement::DefaultMove;
+use crate::{find_cache, CreepTarget};
+
+// the compounds each role gets boosted with, and the parts they go on
+const BOOSTS: &[(Role, Part, ResourceType)] = &[
+    (
+        Role::RangedAttacker,
+        Part::RangedAttack,
+        ResourceType::KeaniumOxide,
+    ),
+    (
+        Role::RangedAttacker,
+        Part::Heal,
+        ResourceType::LemergiumOxide,
+    ),
+    (Role::Upgrader, Part::Work, ResourceType::GhodiumHydride),
+    (
+        Role::StaticUpgrader,
+        Part::Work,
+        ResourceType::GhodiumHydride,
+    ),
+    (Role::Builder, Part::Work, ResourceType::LemergiumHydride),
+    (Role::Miner, Part::Work, ResourceType::UtriumOxide),
+];
+
+// whether the creep has already been through the labs, kept in its memory
+fn is_boosted(creep: &Creep) -> bool {
+    Reflect::get(&creep.memory(), &JsValue::from_str("boosted"))
+        .ok()
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+// renewing a creep strips its boosts, so one that's been renewed goes back through the
+// labs for them the next time it's looking for something to do
+pub fn boosts_lost(creep: &Creep) {
+    let _ = Reflect::delete_property(&creep.memory().into(), &JsValue::from_str("boosted"));
+}
+
+fn mark_boosted(creep: &Creep) {
+    let _ = Reflect::set(
+        &creep.memory(),
+        &JsValue::from_str("boosted"),
+        &JsValue::TRUE,
+    );
+}
+
+// a lab holding enough of `compound`, and the energy, to boost at least one part
+fn loaded_lab(creep: &Creep, compound: ResourceType) -> Option<StructureLab> {
+    let room = creep.room()?;
+    find_cache::structures(&room)
+        .iter()
+        .filter_map(|structure| match structure {
+            StructureObject::StructureLab(lab) if lab.my() => Some(lab),
+            _ => None,
+        })
+        .filter(|lab| {
+            let store = lab.store();
+            lab.mineral_type() == Some(compound)
+                && store.get_used_capacity(Some(compound)) >= LAB_BOOST_MINERAL
+                && store.get_used_capacity(Some(ResourceType::Energy)) >= LAB_BOOST_ENERGY
+        })
+        .min_by_key(|lab| creep.pos().get_range_to(lab.pos()))
+        .cloned()
+}
+
+// where a creep that hasn't been boosted yet should go to get its next boost. Once there's
+// nothing left it can get here, whether it's all applied or the labs just aren't loaded,
+// the creep is marked as done and gets on with its normal duties for good
+pub fn boost_target(creep: &Creep, role: Role) -> Option<CreepTarget> {
+    if is_boosted(creep) {
+        return None;
+    }
+
+    let body = creep.body();
+    let next = BOOSTS
+        .iter()
+        .filter(|(boosted_role, _, _)| *boosted_role == role)
+        .filter(|(_, part, _)| {
+            body.iter()
+                .any(|body_part| body_part.part() == *part && body_part.boost().is_none())
+        })
+        .find_map(|&(_, _, compound)| {
+            let lab = loaded_lab(creep, compound)?;
+            Some(CreepTarget::Boost {
+                lab: lab.id(),
+                compound,
+            })
+        });
+
+    if next.is_none() {
+        mark_boosted(creep);
+    }
+    next
+}
+
+// one visit to a lab, returning whether the creep is done with it
+pub fn apply_boost(creep: &Creep, lab: &StructureLab, compound: ResourceType) -> bool {
+    if !creep.pos().is_near_to(lab.pos()) {
+        let _ = creep.default_move_to(lab);
+        return false;
+    }
+
+    match lab.boost_creep(creep, None) {
+        Ok(()) => info!("boosted {} with {:?}", creep.name(), compound),
+        Err(e) => {
+            // don't keep walking back to a lab that won't boost us
+            warn!(
+                "couldn't boost {} with {:?}: {:?}",
+                creep.name(),
+                compound,
+                e
+            );
+            mark_boosted(creep);
+        }
+    }
+    true
+}
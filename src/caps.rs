@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use log::*;
+use screeps::{game, prelude::*};
+
+use crate::body::Role;
+use crate::config;
+use crate::recycling::recycle_target;
+use crate::spawning::spawned_roles;
+use crate::CreepTarget;
+
+// hard limits on how many creeps there can be, as a backstop for the spawn queue
+pub struct CreepCaps {
+    pub max_creeps: usize,
+    pub roles: HashMap<Role, usize>,
+}
+
+impl CreepCaps {
+    pub fn load() -> Self {
+        CreepCaps {
+            max_creeps: config::max_creeps(),
+            roles: config::role_caps(),
+        }
+    }
+
+    // which cap, if either, one more `role` would go past, given `total` creeps alive and
+    // `of_role` of them with the role
+    pub fn blocked_by(&self, role: Role, total: usize, of_role: usize) -> Option<&'static str> {
+        if total >= self.max_creeps {
+            Some("total")
+        } else if self.roles.get(&role).is_some_and(|&cap| of_role >= cap) {
+            Some("role")
+        } else {
+            None
+        }
+    }
+}
+
+// every creep by role, going by their names, including the ones spawned earlier this tick
+pub fn live_creeps(now: u32) -> HashMap<Role, usize> {
+    let mut live = spawned_roles(now);
+    for name in game::creeps().keys() {
+        *live.entry(Role::of_creep(&name)).or_default() += 1;
+    }
+    live
+}
+
+// the creeps, given as (name, role, ticks to live), that put their role or the total past
+// its cap. It's always the newest that go, since the oldest are closest to dying anyway
+pub fn over_cap(mut creeps: Vec<(String, Role, u32)>, caps: &CreepCaps) -> Vec<String> {
+    creeps.sort_by_key(|&(_, _, ticks)| ticks);
+
+    let mut kept: HashMap<Role, usize> = HashMap::new();
+    let mut total = 0;
+    let mut surplus = Vec::new();
+    for (name, role, _) in creeps {
+        let of_role = kept.entry(role).or_default();
+        if caps.blocked_by(role, total, *of_role).is_some() {
+            surplus.push(name);
+        } else {
+            *of_role += 1;
+            total += 1;
+        }
+    }
+    surplus
+}
+
+// sends any creeps past the caps off to be recycled
+pub fn recycle_over_cap(creep_targets: &mut HashMap<String, CreepTarget>) {
+    let caps = CreepCaps::load();
+    let creeps: Vec<(String, Role, u32)> = game::creeps()
+        .values()
+        .filter(|creep| !creep.spawning())
+        .filter(|creep| {
+            !matches!(
+                creep_targets.get(&creep.name()),
+                Some(CreepTarget::Recycle(_))
+            )
+        })
+        .map(|creep| {
+            let name = creep.name();
+            let role = Role::of_creep(&name);
+            (name, role, creep.ticks_to_live().unwrap_or(u32::MAX))
+        })
+        .collect();
+
+    for name in over_cap(creeps, &caps) {
+        let Some(creep) = game::creeps().get(name.clone()) else {
+            continue;
+        };
+        if let Some(target) = recycle_target(&creep) {
+            warn!("{name} is over the creep caps, recycling it");
+            creep_targets.insert(name, target);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(max_creeps: usize, roles: &[(Role, usize)]) -> CreepCaps {
+        CreepCaps {
+            max_creeps,
+            roles: roles.iter().copied().collect(),
+        }
+    }
+
+    // `count` haulers, the nth with n * 100 ticks to live
+    fn haulers(count: u32) -> Vec<(String, Role, u32)> {
+        (1..=count)
+            .map(|n| (format!("hauler-{n}"), Role::Hauler, n * 100))
+            .collect()
+    }
+
+    #[test]
+    fn nothing_goes_at_or_below_the_cap() {
+        let caps = caps(100, &[(Role::Hauler, 3)]);
+        assert!(over_cap(haulers(2), &caps).is_empty());
+        assert!(over_cap(haulers(3), &caps).is_empty());
+    }
+
+    #[test]
+    fn the_newest_go_above_the_cap() {
+        let caps = caps(100, &[(Role::Hauler, 3)]);
+        assert_eq!(over_cap(haulers(4), &caps), ["hauler-4"]);
+
+        let mut shuffled = haulers(5);
+        shuffled.reverse();
+        let mut surplus = over_cap(shuffled, &caps);
+        surplus.sort();
+        assert_eq!(surplus, ["hauler-4", "hauler-5"]);
+    }
+
+    #[test]
+    fn roles_without_a_cap_only_count_towards_the_total() {
+        let caps = caps(4, &[(Role::Upgrader, 1)]);
+        assert!(over_cap(haulers(4), &caps).is_empty());
+        assert_eq!(over_cap(haulers(6), &caps), ["hauler-5", "hauler-6"]);
+        assert_eq!(caps.blocked_by(Role::Hauler, 3, 3), None);
+        assert_eq!(caps.blocked_by(Role::Hauler, 4, 0), Some("total"));
+        assert_eq!(caps.blocked_by(Role::Upgrader, 0, 1), Some("role"));
+    }
+}
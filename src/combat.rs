@@ -0,0 +1,297 @@
+use log::*;
+use screeps::{
+    constants::{Direction, Part, RANGED_ATTACK_POWER},
+    enums::StructureObject,
+    find,
+    local::RoomXY,
+    objects::{Creep, Structure},
+    prelude::*,
+};
+
+use crate::config::RoomConfig;
+use crate::movement::{DefaultMove, DIRECTIONS};
+use crate::tower::select_target;
+
+// how close a melee hostile can get before we back off from it
+const KITE_RANGE: u32 = 2;
+// friendly healers this close count as support for a badly hurt creep
+const SUPPORT_RANGE: u8 = 3;
+
+// what a combat creep should do about the damage it's taken
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wounds {
+    // keep fighting
+    Fine,
+    // heal up before attacking again
+    Heal,
+    // get back to the spawn or behind a rampart
+    Retreat,
+}
+
+// how a creep with `hits` out of `hits_max` should react, given whether it can heal itself
+// and whether there's a friendly healer close by
+pub fn assess_wounds(
+    hits: u32,
+    hits_max: u32,
+    can_heal: bool,
+    supported: bool,
+    config: &RoomConfig,
+) -> Wounds {
+    if hits_max == 0 {
+        return Wounds::Fine;
+    }
+    let fraction = hits as f64 / hits_max as f64;
+    if fraction < config.combat_retreat_fraction && !supported {
+        Wounds::Retreat
+    } else if fraction < config.combat_heal_fraction && can_heal {
+        Wounds::Heal
+    } else {
+        Wounds::Fine
+    }
+}
+
+// heals or pulls back a hurt combat creep instead of letting it fight on. Returns whether
+// it did, in which case it shouldn't attack this tick
+pub fn look_after_self(creep: &Creep, config: &RoomConfig) -> bool {
+    let can_heal = creep.get_active_bodyparts(Part::Heal) > 0;
+    let supported = || {
+        creep
+            .pos()
+            .find_in_range(find::MY_CREEPS, SUPPORT_RANGE)
+            .iter()
+            .any(|other| other.name() != creep.name() && other.get_active_bodyparts(Part::Heal) > 0)
+    };
+    // most ticks a creep is nowhere near hurt enough, so skip looking around for that
+    let hurt_below = config
+        .combat_heal_fraction
+        .max(config.combat_retreat_fraction);
+    let wounds = if creep.hits() as f64 >= creep.hits_max() as f64 * hurt_below {
+        Wounds::Fine
+    } else {
+        assess_wounds(
+            creep.hits(),
+            creep.hits_max(),
+            can_heal,
+            supported(),
+            config,
+        )
+    };
+
+    match wounds {
+        Wounds::Fine => false,
+        Wounds::Heal => {
+            heal_self(creep);
+            step_away_from_melee(creep);
+            true
+        }
+        Wounds::Retreat => {
+            heal_self(creep);
+            retreat(creep);
+            true
+        }
+    }
+}
+
+// backs away from any melee hostile in reach, returning whether it had to
+fn step_away_from_melee(creep: &Creep) -> bool {
+    let melee: Vec<RoomXY> = creep
+        .pos()
+        .find_in_range(find::HOSTILE_CREEPS, KITE_RANGE as u8)
+        .iter()
+        .filter(|hostile| hostile.get_active_bodyparts(Part::Attack) > 0)
+        .map(|hostile| hostile.pos().xy())
+        .collect();
+
+    match flee_direction(creep.pos().xy(), &melee) {
+        Some(direction) => {
+            let _ = creep.move_direction(direction);
+            true
+        }
+        None => false,
+    }
+}
+
+// out of reach of melee first, then back to the closest of our ramparts or spawns to wait
+// there until it's healed
+fn retreat(creep: &Creep) {
+    if step_away_from_melee(creep) {
+        return;
+    }
+
+    let Some(room) = creep.room() else {
+        return;
+    };
+    let shelter = room
+        .find(find::MY_STRUCTURES, None)
+        .into_iter()
+        .filter(|structure| {
+            matches!(
+                structure,
+                StructureObject::StructureRampart(_) | StructureObject::StructureSpawn(_)
+            )
+        })
+        .min_by_key(|structure| creep.pos().get_range_to(structure.pos()));
+
+    match shelter {
+        // a rampart can be stood on, a spawn only next to
+        Some(StructureObject::StructureRampart(rampart)) if creep.pos() != rampart.pos() => {
+            let _ = creep.default_move_to(&rampart);
+        }
+        Some(StructureObject::StructureSpawn(spawn)) if !creep.pos().is_near_to(spawn.pos()) => {
+            let _ = creep.default_move_to(&spawn);
+        }
+        _ => {}
+    }
+}
+
+// the hostile creep a ranged attacker should go after, scored the same way towers
+// pick theirs
+pub fn select_ranged_target<'a>(creep: &Creep, hostiles: &'a [Creep]) -> Option<&'a Creep> {
+    let power = creep.get_active_bodyparts(Part::RangedAttack) as u32 * RANGED_ATTACK_POWER;
+    let damage = |range| if range <= 3 { power } else { 0 };
+    select_target(creep.pos(), hostiles, damage)
+}
+
+// one tick of ranged combat against `target`: heal up, shoot, and keep out of reach of
+// anything that hits back in melee
+pub fn fight(creep: &Creep, target: &Creep) {
+    heal_self(creep);
+
+    let nearby = creep.pos().find_in_range(find::HOSTILE_CREEPS, 3);
+    let adjacent = nearby
+        .iter()
+        .filter(|hostile| creep.pos().is_near_to(hostile.pos()))
+        .count();
+
+    if adjacent > 1 {
+        creep.ranged_mass_attack().unwrap_or_else(|e| {
+            warn!("couldn't mass attack: {:?}", e);
+        });
+    } else if creep.pos().in_range_to(target.pos(), 3) {
+        creep.ranged_attack(target).unwrap_or_else(|e| {
+            warn!("couldn't ranged attack: {:?}", e);
+        });
+    }
+
+    let melee: Vec<RoomXY> = nearby
+        .iter()
+        .filter(|hostile| hostile.get_active_bodyparts(Part::Attack) > 0)
+        .filter(|hostile| creep.pos().in_range_to(hostile.pos(), KITE_RANGE))
+        .map(|hostile| hostile.pos().xy())
+        .collect();
+
+    if let Some(direction) = flee_direction(creep.pos().xy(), &melee) {
+        let _ = creep.move_direction(direction);
+    } else if !creep.pos().in_range_to(target.pos(), 3) {
+        let _ = creep.default_move_to(target);
+    }
+}
+
+// works on a hostile structure we've been told to clear with a flag. Bodies that can
+// shoot do it from range, anything else gets up close and either dismantles it, if it has
+// more work parts than attack parts, or hits it
+pub fn siege(creep: &Creep, structure: Structure) {
+    heal_self(creep);
+
+    let ranged = creep.get_active_bodyparts(Part::RangedAttack) > 0;
+    let reach = if ranged { 3 } else { 1 };
+    if !creep.pos().in_range_to(structure.pos(), reach) {
+        let _ = creep.default_move_to(&structure);
+        return;
+    }
+
+    let structure = StructureObject::from(structure);
+    if ranged {
+        if let Some(attackable) = structure.as_attackable() {
+            creep.ranged_attack(attackable).unwrap_or_else(|e| {
+                warn!("couldn't ranged attack structure: {:?}", e);
+            });
+        }
+    } else if creep.get_active_bodyparts(Part::Work) > creep.get_active_bodyparts(Part::Attack) {
+        if let Some(dismantleable) = structure.as_dismantleable() {
+            creep.dismantle(dismantleable).unwrap_or_else(|e| {
+                warn!("couldn't dismantle structure: {:?}", e);
+            });
+        }
+    } else if let Some(attackable) = structure.as_attackable() {
+        creep.attack(attackable).unwrap_or_else(|e| {
+            warn!("couldn't attack structure: {:?}", e);
+        });
+    }
+}
+
+fn heal_self(creep: &Creep) {
+    if creep.hits() < creep.hits_max() && creep.get_active_bodyparts(Part::Heal) > 0 {
+        creep.heal(creep).unwrap_or_else(|e| {
+            warn!("couldn't heal: {:?}", e);
+        });
+    }
+}
+
+// the direction leading most directly away from all of `threats` at once, or None if
+// there's nothing to run from (or they cancel each other out)
+pub fn flee_direction(from: RoomXY, threats: &[RoomXY]) -> Option<Direction> {
+    let (dx, dy) = threats.iter().fold((0i32, 0i32), |(dx, dy), threat| {
+        (
+            dx + from.x.u8() as i32 - threat.x.u8() as i32,
+            dy + from.y.u8() as i32 - threat.y.u8() as i32,
+        )
+    });
+
+    let away = (dx.signum(), dy.signum());
+    DIRECTIONS
+        .into_iter()
+        .find(|&direction| <(i32, i32)>::from(direction) == away)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // at the default heal (0.5) and retreat (0.2) fractions, out of 1000 hits
+    fn wounds(hits: u32, can_heal: bool, supported: bool) -> Wounds {
+        assess_wounds(hits, 1_000, can_heal, supported, &RoomConfig::default())
+    }
+
+    #[test]
+    fn heals_below_the_heal_fraction() {
+        assert_eq!(wounds(1_000, true, false), Wounds::Fine);
+        assert_eq!(wounds(500, true, false), Wounds::Fine);
+        assert_eq!(wounds(499, true, false), Wounds::Heal);
+        assert_eq!(wounds(200, true, false), Wounds::Heal);
+        // nothing to heal with, so it fights on until it has to run
+        assert_eq!(wounds(499, false, false), Wounds::Fine);
+        assert_eq!(wounds(200, false, false), Wounds::Fine);
+    }
+
+    #[test]
+    fn retreats_below_the_retreat_fraction() {
+        assert_eq!(wounds(199, true, false), Wounds::Retreat);
+        assert_eq!(wounds(199, false, false), Wounds::Retreat);
+        assert_eq!(wounds(0, false, false), Wounds::Retreat);
+    }
+
+    #[test]
+    fn a_healer_nearby_keeps_it_from_retreating() {
+        assert_eq!(wounds(199, true, true), Wounds::Heal);
+        assert_eq!(wounds(199, false, true), Wounds::Fine);
+    }
+
+    #[test]
+    fn thresholds_come_from_the_config() {
+        let config = RoomConfig {
+            combat_heal_fraction: 0.8,
+            combat_retreat_fraction: 0.4,
+            ..RoomConfig::default()
+        };
+        assert_eq!(
+            assess_wounds(799, 1_000, true, false, &config),
+            Wounds::Heal
+        );
+        assert_eq!(
+            assess_wounds(399, 1_000, true, false, &config),
+            Wounds::Retreat
+        );
+        assert_eq!(assess_wounds(0, 0, false, false, &config), Wounds::Fine);
+    }
+}
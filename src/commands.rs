@@ -0,0 +1,54 @@
+// functions meant to be called by hand from the screeps console, e.g.
+// `require('valbot').dump_targets()`. They can run outside of a tick, so none of them
+// touch the game state
+use log::*;
+use rand::{rngs::SmallRng, SeedableRng};
+use wasm_bindgen::prelude::*;
+
+use crate::{logging, CREEP_TARGETS, CREEP_TARGETS_RESTORED, RNG};
+
+#[wasm_bindgen]
+pub fn reset_targets() -> String {
+    CREEP_TARGETS.with(|creep_targets| match creep_targets.try_borrow_mut() {
+        Ok(mut creep_targets) => {
+            let count = creep_targets.len();
+            creep_targets.clear();
+            // don't let a pending restore bring the old targets back from Memory
+            CREEP_TARGETS_RESTORED.set(true);
+            info!("reset {count} creep targets from the console");
+            format!("cleared {count} creep targets")
+        }
+        Err(_) => "creep targets are in use, try again".to_string(),
+    })
+}
+
+#[wasm_bindgen]
+pub fn dump_targets() -> String {
+    CREEP_TARGETS.with(|creep_targets| match creep_targets.try_borrow() {
+        Ok(creep_targets) => serde_json::to_string(&*creep_targets)
+            .unwrap_or_else(|e| format!("couldn't serialize creep targets: {}", e)),
+        Err(_) => "creep targets are in use, try again".to_string(),
+    })
+}
+
+// swaps in a fixed seed, so the random choices that follow play out the same every time.
+// Only lasts until the next global reset
+#[wasm_bindgen]
+pub fn seed_rng(seed: u64) -> String {
+    // `set` skips the initializer, which would read the tick. Nothing holds the rng
+    // between ticks, so it can't be borrowed here
+    RNG.set(SmallRng::seed_from_u64(seed));
+    info!("rng seeded with {seed} from the console");
+    format!("rng seeded with {seed}")
+}
+
+#[wasm_bindgen]
+pub fn set_log_level(level: &str) -> String {
+    match level.parse::<LevelFilter>() {
+        Ok(level) => {
+            logging::set_level(level);
+            format!("log level set to {level}")
+        }
+        Err(_) => format!("unknown log level {level:?}, try off/error/warn/info/debug/trace"),
+    }
+}
@@ -0,0 +1,240 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use js_sys::{Object, Reflect};
+use log::*;
+use screeps::{constants::ResourceType, game, local::RoomName};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+use crate::body::Role;
+use crate::spawning::SpawnRules;
+
+// walls and ramparts get topped up to this many hits per controller level by default.
+// Their real hits pools run into the hundreds of millions, which would soak up every
+// spare bit of energy if we chased them
+pub const WALL_REPAIR_PER_RCL: u32 = 100_000;
+
+// storage energy past this is spare, and can go to other rooms through the terminal
+pub const TERMINAL_ENERGY_THRESHOLD: u32 = 50_000;
+
+// haulers start feeding the room out of storage once it holds this much energy, and go
+// back to filling it once it drops to the low mark
+pub const STORAGE_HIGH_WATER: u32 = 100_000;
+pub const STORAGE_LOW_WATER: u32 = 20_000;
+
+// credits the market never spends below, and the most it pays per unit of energy
+pub const MARKET_CREDIT_RESERVE: f64 = 10_000.0;
+pub const MARKET_MAX_ENERGY_PRICE: f64 = 1.0;
+
+// a heap this full is getting close to the limit, and a reset that throws away every cache
+pub const HEAP_WARN_FRACTION: f64 = 0.8;
+
+// enough for the smallest body that can still mine and carry
+pub const EMERGENCY_SPAWN_ENERGY: u32 = 200;
+
+// never more creeps than this alive at once, whatever the rooms want
+pub const MAX_CREEPS: usize = 100;
+
+// below these fractions of their hits, combat creeps stop attacking to heal themselves,
+// and then pull back altogether if nobody's around to heal them
+pub const COMBAT_HEAL_FRACTION: f64 = 0.5;
+pub const COMBAT_RETREAT_FRACTION: f64 = 0.2;
+
+// a tile in the room, for settings that point at one
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Tile {
+    pub x: u8,
+    pub y: u8,
+}
+
+// a resource the terminal sells whatever it holds past `keep` of, to buy orders paying
+// at least `min_price`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SellRule {
+    pub resource: ResourceType,
+    pub keep: u32,
+    pub min_price: f64,
+}
+
+// tunables for a room, read from Memory.config. Anything left out falls back to the
+// defaults, and Memory.config.rooms[room_name] overrides settings for just that room
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoomConfig {
+    pub targets: SpawnRules,
+    pub rampart_hits_per_rcl: u32,
+    pub wall_hits_per_rcl: u32,
+    // (creep count, energy budget) - while there are fewer creeps than a tier's count,
+    // bodies are capped at its budget, or the room's full capacity for no budget. The
+    // last tier's count is also the creep cap
+    pub spawn_tiers: Vec<(usize, Option<u32>)>,
+    // (controller level, energy budget) - the same, for while the room's controller is
+    // below a tier's level. Bodies go by whichever tier is tighter
+    pub level_tiers: Vec<(u8, Option<u32>)>,
+    // what an emergency harvester gets spawned with when the room has stalled
+    pub emergency_spawn_energy: u32,
+    // where idle creeps wait, instead of the spot picked near the controller
+    pub idle_position: Option<Tile>,
+    // where the terminal sends spare energy, if anywhere
+    pub terminal_target: Option<RoomName>,
+    pub terminal_energy_threshold: u32,
+    // storage energy water marks, see storage::StorageMode
+    pub storage_high_water: u32,
+    pub storage_low_water: u32,
+    // the compound the room's labs should be making, if any
+    pub lab_reaction: Option<ResourceType>,
+    // rooms the observer takes turns looking at, to keep their intel fresh
+    pub remote_rooms: Vec<RoomName>,
+    pub market_sell: Vec<SellRule>,
+    // energy gets bought in while storage holds less than this, if set
+    pub market_buy_energy_below: Option<u32>,
+    pub market_max_energy_price: f64,
+    pub market_credit_reserve: f64,
+    // full haulers hand their energy over to empty ones they pass, see hauling::hand_off
+    pub bucket_brigade: bool,
+    // see combat::look_after_self
+    pub combat_heal_fraction: f64,
+    pub combat_retreat_fraction: f64,
+    // the share of workers that build rather than upgrade while there are construction
+    // sites, see targeting::should_build. Unset, building always comes first
+    pub build_share: Option<f64>,
+}
+
+impl Default for RoomConfig {
+    fn default() -> Self {
+        RoomConfig {
+            targets: SpawnRules::default(),
+            rampart_hits_per_rcl: WALL_REPAIR_PER_RCL,
+            wall_hits_per_rcl: WALL_REPAIR_PER_RCL,
+            spawn_tiers: vec![(6, Some(300)), (20, None)],
+            level_tiers: vec![(3, Some(300))],
+            emergency_spawn_energy: EMERGENCY_SPAWN_ENERGY,
+            idle_position: None,
+            terminal_target: None,
+            terminal_energy_threshold: TERMINAL_ENERGY_THRESHOLD,
+            storage_high_water: STORAGE_HIGH_WATER,
+            storage_low_water: STORAGE_LOW_WATER,
+            lab_reaction: None,
+            remote_rooms: Vec::new(),
+            market_sell: Vec::new(),
+            market_buy_energy_below: None,
+            market_max_energy_price: MARKET_MAX_ENERGY_PRICE,
+            market_credit_reserve: MARKET_CREDIT_RESERVE,
+            bucket_brigade: false,
+            combat_heal_fraction: COMBAT_HEAL_FRACTION,
+            combat_retreat_fraction: COMBAT_RETREAT_FRACTION,
+            build_share: None,
+        }
+    }
+}
+
+impl RoomConfig {
+    pub fn creep_cap(&self) -> usize {
+        self.spawn_tiers.last().map_or(0, |(cap, _)| *cap)
+    }
+}
+
+// a setting that applies to the whole bot rather than any one room, read straight from
+// Memory.config[name]. Each of the settings below goes by its function's name
+fn global_setting(name: &str) -> Option<JsValue> {
+    Reflect::get(&screeps::memory::ROOT, &JsValue::from_str("config"))
+        .ok()
+        .filter(|config| config.is_object())
+        .and_then(|config| Reflect::get(&config, &JsValue::from_str(name)).ok())
+        .filter(|value| !value.is_undefined())
+}
+
+// whether a full bucket should be turned into a pixel. Private servers have no pixels, so
+// it's off by default
+pub fn generate_pixels() -> bool {
+    global_setting("generate_pixels")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+// how much of the heap limit can be in use before it gets warned about
+pub fn heap_warn_fraction() -> f64 {
+    global_setting("heap_warn_fraction")
+        .and_then(|value| value.as_f64())
+        .unwrap_or(HEAP_WARN_FRACTION)
+}
+
+// the most creeps there can be across every room
+pub fn max_creeps() -> usize {
+    global_setting("max_creeps")
+        .and_then(|value| value.as_f64())
+        .map_or(MAX_CREEPS, |max| max as usize)
+}
+
+// the most creeps of each role there can be across every room, e.g. `{ "Hauler": 8 }`.
+// Roles left out aren't capped beyond max_creeps
+pub fn role_caps() -> HashMap<Role, usize> {
+    global_setting("role_caps")
+        .and_then(|caps| {
+            serde_wasm_bindgen::from_value(caps)
+                .map_err(|e| warn!("Memory.config.role_caps should map roles to counts: {}", e))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+// the other shards to hear from through inter-shard memory. Asking for a shard that
+// doesn't exist is an error, and servers without shards have no inter-shard memory at
+// all, so it's left alone until this is filled in
+pub fn shards() -> Vec<String> {
+    global_setting("shards")
+        .and_then(|shards| {
+            serde_wasm_bindgen::from_value(shards)
+                .map_err(|e| warn!("Memory.config.shards should be a list of names: {}", e))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+thread_local! {
+    // Memory can be edited from the console at any time, so this is only kept for the
+    // tick it was read on
+    static CONFIG: RefCell<(u32, HashMap<RoomName, Rc<RoomConfig>>)> = RefCell::new((0, HashMap::new()));
+}
+
+pub fn room_config(room_name: RoomName) -> Rc<RoomConfig> {
+    CONFIG.with_borrow_mut(|(tick, configs)| {
+        let now = game::time();
+        if *tick != now {
+            *tick = now;
+            configs.clear();
+        }
+        configs
+            .entry(room_name)
+            .or_insert_with(|| Rc::new(load(room_name)))
+            .clone()
+    })
+}
+
+fn load(room_name: RoomName) -> RoomConfig {
+    let config = match Reflect::get(&screeps::memory::ROOT, &JsValue::from_str("config")) {
+        Ok(config) if config.is_object() => config,
+        _ => return RoomConfig::default(),
+    };
+
+    // lay the room's own settings over the shared ones
+    let merged = Object::assign(&Object::new(), &config.clone().into());
+    if let Some(overrides) = Reflect::get(&config, &JsValue::from_str("rooms"))
+        .ok()
+        .filter(|rooms| rooms.is_object())
+        .and_then(|rooms| Reflect::get(&rooms, &room_name.to_string().into()).ok())
+        .filter(|overrides| overrides.is_object())
+    {
+        Object::assign(&merged, &overrides.into());
+    }
+
+    serde_wasm_bindgen::from_value(merged.into()).unwrap_or_else(|e| {
+        warn!(
+            "couldn't read config for {}, using defaults: {}",
+            room_name, e
+        );
+        RoomConfig::default()
+    })
+}
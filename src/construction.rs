@@ -0,0 +1,191 @@
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use log::*;
+use screeps::{
+    constants::StructureType, game, local::ObjectId, look, objects::ConstructionSite, prelude::*,
+};
+
+// how often sites get checked over for ones that will never be finished
+pub const SITE_CLEANUP_INTERVAL: u32 = 500;
+// a site nobody has put any work into for this long isn't going to be built
+const STALE_SITE_TICKS: u32 = 10_000;
+
+thread_local! {
+    // the tick each site was first seen by a cleanup pass
+    static SITES_FIRST_SEEN: RefCell<HashMap<ObjectId<ConstructionSite>, u32>> = RefCell::new(HashMap::new());
+}
+
+// lower builds first. Anything that keeps the room alive or defended comes before the
+// economy, and roads and walls can always wait
+pub fn build_priority(structure_type: StructureType) -> u8 {
+    match structure_type {
+        StructureType::Spawn => 0,
+        StructureType::Tower => 1,
+        StructureType::Extension => 2,
+        StructureType::Container => 3,
+        StructureType::Storage => 4,
+        StructureType::Road => 6,
+        StructureType::Rampart => 7,
+        StructureType::Wall => 8,
+        _ => 5,
+    }
+}
+
+// the site to work on next: highest priority first, then whichever is furthest along so
+// half-built structures get finished before new ones are started
+pub fn best_site(sites: &[ConstructionSite]) -> Option<&ConstructionSite> {
+    sites.iter().filter(|site| site.my()).min_by_key(|site| {
+        (
+            build_priority(site.structure_type()),
+            Reverse(site.progress()),
+        )
+    })
+}
+
+// whether a site first seen at `first_seen` has sat untouched for too long
+pub fn is_stale(progress: u32, first_seen: u32, now: u32) -> bool {
+    progress == 0 && now.saturating_sub(first_seen) >= STALE_SITE_TICKS
+}
+
+// whether a `site_type` site can never be finished over the structures already on its
+// tile. Ramparts go over anything, and roads and containers can share a tile, but
+// otherwise one structure per tile
+pub fn tile_taken(site_type: StructureType, existing: &[StructureType]) -> bool {
+    let coexist = |a, b| {
+        matches!(
+            (a, b),
+            (StructureType::Rampart, _)
+                | (_, StructureType::Rampart)
+                | (StructureType::Road, StructureType::Container)
+                | (StructureType::Container, StructureType::Road)
+        )
+    };
+    existing
+        .iter()
+        .any(|&structure_type| structure_type == site_type || !coexist(site_type, structure_type))
+}
+
+// removes our sites that have gone untouched for too long, along with any whose tile has
+// since been built over, so builders aren't sent to them forever
+pub fn clean_up_sites() {
+    let now = game::time();
+    let sites: Vec<ConstructionSite> = game::construction_sites().values().collect();
+
+    SITES_FIRST_SEEN.with_borrow_mut(|first_seen| {
+        let ids: HashSet<_> = sites.iter().filter_map(|site| site.try_id()).collect();
+        first_seen.retain(|id, _| ids.contains(id));
+
+        for site in &sites {
+            let Some(id) = site.try_id() else {
+                continue;
+            };
+            let seen = *first_seen.entry(id).or_insert(now);
+            let pos = site.pos();
+            // no vision of the tile means nothing's known to be on it
+            let existing: Vec<StructureType> = pos
+                .look_for(look::STRUCTURES)
+                .map(|structures| {
+                    structures
+                        .iter()
+                        .map(|structure| structure.structure_type())
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let reason = if is_stale(site.progress(), seen, now) {
+                "has had no progress"
+            } else if tile_taken(site.structure_type(), &existing) {
+                "is on a tile that's been built over"
+            } else {
+                continue;
+            };
+
+            match site.remove() {
+                Ok(()) => {
+                    info!(
+                        "removed {:?} site at {} that {reason}",
+                        site.structure_type(),
+                        pos
+                    );
+                    first_seen.remove(&id);
+                }
+                Err(e) => warn!("couldn't remove site at {}: {:?}", pos, e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn priority_follows_the_build_order() {
+        let order = [
+            StructureType::Spawn,
+            StructureType::Tower,
+            StructureType::Extension,
+            StructureType::Container,
+            StructureType::Storage,
+            StructureType::Road,
+            StructureType::Rampart,
+            StructureType::Wall,
+        ];
+        for pair in order.windows(2) {
+            assert!(
+                build_priority(pair[0]) < build_priority(pair[1]),
+                "{:?} should come before {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn everything_else_goes_between_storage_and_roads() {
+        let link = build_priority(StructureType::Link);
+        assert!(build_priority(StructureType::Storage) < link);
+        assert!(link < build_priority(StructureType::Road));
+    }
+
+    #[test]
+    fn untouched_sites_go_stale_at_the_cutoff() {
+        let seen = 1_000;
+        assert!(!is_stale(0, seen, seen));
+        assert!(!is_stale(0, seen, seen + STALE_SITE_TICKS - 1));
+        assert!(is_stale(0, seen, seen + STALE_SITE_TICKS));
+        assert!(is_stale(0, seen, seen + STALE_SITE_TICKS * 2));
+        // seen after now, say after a reset, isn't stale
+        assert!(!is_stale(0, seen, 0));
+    }
+
+    #[test]
+    fn started_sites_never_go_stale() {
+        assert!(!is_stale(1, 0, STALE_SITE_TICKS * 10));
+    }
+
+    #[test]
+    fn tiles_taken_by_another_structure() {
+        use StructureType::*;
+        assert!(!tile_taken(Extension, &[]));
+        assert!(tile_taken(Extension, &[Extension]));
+        assert!(tile_taken(Extension, &[Road]));
+        assert!(tile_taken(Tower, &[Wall]));
+        assert!(tile_taken(Road, &[Spawn]));
+    }
+
+    #[test]
+    fn tiles_shared_by_structures_that_coexist() {
+        use StructureType::*;
+        assert!(!tile_taken(Rampart, &[Spawn]));
+        assert!(!tile_taken(Spawn, &[Rampart]));
+        assert!(!tile_taken(Road, &[Container]));
+        assert!(!tile_taken(Container, &[Road, Rampart]));
+        // but never two of the same
+        assert!(tile_taken(Rampart, &[Rampart]));
+        assert!(tile_taken(Road, &[Road]));
+        assert!(tile_taken(Container, &[Road, Spawn]));
+    }
+}
@@ -0,0 +1,398 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use log::*;
+use screeps::{
+    constants::Part,
+    enums::StructureObject,
+    find, game,
+    local::{RoomName, RoomXY},
+    objects::Room,
+    prelude::*,
+};
+
+use crate::find_cache;
+
+// hostiles this close to a spawn are in the base, rampart or not
+const SPAWN_BREACH_RANGE: u8 = 4;
+// a threat has to still be there after this many ticks in a row before we spend a safe
+// mode on it, so something that just wanders through doesn't trip it
+const BREACH_CONFIRM_TICKS: u32 = 2;
+// a hostile with this many fighting parts, or any boosted ones, is part of an assault
+// rather than someone picking off our harvesters
+const ASSAULT_PARTS: usize = 10;
+// the most defenders any one threat gets answered with
+const MAX_DEFENDERS: usize = 6;
+
+thread_local! {
+    // consecutive ticks each room has had a threat inside its base
+    static BREACH_TICKS: RefCell<HashMap<RoomName, u32>> = RefCell::new(HashMap::new());
+    // the last threat level logged for each room, so it's only logged when it changes
+    static THREAT_LEVELS: RefCell<HashMap<RoomName, ThreatLevel>> = RefCell::new(HashMap::new());
+    // the tick the assessments below were made on, and each room's. Every creep picking a
+    // target asks, so the hostiles are only looked over once a tick
+    static ASSESSMENTS: RefCell<(u32, HashMap<RoomName, ThreatAssessment>)> =
+        RefCell::new((0, HashMap::new()));
+}
+
+// how worried a room should be about the hostiles in it, least first
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ThreatLevel {
+    // nothing that can do damage, e.g. scouts
+    #[default]
+    Harmless,
+    // a few fighting parts, after our harvesters and haulers more than the base
+    Harassment,
+    Assault,
+}
+
+// what the hostiles in a room add up to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ThreatAssessment {
+    pub level: ThreatLevel,
+    // hostiles that can do damage at all
+    pub hostiles: usize,
+    pub boosted: bool,
+}
+
+impl ThreatAssessment {
+    // (ranged, melee) defenders to answer the threat with. Harassment only needs chasing
+    // off, which ranged attackers do best, an assault gets two defenders a hostile (three
+    // if they're boosted) split between the two
+    pub fn defenders(&self) -> (usize, usize) {
+        match self.level {
+            ThreatLevel::Harmless => (0, 0),
+            ThreatLevel::Harassment => (self.hostiles.min(MAX_DEFENDERS), 0),
+            ThreatLevel::Assault => {
+                let per_hostile = if self.boosted { 3 } else { 2 };
+                let defenders = (self.hostiles * per_hostile).min(MAX_DEFENDERS);
+                (defenders - defenders / 2, defenders / 2)
+            }
+        }
+    }
+}
+
+// a single hostile, going by its body as (part, boosted) for every part that's still
+// alive
+pub fn classify(body: &[(Part, bool)]) -> ThreatLevel {
+    let parts: Vec<Part> = body.iter().map(|&(part, _)| part).collect();
+    if !is_threat(&parts) {
+        return ThreatLevel::Harmless;
+    }
+
+    let fighting = parts
+        .iter()
+        .filter(|part| {
+            matches!(
+                part,
+                Part::Attack | Part::RangedAttack | Part::Work | Part::Heal
+            )
+        })
+        .count();
+    if fighting >= ASSAULT_PARTS || body.iter().any(|&(_, boosted)| boosted) {
+        ThreatLevel::Assault
+    } else {
+        ThreatLevel::Harassment
+    }
+}
+
+// the hostiles as a whole are as bad as the worst of them
+pub fn assess(bodies: &[Vec<(Part, bool)>]) -> ThreatAssessment {
+    bodies
+        .iter()
+        .map(|body| (classify(body), body))
+        .filter(|(level, _)| *level > ThreatLevel::Harmless)
+        .fold(ThreatAssessment::default(), |assessment, (level, body)| {
+            ThreatAssessment {
+                level: assessment.level.max(level),
+                hostiles: assessment.hostiles + 1,
+                boosted: assessment.boosted || body.iter().any(|&(_, boosted)| boosted),
+            }
+        })
+}
+
+// assesses the hostiles currently in the room, logging whenever the level changes
+pub fn assess_room(room: &Room) -> ThreatAssessment {
+    let now = game::time();
+    let cached = ASSESSMENTS.with_borrow_mut(|(tick, assessments)| {
+        if *tick != now {
+            *tick = now;
+            assessments.clear();
+        }
+        assessments.get(&room.name()).copied()
+    });
+    if let Some(assessment) = cached {
+        return assessment;
+    }
+
+    let bodies: Vec<Vec<(Part, bool)>> = room
+        .find(find::HOSTILE_CREEPS, None)
+        .iter()
+        .map(|creep| {
+            creep
+                .body()
+                .iter()
+                .filter(|part| part.hits() > 0)
+                .map(|part| (part.part(), part.boost().is_some()))
+                .collect()
+        })
+        .collect();
+    let assessment = assess(&bodies);
+
+    let changed = THREAT_LEVELS.with_borrow_mut(|levels| {
+        levels.insert(room.name(), assessment.level) != Some(assessment.level)
+    });
+    if changed {
+        let (ranged, melee) = assessment.defenders();
+        warn!(
+            "{} threat now {:?}: {} hostiles{}, wants {ranged} ranged and {melee} melee defenders",
+            room.name(),
+            assessment.level,
+            assessment.hostiles,
+            if assessment.boosted { " (boosted)" } else { "" }
+        );
+    }
+    ASSESSMENTS.with_borrow_mut(|(_, assessments)| assessments.insert(room.name(), assessment));
+    assessment
+}
+
+// whether anything in the room is out to do damage
+pub fn under_attack(room: &Room) -> bool {
+    assess_room(room).level > ThreatLevel::Harmless
+}
+
+pub fn run_defense(room: &Room) {
+    let breached = base_breached(room);
+    let ticks = BREACH_TICKS.with_borrow_mut(|breaches| {
+        let ticks = breaches.entry(room.name()).or_default();
+        *ticks = if breached { *ticks + 1 } else { 0 };
+        *ticks
+    });
+
+    if ticks < BREACH_CONFIRM_TICKS {
+        return;
+    }
+
+    let Some(controller) = room.controller() else {
+        return;
+    };
+    if controller.safe_mode().is_some()
+        || controller.safe_mode_cooldown().is_some()
+        || controller.safe_mode_available() == 0
+    {
+        return;
+    }
+
+    match controller.activate_safe_mode() {
+        Ok(()) => error!(
+            "BASE BREACHED in {}, activated safe mode ({} left)",
+            room.name(),
+            controller.safe_mode_available() - 1
+        ),
+        Err(e) => error!(
+            "BASE BREACHED in {}, couldn't activate safe mode: {:?}",
+            room.name(),
+            e
+        ),
+    }
+}
+
+fn base_breached(room: &Room) -> bool {
+    let hostiles: Vec<(Vec<Part>, RoomXY)> = room
+        .find(find::HOSTILE_CREEPS, None)
+        .iter()
+        .map(|creep| {
+            let parts = creep
+                .body()
+                .iter()
+                .filter(|part| part.hits() > 0)
+                .map(|part| part.part())
+                .collect();
+            (parts, creep.pos().xy())
+        })
+        .collect();
+    if hostiles.is_empty() {
+        return false;
+    }
+
+    let mut spawns = Vec::new();
+    let mut ramparts = Vec::new();
+    for structure in find_cache::structures(room).iter() {
+        match structure {
+            StructureObject::StructureSpawn(spawn) if spawn.my() => spawns.push(spawn.pos().xy()),
+            StructureObject::StructureRampart(rampart) if rampart.my() => {
+                ramparts.push(rampart.pos().xy())
+            }
+            _ => {}
+        }
+    }
+
+    threat_inside_base(&hostiles, &spawns, &ramparts)
+}
+
+// only creeps that can actually hurt structures count as a threat, a scout or a lone
+// healer can wander around all it likes
+pub fn is_threat(parts: &[Part]) -> bool {
+    parts
+        .iter()
+        .any(|part| matches!(part, Part::Attack | Part::RangedAttack | Part::Work))
+}
+
+// whether any threatening hostile is near a spawn, or inside the box our ramparts span.
+// Hostiles can't stand on our ramparts, so being strictly inside that box means they've
+// gotten past them
+pub fn threat_inside_base(
+    hostiles: &[(Vec<Part>, RoomXY)],
+    spawns: &[RoomXY],
+    ramparts: &[RoomXY],
+) -> bool {
+    let range = |a: RoomXY, b: RoomXY| {
+        let dx = a.x.u8().abs_diff(b.x.u8());
+        let dy = a.y.u8().abs_diff(b.y.u8());
+        dx.max(dy)
+    };
+
+    let rampart_box = ramparts
+        .iter()
+        .fold(None, |bounds: Option<(u8, u8, u8, u8)>, xy| {
+            let (x, y) = (xy.x.u8(), xy.y.u8());
+            Some(match bounds {
+                None => (x, y, x, y),
+                Some((min_x, min_y, max_x, max_y)) => {
+                    (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+                }
+            })
+        });
+    let inside_ramparts = |xy: RoomXY| {
+        rampart_box.is_some_and(|(min_x, min_y, max_x, max_y)| {
+            let (x, y) = (xy.x.u8(), xy.y.u8());
+            min_x < x && x < max_x && min_y < y && y < max_y
+        })
+    };
+
+    hostiles.iter().any(|(parts, xy)| {
+        is_threat(parts)
+            && (spawns
+                .iter()
+                .any(|&spawn| range(spawn, *xy) <= SPAWN_BREACH_RANGE)
+                || inside_ramparts(*xy))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xy(x: u8, y: u8) -> RoomXY {
+        RoomXY::try_from((x, y)).unwrap()
+    }
+
+    const RAMPARTS: [(u8, u8); 2] = [(10, 10), (30, 30)];
+
+    fn breached(parts: &[Part], at: (u8, u8)) -> bool {
+        let ramparts: Vec<RoomXY> = RAMPARTS.iter().map(|&(x, y)| xy(x, y)).collect();
+        threat_inside_base(
+            &[(parts.to_vec(), xy(at.0, at.1))],
+            &[xy(20, 20)],
+            &ramparts,
+        )
+    }
+
+    #[test]
+    fn attacker_inside_ramparts_is_a_breach() {
+        assert!(breached(&[Part::Attack, Part::Move], (12, 28)));
+    }
+
+    #[test]
+    fn attacker_outside_ramparts_is_not() {
+        assert!(!breached(&[Part::Attack, Part::Move], (5, 20)));
+        assert!(!breached(&[Part::Attack, Part::Move], (10, 20)));
+    }
+
+    #[test]
+    fn attacker_near_a_spawn_is_a_breach_without_ramparts() {
+        let hostile = vec![(vec![Part::RangedAttack], xy(23, 24))];
+        assert!(threat_inside_base(&hostile, &[xy(20, 20)], &[]));
+        let hostile = vec![(vec![Part::RangedAttack], xy(25, 20))];
+        assert!(!threat_inside_base(&hostile, &[xy(20, 20)], &[]));
+    }
+
+    #[test]
+    fn harmless_creeps_never_breach() {
+        assert!(!breached(&[Part::Move, Part::Carry], (20, 21)));
+        assert!(!breached(&[Part::Heal, Part::Move], (15, 15)));
+    }
+
+    fn body(parts: &[(Part, usize)], boosted: bool) -> Vec<(Part, bool)> {
+        parts
+            .iter()
+            .flat_map(|&(part, count)| std::iter::repeat((part, boosted)).take(count))
+            .collect()
+    }
+
+    #[test]
+    fn lone_scout_gets_no_defenders() {
+        let scout = body(&[(Part::Move, 1)], false);
+        assert_eq!(classify(&scout), ThreatLevel::Harmless);
+        let assessment = assess(&[scout]);
+        assert_eq!(assessment.hostiles, 0);
+        assert_eq!(assessment.defenders(), (0, 0));
+    }
+
+    #[test]
+    fn attack_parts_get_defenders() {
+        let raider = body(&[(Part::Attack, 2), (Part::Move, 2)], false);
+        assert_eq!(classify(&raider), ThreatLevel::Harassment);
+        assert_eq!(assess(&[raider.clone()]).defenders(), (1, 0));
+
+        let scout = body(&[(Part::Move, 1)], false);
+        let assessment = assess(&[raider.clone(), raider, scout]);
+        assert_eq!(assessment.hostiles, 2);
+        assert_eq!(assessment.defenders(), (2, 0));
+    }
+
+    #[test]
+    fn big_or_boosted_hostiles_are_an_assault() {
+        let big = body(&[(Part::Attack, ASSAULT_PARTS), (Part::Move, 5)], false);
+        assert_eq!(classify(&big), ThreatLevel::Assault);
+        assert_eq!(assess(&[big.clone()]).defenders(), (1, 1));
+
+        let boosted = body(&[(Part::RangedAttack, 1), (Part::Move, 1)], true);
+        assert_eq!(classify(&boosted), ThreatLevel::Assault);
+        let assessment = assess(&[big.clone(), boosted]);
+        assert!(assessment.boosted);
+        assert_eq!(assessment.defenders(), (3, 3));
+
+        let horde = vec![big; 5];
+        let (ranged, melee) = assess(&horde).defenders();
+        assert_eq!(ranged + melee, MAX_DEFENDERS);
+    }
+
+    #[test]
+    fn healers_only_count_alongside_something_that_hurts() {
+        // can't do any damage by itself, however much it heals
+        let healer = body(&[(Part::Heal, ASSAULT_PARTS), (Part::Move, 5)], false);
+        assert_eq!(classify(&healer), ThreatLevel::Harmless);
+        assert_eq!(assess(&[healer]).defenders(), (0, 0));
+
+        // but heal parts count towards an assault's size like any other fighting part
+        let medic = body(
+            &[
+                (Part::Attack, 1),
+                (Part::Heal, ASSAULT_PARTS - 1),
+                (Part::Move, 5),
+            ],
+            false,
+        );
+        assert_eq!(classify(&medic), ThreatLevel::Assault);
+        let light = body(
+            &[
+                (Part::Attack, 1),
+                (Part::Heal, ASSAULT_PARTS - 2),
+                (Part::Move, 5),
+            ],
+            false,
+        );
+        assert_eq!(classify(&light), ThreatLevel::Harassment);
+    }
+}
@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+use log::*;
+use screeps::local::RoomName;
+
+use crate::owned_rooms;
+
+// how many ticks of fill level go into each decision. The history starts over after every
+// change, so this is also the least time between two changes
+const ENERGY_HISTORY_LEN: usize = 50;
+// rooms averaging below this are starving, above the other they've got energy to spare.
+// The gap between the two keeps a room sitting near either one from flip-flopping
+const STARVING_FILL: f64 = 0.3;
+const SATURATED_FILL: f64 = 0.9;
+// how far the harvester and hauler counts can be pushed either way
+const MAX_BOOST: i32 = 2;
+const MAX_CUT: i32 = 1;
+
+#[derive(Default)]
+struct RoomEnergy {
+    // energy_available / energy_capacity_available, oldest first
+    history: VecDeque<f64>,
+    adjustment: i32,
+}
+
+thread_local! {
+    static ROOM_ENERGY: RefCell<HashMap<RoomName, RoomEnergy>> = RefCell::new(HashMap::new());
+}
+
+// the new adjustment, given a full window of fill levels and the current one. It only
+// ever moves one step at a time: up while the room is starving and not recovering on its
+// own, down while it's saturated
+pub fn adjust(history: &VecDeque<f64>, current: i32) -> i32 {
+    let (Some(first), Some(last)) = (history.front(), history.back()) else {
+        return current;
+    };
+
+    let average = history.iter().sum::<f64>() / history.len() as f64;
+    let trend = last - first;
+
+    if average < STARVING_FILL && trend <= 0.0 {
+        (current + 1).min(MAX_BOOST)
+    } else if average > SATURATED_FILL && trend >= 0.0 {
+        (current - 1).max(-MAX_CUT)
+    } else {
+        current
+    }
+}
+
+// a desired count with the room's adjustment applied. Roles the room doesn't want at all
+// stay at zero, and ones it does want never get cut to nothing
+pub fn adjusted(base: usize, adjustment: i32) -> usize {
+    if base == 0 {
+        return 0;
+    }
+    (base as i32 + adjustment).max(1) as usize
+}
+
+pub fn record_energy() {
+    for room in owned_rooms() {
+        let capacity = room.energy_capacity_available();
+        if capacity == 0 {
+            continue;
+        }
+        let fill = room.energy_available() as f64 / capacity as f64;
+
+        ROOM_ENERGY.with_borrow_mut(|rooms| {
+            let energy = rooms.entry(room.name()).or_default();
+            energy.history.push_back(fill);
+            if energy.history.len() < ENERGY_HISTORY_LEN {
+                return;
+            }
+
+            let adjustment = adjust(&energy.history, energy.adjustment);
+            if adjustment != energy.adjustment {
+                info!(
+                    "{} energy averaging {:.0}%, harvester/hauler adjustment now {adjustment:+}",
+                    room.name(),
+                    100.0 * energy.history.iter().sum::<f64>() / energy.history.len() as f64
+                );
+                energy.adjustment = adjustment;
+                energy.history.clear();
+            } else {
+                energy.history.pop_front();
+            }
+        });
+    }
+}
+
+// how many harvesters and haulers above (or below) the configured counts the room wants
+pub fn adjustment(room_name: RoomName) -> i32 {
+    ROOM_ENERGY.with_borrow(|rooms| rooms.get(&room_name).map_or(0, |energy| energy.adjustment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(fills: &[f64]) -> VecDeque<f64> {
+        fills.iter().copied().collect()
+    }
+
+    #[test]
+    fn starving_and_not_recovering_steps_up() {
+        assert_eq!(adjust(&history(&[0.2, 0.1, 0.1]), 0), 1);
+        assert_eq!(adjust(&history(&[0.2, 0.2, 0.2]), 1), 2);
+    }
+
+    #[test]
+    fn starving_but_recovering_holds() {
+        assert_eq!(adjust(&history(&[0.0, 0.2, 0.4]), 0), 0);
+    }
+
+    #[test]
+    fn saturated_steps_down() {
+        assert_eq!(adjust(&history(&[0.95, 0.95, 1.0]), 0), -1);
+        assert_eq!(adjust(&history(&[0.95, 1.0, 0.92]), 0), 0);
+    }
+
+    #[test]
+    fn never_goes_past_the_limits() {
+        assert_eq!(adjust(&history(&[0.0, 0.0]), MAX_BOOST), MAX_BOOST);
+        assert_eq!(adjust(&history(&[1.0, 1.0]), -MAX_CUT), -MAX_CUT);
+    }
+
+    #[test]
+    fn empty_history_changes_nothing() {
+        assert_eq!(adjust(&VecDeque::new(), 1), 1);
+    }
+
+    #[test]
+    fn adjusted_counts_keep_roles_wanted() {
+        assert_eq!(adjusted(0, 2), 0);
+        assert_eq!(adjusted(2, -5), 1);
+        assert_eq!(adjusted(2, 1), 3);
+    }
+}
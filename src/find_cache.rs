@@ -0,0 +1,190 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use log::*;
+use screeps::{
+    enums::StructureObject,
+    find, game,
+    local::{ObjectId, RoomName},
+    objects::{ConstructionSite, Room, Source, Structure},
+    prelude::*,
+};
+
+// find results for one room, each filled in the first time something asks for it
+#[derive(Default)]
+struct RoomFinds {
+    structures: Option<Rc<Vec<StructureObject>>>,
+    construction_sites: Option<Rc<Vec<ConstructionSite>>>,
+    sources_active: Option<Rc<Vec<Source>>>,
+}
+
+// in rooms we don't own, other players and invaders can build without our signature
+// noticing, so structure ids found there are only trusted for this long
+const FOREIGN_IDS_TTL: u32 = 10;
+
+// what a room's structures are expected to stay the same for: how many structures of ours
+// it has, the ids of its construction sites and its controller level. Anything being
+// built, finished or unlocked changes one of them, even a road finishing on the tick
+// another site is placed, since unowned structures don't count towards the first. Those
+// going away is caught by their ids no longer resolving
+type StructureSignature = (usize, Vec<ObjectId<ConstructionSite>>, u8);
+
+// the ids from a room's last structure find, kept across ticks
+struct StructureIds {
+    signature: StructureSignature,
+    ids: Vec<ObjectId<Structure>>,
+    // the tick they were found on
+    found: u32,
+    // what the find that filled this in cost, to compare re-resolving against
+    find_cpu: f64,
+}
+
+thread_local! {
+    // game objects go stale at the end of the tick, so everything here is thrown out as
+    // soon as the tick it was found on is over
+    static FIND_CACHE: RefCell<(u32, HashMap<RoomName, RoomFinds>)> = RefCell::new((0, HashMap::new()));
+
+    // ids stay good for as long as the structures do, so these live until something in
+    // the room changes
+    static STRUCTURE_IDS: RefCell<HashMap<RoomName, StructureIds>> = RefCell::new(HashMap::new());
+
+    // this tick's signature for each room, as of the check at the top of the tick
+    static SIGNATURES: RefCell<HashMap<RoomName, StructureSignature>> = RefCell::new(HashMap::new());
+
+    // cpu saved by resolving cached ids instead of finding, since the last report
+    static STRUCTURE_CPU_SAVED: Cell<f64> = Cell::new(0.0);
+}
+
+fn structure_signatures() -> HashMap<RoomName, StructureSignature> {
+    let mut signatures: HashMap<RoomName, StructureSignature> = HashMap::new();
+    for structure in game::structures().values() {
+        let signature = signatures.entry(structure.pos().room_name()).or_default();
+        signature.0 += 1;
+        if let StructureObject::StructureController(controller) = structure {
+            signature.2 = controller.level();
+        }
+    }
+    for site in game::construction_sites().values() {
+        if let Some(id) = site.try_id() {
+            signatures
+                .entry(site.pos().room_name())
+                .or_default()
+                .1
+                .push(id);
+        }
+    }
+    for signature in signatures.values_mut() {
+        signature.1.sort_unstable();
+    }
+    signatures
+}
+
+// drops the cached structure ids of any room whose structures might have changed since
+// they were found. Meant to run once at the top of the tick
+pub fn check_structure_ids() {
+    let start = game::cpu::get_used();
+
+    let now = game::time();
+    let signatures = structure_signatures();
+    STRUCTURE_IDS.with_borrow_mut(|rooms| {
+        rooms.retain(|room_name, cached| {
+            let signature = signatures.get(room_name).cloned().unwrap_or_default();
+            // only a room with a controller of ours can't change behind our back
+            let owned = signature.2 > 0;
+            signature == cached.signature && (owned || now < cached.found + FOREIGN_IDS_TTL)
+        })
+    });
+    SIGNATURES.set(signatures);
+
+    // the check isn't free either, so it counts against what the cache saves
+    STRUCTURE_CPU_SAVED.set(STRUCTURE_CPU_SAVED.get() - (game::cpu::get_used() - start));
+}
+
+pub fn log_structure_savings() {
+    info!(
+        "structure id cache saved {:.2} cpu",
+        STRUCTURE_CPU_SAVED.replace(0.0)
+    );
+}
+
+// a room's structures, re-resolved from the cached ids when there are some. If any of them
+// is gone the ids are no good and the room gets a fresh find instead
+fn find_structures(room: &Room) -> Vec<StructureObject> {
+    let start = game::cpu::get_used();
+
+    let cached = STRUCTURE_IDS.with_borrow(|rooms| {
+        let cached = rooms.get(&room.name())?;
+        let structures: Option<Vec<StructureObject>> = cached
+            .ids
+            .iter()
+            .map(|id| id.resolve().map(StructureObject::from))
+            .collect();
+        structures.map(|structures| (structures, cached.find_cpu))
+    });
+    if let Some((structures, find_cpu)) = cached {
+        let resolve_cpu = game::cpu::get_used() - start;
+        STRUCTURE_CPU_SAVED.set(STRUCTURE_CPU_SAVED.get() + find_cpu - resolve_cpu);
+        return structures;
+    }
+
+    let start = game::cpu::get_used();
+    let structures = room.find(find::STRUCTURES, None);
+    let find_cpu = game::cpu::get_used() - start;
+
+    let signature = SIGNATURES
+        .with_borrow(|signatures| signatures.get(&room.name()).cloned().unwrap_or_default());
+    STRUCTURE_IDS.with_borrow_mut(|rooms| {
+        rooms.insert(
+            room.name(),
+            StructureIds {
+                signature,
+                ids: structures
+                    .iter()
+                    .map(|structure| structure.as_structure().id())
+                    .collect(),
+                found: game::time(),
+                find_cpu,
+            },
+        )
+    });
+    structures
+}
+
+fn with_room_finds<R>(room: &Room, f: impl FnOnce(&mut RoomFinds) -> R) -> R {
+    FIND_CACHE.with_borrow_mut(|(tick, rooms)| {
+        let now = game::time();
+        if *tick != now {
+            *tick = now;
+            rooms.clear();
+        }
+        f(rooms.entry(room.name()).or_default())
+    })
+}
+
+pub fn structures(room: &Room) -> Rc<Vec<StructureObject>> {
+    with_room_finds(room, |finds| {
+        finds
+            .structures
+            .get_or_insert_with(|| Rc::new(find_structures(room)))
+            .clone()
+    })
+}
+
+pub fn construction_sites(room: &Room) -> Rc<Vec<ConstructionSite>> {
+    with_room_finds(room, |finds| {
+        finds
+            .construction_sites
+            .get_or_insert_with(|| Rc::new(room.find(find::CONSTRUCTION_SITES, None)))
+            .clone()
+    })
+}
+
+pub fn sources_active(room: &Room) -> Rc<Vec<Source>> {
+    with_room_finds(room, |finds| {
+        finds
+            .sources_active
+            .get_or_insert_with(|| Rc::new(room.find(find::SOURCES_ACTIVE, None)))
+            .clone()
+    })
+}
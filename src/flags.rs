@@ -0,0 +1,267 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use log::*;
+use screeps::{
+    constants::{Color, Part, StructureType},
+    enums::StructureObject,
+    find, game, look,
+    objects::{Creep, Flag, Structure},
+    prelude::*,
+};
+use wasm_bindgen::JsValue;
+
+use crate::{movement::DefaultMove, recycling, CreepTarget};
+
+thread_local! {
+    // the claimer each purple flag has, by flag name, so two flags get two claimers
+    static CLAIMERS: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+}
+
+// flags placed by hand are read as commands, going by their primary color:
+//   red: send the nearest idle creep after whichever hostile is closest to it
+//   white: place a construction site under the flag, taking the structure type from the
+//          start of the flag's name (`extension-2`, `tower`...), or a road if it isn't one
+//   purple: send a claimer to the flag's room, to claim its controller if the flag's name
+//           starts with `claim` or to keep it reserved otherwise
+//   orange: have ranged attackers, idle defenders and dismantlers take down the hostile
+//           structure under the flag, which they otherwise leave alone. A flag whose name
+//           starts with `room` has them clear every hostile structure in its room
+//           instead, invader cores first
+//   brown: recycle the creep standing on the flag
+//   yellow: have a dismantler take apart the structure under the flag, ours or not
+pub fn process_flags(creep_targets: &mut HashMap<String, CreepTarget>) {
+    for flag in game::flags().values() {
+        match flag.color() {
+            Color::Red => attack_flag(&flag, creep_targets),
+            Color::White => build_flag(&flag),
+            Color::Brown => recycle_flag(&flag, creep_targets),
+            Color::Purple if owns_flag_room(&flag) => {
+                info!("flag {}: room claimed, removing", flag.name());
+                flag.remove();
+            }
+            Color::Orange if flag.room().is_some() && flagged_structure(&flag).is_none() => {
+                info!("flag {}: structure cleared, removing", flag.name());
+                flag.remove();
+            }
+            Color::Yellow if flag.room().is_some() && dismantle_structure(&flag).is_none() => {
+                info!("flag {}: structure dismantled, removing", flag.name());
+                flag.remove();
+            }
+            _ => {}
+        }
+    }
+}
+
+// purple flags still waiting on a claimer
+pub fn claim_flags() -> impl Iterator<Item = Flag> {
+    game::flags()
+        .values()
+        .filter(|flag| flag.color() == Color::Purple && !owns_flag_room(flag))
+}
+
+// orange flags with something still to clear
+pub fn clear_flags() -> impl Iterator<Item = Flag> {
+    game::flags().values().filter(|flag| {
+        flag.color() == Color::Orange
+            && (flag.room().is_none() || flagged_structure(flag).is_some())
+    })
+}
+
+fn flagged_structure(flag: &Flag) -> Option<Structure> {
+    if String::from(flag.name()).starts_with("room") {
+        return flag
+            .room()?
+            .find(find::HOSTILE_STRUCTURES, None)
+            .into_iter()
+            .filter(|structure| !matches!(structure, StructureObject::StructureController(_)))
+            .min_by_key(|structure| {
+                (
+                    !matches!(structure, StructureObject::StructureInvaderCore(_)),
+                    flag.pos().get_range_to(structure.pos()),
+                )
+            })
+            .map(|structure| structure.as_structure().clone());
+    }
+
+    flag.pos()
+        .find_in_range(find::HOSTILE_STRUCTURES, 0)
+        .into_iter()
+        .next()
+        .map(|structure| structure.as_structure().clone())
+}
+
+// the hostile structure a ranged attacker, defender or dismantler should clear. Like
+// claimers, they head for the flag until they can see what's under it
+pub fn clear_flag_target(creep: &Creep) -> Option<CreepTarget> {
+    let flag = clear_flags().next()?;
+    if flag.room().is_none() {
+        let _ = creep.default_move_to(&flag);
+        return None;
+    }
+
+    flagged_structure(&flag).map(|structure| CreepTarget::AttackStructure(structure.id()))
+}
+
+// yellow flags with something still to take apart
+pub fn dismantle_flags() -> impl Iterator<Item = Flag> {
+    game::flags().values().filter(|flag| {
+        flag.color() == Color::Yellow
+            && (flag.room().is_none() || dismantle_structure(flag).is_some())
+    })
+}
+
+// whatever's under the flag that can be dismantled
+fn dismantle_structure(flag: &Flag) -> Option<Structure> {
+    flag.pos()
+        .look_for(look::STRUCTURES)
+        .ok()?
+        .into_iter()
+        .find(|structure| structure.as_dismantleable().is_some())
+        .map(|structure| structure.as_structure().clone())
+}
+
+// the structure a dismantler should take apart next, the closest flagged one. Flags in
+// rooms we can't see get walked to until there's something to target
+pub fn dismantle_flag_target(creep: &Creep) -> Option<CreepTarget> {
+    let flag = dismantle_flags().min_by_key(|flag| {
+        (
+            flag.pos().room_name() != creep.pos().room_name(),
+            creep.pos().get_range_to(flag.pos()),
+        )
+    })?;
+    let Some(structure) = dismantle_structure(&flag) else {
+        let _ = creep.default_move_to(&flag);
+        return None;
+    };
+    Some(CreepTarget::Dismantle(structure.id()))
+}
+
+fn owns_flag_room(flag: &Flag) -> bool {
+    flag.room()
+        .and_then(|room| room.controller())
+        .is_some_and(|controller| controller.my())
+}
+
+// the purple flag `creep` has taken on, or failing that the first one nobody else has.
+// Flags and claimers that are gone free their slots up
+fn claimer_flag(creep: &Creep) -> Option<Flag> {
+    let name = creep.name();
+    let flags: Vec<(String, Flag)> = claim_flags()
+        .map(|flag| (String::from(flag.name()), flag))
+        .collect();
+    CLAIMERS.with_borrow_mut(|claimers| {
+        claimers.retain(|flag, claimer| {
+            flags.iter().any(|(name, _)| name == flag)
+                && game::creeps().get(claimer.clone()).is_some()
+        });
+        let (flag_name, flag) = flags
+            .iter()
+            .find(|(flag, _)| claimers.get(flag) == Some(&name))
+            .or_else(|| flags.iter().find(|(flag, _)| !claimers.contains_key(flag)))?;
+        claimers.insert(flag_name.clone(), name);
+        Some(flag.clone())
+    })
+}
+
+// the controller a claimer should work on. Rooms we can't see yet don't give us a
+// controller to target, so until the claimer gets there it just heads for the flag
+pub fn claim_flag_target(creep: &Creep) -> Option<CreepTarget> {
+    let flag = claimer_flag(creep)?;
+    let Some(controller) = flag.room().and_then(|room| room.controller()) else {
+        let _ = creep.default_move_to(&flag);
+        return None;
+    };
+
+    if String::from(flag.name()).starts_with("claim") {
+        Some(CreepTarget::Claim(controller.id()))
+    } else {
+        Some(CreepTarget::Reserve(controller.id()))
+    }
+}
+
+fn recycle_flag(flag: &Flag, creep_targets: &mut HashMap<String, CreepTarget>) {
+    let name = String::from(flag.name());
+    // the flag's room might not be visible, but then there's no creep under it either
+    let Some(creep) = flag
+        .pos()
+        .look_for(look::CREEPS)
+        .ok()
+        .and_then(|creeps| creeps.into_iter().find(|creep| creep.my()))
+    else {
+        return;
+    };
+
+    if let Some(target) = recycling::recycle_target(&creep) {
+        info!("flag {name}: recycling {}, removing", creep.name());
+        creep_targets.insert(creep.name(), target);
+        flag.remove();
+    }
+}
+
+fn attack_flag(flag: &Flag, creep_targets: &mut HashMap<String, CreepTarget>) {
+    let name = String::from(flag.name());
+    let Some(room) = flag.room() else {
+        return;
+    };
+
+    if room.find(find::HOSTILE_CREEPS, None).is_empty() {
+        info!("flag {name}: no hostiles left in {}, removing", room.name());
+        flag.remove();
+        return;
+    }
+
+    // one creep on the job at a time, in this room at least
+    if creep_targets.values().any(|target| match target {
+        CreepTarget::Attack(id) => id
+            .resolve()
+            .is_some_and(|hostile| hostile.pos().room_name() == room.name()),
+        _ => false,
+    }) {
+        return;
+    }
+
+    // only creeps that can actually hurt something are worth sending
+    let Some(creep) = room
+        .find(find::MY_CREEPS, None)
+        .into_iter()
+        .filter(|creep| !creep.spawning() && !creep_targets.contains_key(&creep.name()))
+        .filter(|creep| {
+            creep.get_active_bodyparts(Part::Attack)
+                + creep.get_active_bodyparts(Part::RangedAttack)
+                > 0
+        })
+        .min_by_key(|creep| creep.pos().get_range_to(flag.pos()))
+    else {
+        return;
+    };
+
+    if let Some(hostile) = creep.pos().find_closest_by_range(find::HOSTILE_CREEPS) {
+        info!(
+            "flag {name}: sending {} to attack {}",
+            creep.name(),
+            hostile.name()
+        );
+        if let Some(id) = hostile.try_id() {
+            creep_targets.insert(creep.name(), CreepTarget::Attack(id));
+        }
+    }
+}
+
+fn build_flag(flag: &Flag) {
+    let name = String::from(flag.name());
+    let Some(room) = flag.room() else {
+        return;
+    };
+
+    let kind = name.split('-').next().unwrap_or_default();
+    let structure_type =
+        StructureType::from_js_value(&JsValue::from_str(kind)).unwrap_or(StructureType::Road);
+    let pos = flag.pos();
+
+    match room.create_construction_site(pos.x().u8(), pos.y().u8(), structure_type, None) {
+        Ok(()) => info!("flag {name}: placed a {structure_type:?} site at {pos}"),
+        Err(e) => warn!("flag {name}: couldn't place a {structure_type:?} site: {e:?}"),
+    }
+    flag.remove();
+}
@@ -0,0 +1,215 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use log::*;
+use screeps::{
+    constants::{ErrorCode, ResourceType},
+    find, game,
+    local::ObjectId,
+    objects::{Creep, StructureContainer},
+    prelude::*,
+};
+
+use crate::body::Role;
+use crate::timer::intent;
+use crate::{spawning, working, CreepTarget};
+
+// a hauler gives up on its home container once it's been empty this long, e.g. because
+// its miner died
+const HOME_EMPTY_TICKS: u32 = 50;
+// haulers sit out handoffs for this long after taking part in one, so the pair don't
+// pass the same energy straight back once they've both turned around
+const HANDOFF_COOLDOWN: u32 = 10;
+
+// the container a hauler empties, and since when it's been empty if it is
+struct HaulerHome {
+    container: ObjectId<StructureContainer>,
+    empty_since: Option<u32>,
+}
+
+thread_local! {
+    static HAULER_HOMES: RefCell<HashMap<String, HaulerHome>> = RefCell::new(HashMap::new());
+
+    // the tick each hauler last gave or took energy in a handoff
+    static LAST_HANDOFF: RefCell<HashMap<String, u32>> = RefCell::new(HashMap::new());
+}
+
+// the container with the fewest haulers already assigned, the closest of those breaking
+// the tie. Each candidate is the container, its range from the hauler and how many
+// haulers it has
+pub fn pick_home<T: Copy>(candidates: &[(T, u32, usize)]) -> Option<T> {
+    candidates
+        .iter()
+        .min_by_key(|&&(_, range, assigned)| (assigned, range))
+        .map(|&(container, _, _)| container)
+}
+
+// the source container this hauler works out of. It sticks with the one it was given
+// until that's destroyed or stays empty for a while, and only then gets another of the
+// `containers` that have energy in them
+pub fn home_container(
+    creep: &Creep,
+    containers: &[StructureContainer],
+) -> Option<StructureContainer> {
+    let name = creep.name();
+    let now = game::time();
+    let energy = |container: &StructureContainer| {
+        container
+            .store()
+            .get_used_capacity(Some(ResourceType::Energy))
+    };
+
+    HAULER_HOMES.with_borrow_mut(|homes| {
+        if let Some(home) = homes.get_mut(&name) {
+            if let Some(container) = home.container.resolve() {
+                if energy(&container) > 0 {
+                    home.empty_since = None;
+                    return Some(container);
+                }
+                let since = *home.empty_since.get_or_insert(now);
+                if now - since < HOME_EMPTY_TICKS {
+                    return Some(container);
+                }
+            }
+            homes.remove(&name);
+        }
+
+        let mut assigned: HashMap<ObjectId<StructureContainer>, usize> = HashMap::new();
+        for home in homes.values() {
+            *assigned.entry(home.container).or_default() += 1;
+        }
+        let candidates: Vec<(usize, u32, usize)> = containers
+            .iter()
+            .enumerate()
+            .filter(|(_, container)| energy(container) > 0)
+            .map(|(i, container)| {
+                let range = creep.pos().get_range_to(container.pos());
+                (
+                    i,
+                    range,
+                    assigned.get(&container.id()).copied().unwrap_or(0),
+                )
+            })
+            .collect();
+        let container = containers[pick_home(&candidates)?].clone();
+
+        homes.insert(
+            name,
+            HaulerHome {
+                container: container.id(),
+                empty_since: None,
+            },
+        );
+        Some(container)
+    })
+}
+
+// whether a hauler last in a handoff at `last` can take part in another
+pub fn handoff_ready(last: Option<u32>, now: u32) -> bool {
+    last.map_or(true, |last| now.saturating_sub(last) >= HANDOFF_COOLDOWN)
+}
+
+// whether a hauler with `target` is on its way to pick energy up
+fn inbound(target: &CreepTarget) -> bool {
+    matches!(
+        target,
+        CreepTarget::Withdraw(_)
+            | CreepTarget::WithdrawStorage(_)
+            | CreepTarget::Pickup(_)
+            | CreepTarget::Loot(_)
+            | CreepTarget::LootRuin(_)
+    )
+}
+
+// has a full hauler on its way to deliver pass its energy to an empty one from the same
+// home room next to it that's heading out for more, so each of them can turn around
+// where they meet rather than walk the whole way. Returns whether it handed anything over
+pub fn hand_off(creep: &Creep, creep_targets: &HashMap<String, CreepTarget>) -> bool {
+    let now = game::time();
+    let ready = |name: &String| {
+        LAST_HANDOFF.with_borrow(|handoffs| handoff_ready(handoffs.get(name).copied(), now))
+    };
+
+    let name = creep.name();
+    if creep.store().get_used_capacity(Some(ResourceType::Energy)) == 0
+        || !working::is_working(creep)
+        || !ready(&name)
+    {
+        return false;
+    }
+
+    let home = spawning::home_room(creep);
+    let Some(receiver) = creep
+        .pos()
+        .find_in_range(find::MY_CREEPS, 1)
+        .into_iter()
+        .find(|other| {
+            let other_name = other.name();
+            let store = other.store();
+            other_name != name
+                && !other.spawning()
+                && Role::of_creep(&other_name) == Role::Hauler
+                && spawning::home_room(other) == home
+                && !working::is_working(other)
+                && store.get_used_capacity(Some(ResourceType::Energy)) == 0
+                && store.get_free_capacity(Some(ResourceType::Energy)) > 0
+                && creep_targets.get(&other_name).map_or(true, inbound)
+                && ready(&other_name)
+        })
+    else {
+        return false;
+    };
+
+    match intent(
+        "transfer",
+        creep.transfer(&receiver, ResourceType::Energy, None),
+    ) {
+        Ok(()) => {
+            debug!("{name} handed its energy to {}", receiver.name());
+            LAST_HANDOFF.with_borrow_mut(|handoffs| {
+                handoffs.insert(name, now);
+                handoffs.insert(receiver.name(), now);
+            });
+            true
+        }
+        // filled up by someone else this tick, so carry on as usual
+        Err(ErrorCode::Full) => false,
+        Err(e) => {
+            warn!("couldn't hand energy to {}: {:?}", receiver.name(), e);
+            false
+        }
+    }
+}
+
+pub fn forget_dead(alive: &HashSet<String>) {
+    HAULER_HOMES.with_borrow_mut(|homes| homes.retain(|name, _| alive.contains(name)));
+    LAST_HANDOFF.with_borrow_mut(|handoffs| handoffs.retain(|name, _| alive.contains(name)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_assigned_container_wins() {
+        assert_eq!(
+            pick_home(&[("a", 3, 2), ("b", 20, 0), ("c", 5, 1)]),
+            Some("b")
+        );
+        assert_eq!(pick_home::<&str>(&[]), None);
+    }
+
+    #[test]
+    fn closest_breaks_a_tie_on_haulers() {
+        assert_eq!(
+            pick_home(&[("a", 9, 1), ("b", 4, 1), ("c", 6, 1)]),
+            Some("b")
+        );
+    }
+
+    #[test]
+    fn first_listed_breaks_a_full_tie() {
+        assert_eq!(pick_home(&[("a", 4, 1), ("b", 4, 1)]), Some("a"));
+        assert_eq!(pick_home(&[("b", 4, 1), ("a", 4, 1)]), Some("b"));
+    }
+}
@@ -0,0 +1,96 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+use log::*;
+use screeps::{
+    constants::{Part, ENERGY_REGEN_TIME, HARVEST_POWER},
+    game,
+    local::ObjectId,
+    objects::Source,
+};
+
+use crate::CreepTarget;
+
+// how many ticks of income go into each source's saturation figure
+const INCOME_HISTORY_LEN: usize = 10;
+
+thread_local! {
+    static SOURCE_INCOME: RefCell<HashMap<ObjectId<Source>, VecDeque<u32>>> = RefCell::new(HashMap::new());
+}
+
+// the energy per tick a source can give up, spread evenly over its regen window
+pub fn max_income(energy_capacity: u32) -> u32 {
+    energy_capacity / ENERGY_REGEN_TIME
+}
+
+// what the work parts mining a source could pull out of it each tick, which can't be
+// more than the source regenerates
+pub fn source_income(work_parts: u32, energy_capacity: u32) -> u32 {
+    (work_parts * HARVEST_POWER).min(max_income(energy_capacity))
+}
+
+// average income over the history as a percentage of the most the source can give
+pub fn saturation(history: &VecDeque<u32>, energy_capacity: u32) -> f64 {
+    let max = max_income(energy_capacity);
+    if history.is_empty() || max == 0 {
+        return 0.0;
+    }
+
+    let average = history.iter().sum::<u32>() as f64 / history.len() as f64;
+    100.0 * average / max as f64
+}
+
+// tallies the work parts assigned to each source this tick, going by CREEP_TARGETS
+pub fn record_income(creep_targets: &HashMap<String, CreepTarget>) {
+    let creeps = game::creeps();
+    let mut work_parts: HashMap<ObjectId<Source>, u32> = HashMap::new();
+    for (name, target) in creep_targets {
+        let source = match target {
+            CreepTarget::Harvest { source, .. } | CreepTarget::Mine { source, .. } => source,
+            _ => continue,
+        };
+        if let Some(creep) = creeps.get(name.clone()) {
+            *work_parts.entry(*source).or_default() +=
+                creep.get_active_bodyparts(Part::Work) as u32;
+        }
+    }
+
+    SOURCE_INCOME.with_borrow_mut(|incomes| {
+        // sources nobody is mining this tick still get a zero, so they show up as idle
+        // rather than keeping their last figure forever
+        for (source, history) in incomes.iter_mut() {
+            if !work_parts.contains_key(source) {
+                push(history, 0);
+            }
+        }
+
+        for (source_id, works) in work_parts {
+            let Some(source) = source_id.resolve() else {
+                continue;
+            };
+            let history = incomes.entry(source_id).or_default();
+            push(history, source_income(works, source.energy_capacity()));
+        }
+    });
+}
+
+pub fn log_income() {
+    SOURCE_INCOME.with_borrow(|incomes| {
+        for (source_id, history) in incomes {
+            if let Some(source) = source_id.resolve() {
+                info!(
+                    "Source {}: {:.0}% saturated",
+                    source_id,
+                    saturation(history, source.energy_capacity())
+                );
+            }
+        }
+    });
+}
+
+fn push(history: &mut VecDeque<u32>, income: u32) {
+    if history.len() == INCOME_HISTORY_LEN {
+        history.pop_front();
+    }
+    history.push_back(income);
+}
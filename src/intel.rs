@@ -0,0 +1,133 @@
+use js_sys::{Object, Reflect};
+use log::*;
+use screeps::{
+    enums::StructureObject,
+    find, game,
+    local::{Position, RoomCoordinate, RoomName},
+    objects::{Creep, Room},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+use crate::{movement::move_to_cached, owned_rooms};
+
+// rooms seen more recently than this don't need another visit
+pub const SCOUT_INTERVAL: u32 = 3_000;
+// how close to the middle of a room a scout heads for, which keeps it off the exits
+const SCOUT_RANGE: u32 = 20;
+
+// what we know about a room, as of the tick it was last seen. Kept in Memory.rooms
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoomIntel {
+    pub tick: u32,
+    pub owner: Option<String>,
+    pub controller_level: Option<u8>,
+    pub sources: usize,
+    pub hostiles: usize,
+    // energy left in the room's sources when it was seen
+    #[serde(default)]
+    pub source_energy: u32,
+    #[serde(default)]
+    pub invader_core: bool,
+}
+
+impl RoomIntel {
+    pub fn observe(room: &Room) -> Self {
+        let controller = room.controller();
+        RoomIntel {
+            tick: game::time(),
+            owner: controller
+                .as_ref()
+                .and_then(|controller| controller.owner())
+                .map(|owner| owner.username()),
+            controller_level: controller.map(|controller| controller.level()),
+            sources: room.find(find::SOURCES, None).len(),
+            hostiles: room.find(find::HOSTILE_CREEPS, None).len(),
+            source_energy: room
+                .find(find::SOURCES, None)
+                .iter()
+                .map(|source| source.energy())
+                .sum(),
+            invader_core: room
+                .find(find::HOSTILE_STRUCTURES, None)
+                .iter()
+                .any(|structure| matches!(structure, StructureObject::StructureInvaderCore(_))),
+        }
+    }
+
+    pub fn is_stale(&self) -> bool {
+        game::time().saturating_sub(self.tick) >= SCOUT_INTERVAL
+    }
+}
+
+fn rooms_memory() -> Object {
+    let key = JsValue::from_str("rooms");
+    match Reflect::get(&screeps::memory::ROOT, &key) {
+        Ok(rooms) if rooms.is_object() => rooms.into(),
+        _ => {
+            let rooms = Object::new();
+            Reflect::set(&screeps::memory::ROOT, &key, &rooms).unwrap();
+            rooms
+        }
+    }
+}
+
+pub fn load(room_name: RoomName) -> Option<RoomIntel> {
+    let value = Reflect::get(&rooms_memory(), &room_name.to_string().into()).ok()?;
+    if value.is_undefined() {
+        return None;
+    }
+
+    let json = js_sys::JSON::stringify(&value).ok()?;
+    serde_json::from_str(&String::from(json))
+        .map_err(|e| warn!("couldn't read intel for {}: {}", room_name, e))
+        .ok()
+}
+
+pub fn record(room: &Room) {
+    let intel = RoomIntel::observe(room);
+    let value = serde_json::to_string(&intel)
+        .map_err(|e| e.to_string())
+        .and_then(|json| js_sys::JSON::parse(&json).map_err(|e| format!("{:?}", e)));
+
+    match value {
+        Ok(value) => {
+            info!("recorded intel for {}: {:?}", room.name(), intel);
+            Reflect::set(&rooms_memory(), &room.name().to_string().into(), &value).unwrap();
+        }
+        Err(e) => warn!("couldn't record intel for {}: {}", room.name(), e),
+    }
+}
+
+fn needs_scouting(room_name: RoomName) -> bool {
+    load(room_name).map_or(true, |intel| intel.is_stale())
+}
+
+// the first room next to one of ours that we haven't seen in a while
+pub fn next_room_to_scout() -> Option<RoomName> {
+    owned_rooms()
+        .flat_map(|room| game::map::describe_exits(room.name()).values())
+        .find(|&room_name| needs_scouting(room_name))
+}
+
+pub fn run_scout(creep: &Creep) {
+    if let Some(room) = creep.room() {
+        if needs_scouting(room.name()) {
+            record(&room);
+        }
+    }
+
+    let Some(room_name) = next_room_to_scout() else {
+        return;
+    };
+
+    // the middle tile might well be a wall, so anywhere well inside the room will do.
+    // The room gets recorded the moment the scout steps into it anyway
+    let center = RoomCoordinate::new(25).unwrap();
+    let _ = move_to_cached(
+        creep,
+        &Position::new(center, center, room_name),
+        SCOUT_RANGE,
+    );
+}
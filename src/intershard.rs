@@ -0,0 +1,148 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use js_sys::JsString;
+use log::*;
+use screeps::{
+    constants::{controller_levels, INTER_SHARD_MEMORY_SIZE_LIMIT},
+    game, inter_shard_memory,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, owned_rooms};
+
+// how often the other shards' segments get read. They only change as fast as those shards
+// write them, and nothing acts on them yet
+pub const INTERSHARD_READ_INTERVAL: u32 = 100;
+
+// what one shard reports about itself
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShardSummary {
+    pub tick: u32,
+    pub owned_rooms: usize,
+    // all the energy that's gone into the shard's controllers, which is what gcl counts
+    pub gcl_contribution: f64,
+    // creeps on their way to another shard. Nothing travels between shards yet, so this
+    // stays at zero for now
+    pub creeps_en_route: usize,
+}
+
+// a shard's inter-shard segment: its own summary, plus the last it heard from the others
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShardState {
+    pub shards: BTreeMap<String, ShardSummary>,
+}
+
+thread_local! {
+    // summaries read from the other shards, None until the first read since a reset
+    static REMOTE_SHARDS: RefCell<Option<BTreeMap<String, ShardSummary>>> = RefCell::new(None);
+}
+
+// the energy a controller at `level` has taken in total, `progress` being how far it is
+// into the current level
+pub fn gcl_contribution(level: u32, progress: f64) -> f64 {
+    (1..level)
+        .filter_map(controller_levels)
+        .map(|energy| energy as f64)
+        .sum::<f64>()
+        + progress
+}
+
+fn parse(raw: Option<JsString>, whose: &str) -> Option<ShardState> {
+    let raw = String::from(raw?);
+    // a segment nobody's written to yet is empty
+    if raw.is_empty() {
+        return None;
+    }
+    serde_json::from_str(&raw)
+        .map_err(|e| warn!("couldn't read {whose} inter-shard segment: {}", e))
+        .ok()
+}
+
+// this shard's segment as it was last written, None if it's never been written
+pub fn read_intershard() -> Option<ShardState> {
+    parse(inter_shard_memory::get_local(), "local")
+}
+
+fn read_remote(shard: &str) -> Option<ShardState> {
+    parse(
+        inter_shard_memory::get_remote(&JsString::from(shard)),
+        shard,
+    )
+}
+
+// overwrites this shard's segment, unless the state has grown past what it can hold
+pub fn write_intershard(state: &ShardState) {
+    let json = match serde_json::to_string(state) {
+        Ok(json) => json,
+        Err(e) => {
+            warn!("couldn't serialize inter-shard state: {}", e);
+            return;
+        }
+    };
+    // the limit is in utf-16 units, which is never more than the utf-8 length
+    if json.len() > INTER_SHARD_MEMORY_SIZE_LIMIT as usize {
+        warn!(
+            "inter-shard state is {} bytes, over the {} limit, not writing it",
+            json.len(),
+            INTER_SHARD_MEMORY_SIZE_LIMIT
+        );
+        return;
+    }
+    inter_shard_memory::set_local(&JsString::from(json));
+}
+
+fn local_summary() -> ShardSummary {
+    let mut summary = ShardSummary {
+        tick: game::time(),
+        ..Default::default()
+    };
+    for room in owned_rooms() {
+        summary.owned_rooms += 1;
+        if let Some(controller) = room.controller() {
+            summary.gcl_contribution +=
+                gcl_contribution(controller.level() as u32, controller.progress() as f64);
+        }
+    }
+    summary
+}
+
+// catches up on the other shards every so often, and publishes this shard's summary every
+// tick. Does nothing until Memory.config.shards names the other shards
+pub fn run_intershard(tick: u32) {
+    let others = config::shards();
+    if others.is_empty() {
+        return;
+    }
+    let shard = game::shard::name();
+
+    let restored = REMOTE_SHARDS.with_borrow(|remote| remote.is_some());
+    if !restored || tick % INTERSHARD_READ_INTERVAL == 0 {
+        // what we last wrote is all we know about the others until their segments are read
+        let mut remote = read_intershard().unwrap_or_default().shards;
+        remote.remove(&shard);
+
+        for other in others.into_iter().filter(|other| *other != shard) {
+            let Some(summary) =
+                read_remote(&other).and_then(|mut state| state.shards.remove(&other))
+            else {
+                continue;
+            };
+            info!(
+                "{other} at tick {}: {} rooms, {:.0} gcl energy, {} creeps en route",
+                summary.tick,
+                summary.owned_rooms,
+                summary.gcl_contribution,
+                summary.creeps_en_route
+            );
+            remote.insert(other, summary);
+        }
+        REMOTE_SHARDS.set(Some(remote));
+    }
+
+    let mut shards = REMOTE_SHARDS.with_borrow(|remote| remote.clone().unwrap_or_default());
+    shards.insert(shard, local_summary());
+    write_intershard(&ShardState { shards });
+}
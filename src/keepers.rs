@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+
+use screeps::{
+    constants::SOURCE_KEEPER_USERNAME,
+    enums::StructureObject,
+    find,
+    local::RoomXY,
+    look,
+    objects::{Creep, Room, StructureKeeperLair},
+    prelude::*,
+};
+
+use crate::combat::flee_direction;
+use crate::find_cache;
+
+// keepers stay close to their lair, attacking anything that comes within this range
+pub const KEEPER_RANGE: u8 = 5;
+// a lair this close to spawning its keeper is as good as occupied
+const LAIR_WARNING_TICKS: u32 = 10;
+
+// whether a lair reading `ticks_to_spawn` is about to let its keeper out. The game has no
+// countdown while the keeper's alive, which comes through as 0, and then it's the keeper
+// rather than the lair to keep away from
+pub fn lair_warning(ticks_to_spawn: u32) -> bool {
+    (1..=LAIR_WARNING_TICKS).contains(&ticks_to_spawn)
+}
+
+// whether a lair is about to spawn its keeper or has one standing on it, having only just
+// spawned it
+fn lair_active(lair: &StructureKeeperLair) -> bool {
+    lair_warning(lair.ticks_to_spawn())
+        || lair.pos().look_for(look::CREEPS).is_ok_and(|creeps| {
+            creeps
+                .iter()
+                .any(|creep| creep.owner().username() == SOURCE_KEEPER_USERNAME)
+        })
+}
+
+// every tile within KEEPER_RANGE of any of `lairs`
+pub fn danger_zone(lairs: &[RoomXY]) -> HashSet<RoomXY> {
+    let range = KEEPER_RANGE as i16;
+    lairs
+        .iter()
+        .flat_map(|lair| {
+            let (x, y) = (lair.x.u8() as i16, lair.y.u8() as i16);
+            (-range..=range).flat_map(move |dx| (-range..=range).map(move |dy| (x + dx, y + dy)))
+        })
+        .filter_map(|(x, y)| RoomXY::try_from((u8::try_from(x).ok()?, u8::try_from(y).ok()?)).ok())
+        .collect()
+}
+
+fn lairs(room: &Room) -> Vec<StructureKeeperLair> {
+    find_cache::structures(room)
+        .iter()
+        .filter_map(|structure| match structure {
+            StructureObject::StructureKeeperLair(lair) => Some(lair.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+// the tiles in a room that keepers guard, empty for rooms without any
+pub fn danger_tiles(room: &Room) -> HashSet<RoomXY> {
+    let lairs: Vec<RoomXY> = lairs(room).iter().map(|lair| lair.pos().xy()).collect();
+    danger_zone(&lairs)
+}
+
+// steps a creep away from any keeper that's awake and in reach, or any lair about to
+// spawn one. Returns whether it had to run, in which case it's done for the tick
+pub fn flee_keepers(creep: &Creep) -> bool {
+    let Some(room) = creep.room() else {
+        return false;
+    };
+    let lairs = lairs(&room);
+    if lairs.is_empty() {
+        return false;
+    }
+
+    let pos = creep.pos();
+    let threats: Vec<RoomXY> = pos
+        .find_in_range(find::HOSTILE_CREEPS, KEEPER_RANGE)
+        .iter()
+        .filter(|hostile| hostile.owner().username() == SOURCE_KEEPER_USERNAME)
+        .map(|keeper| keeper.pos().xy())
+        .chain(
+            lairs
+                .iter()
+                .filter(|lair| pos.in_range_to(lair.pos(), KEEPER_RANGE as u32))
+                .filter(|lair| lair_active(lair))
+                .map(|lair| lair.pos().xy()),
+        )
+        .collect();
+
+    let Some(direction) = flee_direction(pos.xy(), &threats) else {
+        return false;
+    };
+    let _ = creep.move_direction(direction);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lair_warns_only_while_counting_down() {
+        // no countdown while the keeper's out
+        assert!(!lair_warning(0));
+        assert!(lair_warning(1));
+        assert!(lair_warning(LAIR_WARNING_TICKS));
+        assert!(!lair_warning(LAIR_WARNING_TICKS + 1));
+    }
+
+    fn xy(x: u8, y: u8) -> RoomXY {
+        RoomXY::try_from((x, y)).unwrap()
+    }
+
+    #[test]
+    fn danger_ends_at_keeper_range() {
+        let zone = danger_zone(&[xy(25, 25)]);
+        let range = KEEPER_RANGE;
+        assert!(zone.contains(&xy(25 + range, 25)));
+        assert!(zone.contains(&xy(25 - range, 25 + range)));
+        assert!(!zone.contains(&xy(25 + range + 1, 25)));
+        assert!(!zone.contains(&xy(25, 25 - range - 1)));
+        let side = 2 * range as usize + 1;
+        assert_eq!(zone.len(), side * side);
+    }
+
+    #[test]
+    fn danger_zones_merge_and_stop_at_the_edge() {
+        assert!(danger_zone(&[]).is_empty());
+
+        let side = KEEPER_RANGE as usize + 1;
+        let corner = danger_zone(&[xy(0, 0)]);
+        assert_eq!(corner.len(), side * side);
+        assert!(corner.contains(&xy(KEEPER_RANGE, KEEPER_RANGE)));
+
+        // two lairs a tile apart share most of their tiles
+        let both = danger_zone(&[xy(20, 20), xy(21, 20)]);
+        let full = 2 * KEEPER_RANGE as usize + 1;
+        assert_eq!(both.len(), full * (full + 1));
+    }
+}
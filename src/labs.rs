@@ -0,0 +1,112 @@
+use log::*;
+use screeps::{
+    constants::{ResourceType, LAB_REACTION_AMOUNT},
+    enums::StructureObject,
+    objects::StructureLab,
+    prelude::*,
+};
+
+use crate::{config::room_config, find_cache, owned_rooms};
+
+// how often labs get a chance to react. Most reactions have a cooldown at least this long
+// anyway
+pub const LAB_INTERVAL: u32 = 10;
+// input labs have to be within this range of every output lab they feed
+const REACTION_RANGE: u32 = 2;
+
+// splits a room's labs into the two that feed reactions and the ones that run them. The
+// inputs are whichever two have the most other labs in reach, the usual stamp layout,
+// and every lab in reach of both of them is an output
+pub fn lab_layout(labs: &[StructureLab]) -> Option<([&StructureLab; 2], Vec<&StructureLab>)> {
+    let in_reach = |lab: &StructureLab| {
+        labs.iter()
+            .filter(|other| other.pos().in_range_to(lab.pos(), REACTION_RANGE))
+            .count()
+    };
+
+    let mut by_reach: Vec<&StructureLab> = labs.iter().collect();
+    by_reach.sort_by_key(|&lab| (std::cmp::Reverse(in_reach(lab)), lab.id()));
+    let [first, second] = [*by_reach.first()?, *by_reach.get(1)?];
+
+    let outputs: Vec<&StructureLab> = by_reach[2..]
+        .iter()
+        .copied()
+        .filter(|lab| {
+            lab.pos().in_range_to(first.pos(), REACTION_RANGE)
+                && lab.pos().in_range_to(second.pos(), REACTION_RANGE)
+        })
+        .collect();
+
+    (!outputs.is_empty()).then_some(([first, second], outputs))
+}
+
+fn holds(lab: &StructureLab, resource: ResourceType) -> bool {
+    lab.mineral_type() == Some(resource)
+        && lab.store().get_used_capacity(Some(resource)) >= LAB_REACTION_AMOUNT
+}
+
+// whether an output lab has room for another batch of `compound`
+fn can_take(lab: &StructureLab, compound: ResourceType) -> bool {
+    lab.cooldown() == 0
+        && lab
+            .mineral_type()
+            .map_or(true, |mineral| mineral == compound)
+        && lab.store().get_free_capacity(Some(compound)) >= LAB_REACTION_AMOUNT as i32
+}
+
+// runs the reaction each room's config asks for in every output lab that's ready, as long
+// as the input labs hold its reagents
+pub fn run_labs() {
+    for room in owned_rooms() {
+        let Some(compound) = room_config(room.name()).lab_reaction else {
+            continue;
+        };
+        let Some([a, b]) = compound.reaction_components() else {
+            warn!("{:?} isn't made by a reaction", compound);
+            continue;
+        };
+
+        let labs: Vec<StructureLab> = find_cache::structures(&room)
+            .iter()
+            .filter_map(|structure| match structure {
+                StructureObject::StructureLab(lab) if lab.my() => Some(lab.clone()),
+                _ => None,
+            })
+            .collect();
+        let Some(([first, second], outputs)) = lab_layout(&labs) else {
+            continue;
+        };
+
+        // the reagents can be loaded either way round
+        let (input_a, input_b) = if holds(first, a) && holds(second, b) {
+            (first, second)
+        } else if holds(first, b) && holds(second, a) {
+            (second, first)
+        } else {
+            debug!(
+                "input labs in {} aren't loaded for {:?}",
+                room.name(),
+                compound
+            );
+            continue;
+        };
+
+        // every reaction draws from the inputs, and stores don't update until the next tick,
+        // so only run as many as they can cover between them
+        let batches = input_a
+            .store()
+            .get_used_capacity(Some(a))
+            .min(input_b.store().get_used_capacity(Some(b)))
+            / LAB_REACTION_AMOUNT;
+
+        for output in outputs
+            .into_iter()
+            .filter(|output| can_take(output, compound))
+            .take(batches as usize)
+        {
+            output
+                .run_reaction(input_a, input_b)
+                .unwrap_or_else(|e| warn!("couldn't run {:?} reaction: {:?}", compound, e));
+        }
+    }
+}
@@ -1,28 +1,87 @@
 #![feature(hash_extract_if, inline_const, const_trait_impl, const_for)]
 
-use std::cell::RefCell;
-use std::collections::{hash_map::Entry, HashMap};
+use std::cell::{Cell, RefCell};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 
 use itertools::Itertools;
+use js_sys::{Object, Reflect};
 use log::*;
 use rand::rngs::SmallRng;
-pub(crate) use rand::{Rng, SeedableRng};
+use rand::SeedableRng;
 use screeps::{
-    constants::{ErrorCode, Part, ResourceType},
+    constants::{ErrorCode, Part, ResourceType, PIXEL_CPU_COST},
     enums::StructureObject,
     find, game,
-    local::ObjectId,
+    local::{ObjectId, Position, RoomName},
+    look,
     objects::{Creep, Source, StructureController},
     prelude::*,
 };
 use screeps::{
-    ConstructionSite, PolyStyle, RoomObject, Structure, StructureExtension, StructureSpawn,
-    StructureTower, Terrain,
+    ConstructionSite, Resource, Room, RoomObject, Ruin, Structure, StructureContainer,
+    StructureExtension, StructureLab, StructureSpawn, StructureStorage, StructureTerminal,
+    StructureTower, Tombstone,
 };
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
+mod body;
+mod boosts;
+mod caps;
+mod combat;
+mod commands;
+mod config;
+mod construction;
+mod defense;
+mod energy;
+mod find_cache;
+mod flags;
+mod hauling;
+mod income;
+mod intel;
+mod intershard;
+mod keepers;
+mod labs;
+mod lifespan;
+mod links;
 mod logging;
+mod market;
+mod movement;
+mod nukes;
+mod observer;
+mod parking;
+mod perimeter;
+mod planner;
+mod power;
+mod progress;
+mod recycling;
+mod renewal;
+mod repair;
+mod sources;
+mod spawning;
+mod stats;
+mod status;
+mod storage;
+mod structures;
+mod targeting;
+mod terminals;
+mod timer;
+mod tower;
+mod upgrading;
+mod visuals;
+mod working;
+
+use body::{build_body, Role};
+use caps::CreepCaps;
+use config::RoomConfig;
+use movement::DefaultMove;
+use repair::{repair_threshold, ticks_to_decay_death};
+use sources::{least_loaded, source_container};
+use spawning::{log_spawn_error, should_spawn, try_spawn, SpawnPlan, SpawnQueue};
+use stats::Stats;
+use storage::StorageMode;
+use targeting::{ControllerSnapshot, CreepSnapshot, RoomSnapshot, SinkCandidate, SinkKind, Sinks};
+use timer::{intent, TimerLog};
 
 // add wasm_bindgen to any function you would like to expose for call from js
 #[wasm_bindgen]
@@ -33,9 +92,19 @@ pub fn setup() {
 // this is one way to persist data between ticks within Rust's memory, as opposed to
 // keeping state in memory on game objects - but will be lost on global resets!
 thread_local! {
-    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::seed_from_u64(200));
+    // seeded from the tick the global started on. A fixed seed would have every global
+    // reset replay the exact same "random" source picks, piling creeps onto the same
+    // sources each time. `commands::seed_rng` pins it down again to reproduce a run
+    pub(crate) static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::seed_from_u64(game::time() as u64));
 
     static CREEP_TARGETS: RefCell<HashMap<String, CreepTarget>> = RefCell::new(HashMap::new());
+
+    // false until CREEP_TARGETS has been reloaded from Memory after a global reset
+    static CREEP_TARGETS_RESTORED: Cell<bool> = Cell::new(false);
+
+    // the tick the snapshots below were taken on, and each room's
+    static SHARED_SNAPSHOTS: RefCell<(u32, HashMap<RoomName, SharedSnapshot>)> =
+        RefCell::new((0, HashMap::new()));
 }
 
 trait SumParts {
@@ -52,37 +121,101 @@ impl SumParts for [Part] {
 // to the object id so that we can grab a fresh reference to the object each successive tick,
 // since screeps game objects become 'stale' and shouldn't be used beyond the tick they were fetched
 #[non_exhaustive]
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 enum CreepTarget {
     Upgrade(ObjectId<StructureController>),
-    Harvest(ObjectId<Source>),
+    // harvest `source` from the tile `pos`, held for this creep alone
+    Harvest {
+        source: ObjectId<Source>,
+        pos: Position,
+    },
     Construct(ObjectId<ConstructionSite>),
-    Store(StoreTarget),
+    Store {
+        target: StoreTarget,
+        resource: ResourceType,
+    },
     Repair(ObjectId<Structure>),
+    Pickup(ObjectId<Resource>),
+    Loot(ObjectId<Tombstone>),
+    // takes whatever's left in a ruin, one resource at a time
+    LootRuin(ObjectId<Ruin>),
+    Attack(ObjectId<Creep>),
+    Reserve(ObjectId<StructureController>),
+    Claim(ObjectId<StructureController>),
+    // stand on `pos` and harvest `source` for good
+    Mine {
+        source: ObjectId<Source>,
+        pos: Position,
+    },
+    Withdraw(ObjectId<StructureContainer>),
+    WithdrawStorage(ObjectId<StructureStorage>),
+    AttackStructure(ObjectId<Structure>),
+    Dismantle(ObjectId<Structure>),
+    // stand on `pos` and drop all the energy being carried
+    Drop(Position),
+    // nothing useful to do, so wait out of the way
+    Idle(Position),
+    // head to the spawn and have it take the creep apart for some of its energy back
+    Recycle(ObjectId<StructureSpawn>),
+    // get boosted with `compound` at `lab` before starting work
+    Boost {
+        lab: ObjectId<StructureLab>,
+        compound: ResourceType,
+    },
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 enum StoreTarget {
     Extension(ObjectId<StructureExtension>),
     Spawn(ObjectId<StructureSpawn>),
     Tower(ObjectId<StructureTower>),
+    Storage(ObjectId<StructureStorage>),
+    Terminal(ObjectId<StructureTerminal>),
+}
+
+impl CreepTarget {
+    // filling spawns, extensions and towers is all about energy
+    fn store_energy(target: StoreTarget) -> Self {
+        CreepTarget::Store {
+            target,
+            resource: ResourceType::Energy,
+        }
+    }
+
+    // whether the target object can still be fetched this tick
+    fn resolves(&self) -> bool {
+        match self {
+            CreepTarget::Upgrade(id) => id.resolve().is_some(),
+            CreepTarget::Harvest { source, .. } => source.resolve().is_some(),
+            CreepTarget::Construct(id) => id.resolve().is_some(),
+            CreepTarget::Store { target, .. } => target.resolve().is_some(),
+            CreepTarget::Repair(id) => id.resolve().is_some(),
+            CreepTarget::Pickup(id) => id.resolve().is_some(),
+            CreepTarget::Loot(id) => id.resolve().is_some(),
+            CreepTarget::LootRuin(id) => id.resolve().is_some(),
+            CreepTarget::Attack(id) => id.resolve().is_some(),
+            CreepTarget::Reserve(id) => id.resolve().is_some(),
+            CreepTarget::Claim(id) => id.resolve().is_some(),
+            CreepTarget::Mine { source, .. } => source.resolve().is_some(),
+            CreepTarget::Withdraw(id) => id.resolve().is_some(),
+            CreepTarget::WithdrawStorage(id) => id.resolve().is_some(),
+            CreepTarget::AttackStructure(id) => id.resolve().is_some(),
+            CreepTarget::Dismantle(id) => id.resolve().is_some(),
+            CreepTarget::Drop(_) | CreepTarget::Idle(_) => true,
+            CreepTarget::Recycle(id) => id.resolve().is_some(),
+            CreepTarget::Boost { lab, .. } => lab.resolve().is_some(),
+        }
+    }
 }
 
 impl StoreTarget {
     fn resolve(&self) -> Option<ResolvedStoreTarget> {
         match self {
-            StoreTarget::Extension(id) => match id.resolve() {
-                Some(structure) => Some(ResolvedStoreTarget::Extension(structure)),
-                None => None,
-            },
-            StoreTarget::Spawn(id) => match id.resolve() {
-                Some(structure) => Some(ResolvedStoreTarget::Spawn(structure)),
-                None => None,
-            },
-            StoreTarget::Tower(id) => match id.resolve() {
-                Some(structure) => Some(ResolvedStoreTarget::Tower(structure)),
-                None => None,
-            },
+            StoreTarget::Extension(id) => id.resolve().map(ResolvedStoreTarget::Extension),
+            StoreTarget::Spawn(id) => id.resolve().map(ResolvedStoreTarget::Spawn),
+            StoreTarget::Tower(id) => id.resolve().map(ResolvedStoreTarget::Tower),
+            StoreTarget::Storage(id) => id.resolve().map(ResolvedStoreTarget::Storage),
+            StoreTarget::Terminal(id) => id.resolve().map(ResolvedStoreTarget::Terminal),
         }
     }
 }
@@ -95,6 +228,10 @@ enum ResolvedStoreTarget {
     Spawn(StructureSpawn),
     #[serde(skip)]
     Tower(StructureTower),
+    #[serde(skip)]
+    Storage(StructureStorage),
+    #[serde(skip)]
+    Terminal(StructureTerminal),
 }
 
 impl HasStore for ResolvedStoreTarget {
@@ -105,54 +242,142 @@ impl HasStore for ResolvedStoreTarget {
             Extension(structure) => structure.store(),
             Spawn(structure) => structure.store(),
             Tower(structure) => structure.store(),
+            Storage(structure) => structure.store(),
+            Terminal(structure) => structure.store(),
         }
     }
 }
 
 impl AsRef<RoomObject> for ResolvedStoreTarget {
-    fn as_ref<'a>(&'a self) -> &'a RoomObject {
+    fn as_ref(&self) -> &RoomObject {
         use ResolvedStoreTarget::*;
 
         match self {
             Extension(structure) => structure.as_ref(),
             Spawn(structure) => structure.as_ref(),
             Tower(structure) => structure.as_ref(),
+            Storage(structure) => structure.as_ref(),
+            Terminal(structure) => structure.as_ref(),
         }
     }
 }
 
 impl Transferable for ResolvedStoreTarget {}
 
-#[derive(Deserialize, Debug)]
-struct Memory {
-    creeps: HashMap<String, serde_json::Value>,
+// rooms whose controller we own
+fn owned_rooms() -> impl Iterator<Item = Room> {
+    game::rooms()
+        .values()
+        .filter(|room| room.controller().is_some_and(|controller| controller.my()))
 }
 
-trait DefaultMove {
-    fn default_move_to<T>(&self, target: &T) -> Result<(), ErrorCode>
-    where
-        T: AsRef<RoomObject>;
+// drops Memory.creeps entries for creeps that no longer exist. This only walks the keys
+// and deletes the orphans in place, rather than round-tripping the whole object through
+// serde, so it's cheap enough to run every tick
+fn clean_creep_memory(alive: &HashSet<String>) {
+    let creeps = Reflect::get(&screeps::memory::ROOT, &"creeps".into()).unwrap_or_default();
+    let Some(creeps) = creeps.dyn_ref::<Object>() else {
+        // a fresh account has no Memory.creeps until the first creep writes to its memory,
+        // and anything that isn't an object would break every creep's memory access, so
+        // start it off empty either way
+        if !creeps.is_undefined() {
+            debug!("Memory.creeps isn't an object, resetting it: {:?}", creeps);
+        }
+        if let Err(e) = Reflect::set(&screeps::memory::ROOT, &"creeps".into(), &Object::new()) {
+            warn!("couldn't initialize creep memory: {:?}", e);
+        }
+        return;
+    };
+
+    let mut removed = 0;
+    for key in Object::keys(creeps).iter() {
+        if key.as_string().is_some_and(|name| !alive.contains(&name)) {
+            if let Err(e) = Reflect::delete_property(creeps, &key) {
+                warn!("couldn't clear creep memory: {:?}", e);
+            }
+            removed += 1;
+        }
+    }
+
+    if removed > 0 {
+        info!("cleared memory of {removed} dead creeps");
+    }
 }
 
-impl DefaultMove for Creep {
-    fn default_move_to<T>(&self, target: &T) -> Result<(), ErrorCode>
-    where
-        T: AsRef<RoomObject>,
-    {
-        self.move_to_with_options(
-            target,
-            Some(
-                screeps::MoveToOptions::new()
-                    .reuse_path(5)
-                    .visualize_path_style(
-                        PolyStyle::default()
-                            .fill("black")
-                            .stroke_width(0.15)
-                            .opacity(0.1)
-                            .line_style(screeps::LineDrawStyle::Dashed),
-                    ),
-            ),
-        )
+// CREEP_TARGETS is mirrored into Memory.creep_targets at the end of every tick so creeps
+// can pick their tasks right back up after a global reset wipes the wasm heap. This goes
+// through serde_json rather than serde_wasm_bindgen, since the latter writes object ids
+// as byte arrays which don't survive Memory's JSON round trip
+fn load_creep_targets() -> HashMap<String, CreepTarget> {
+    let raw =
+        Reflect::get(&screeps::memory::ROOT, &"creep_targets".into()).unwrap_or(JsValue::UNDEFINED);
+
+    if raw.is_undefined() {
+        return HashMap::new();
+    }
+
+    let json: String = match js_sys::JSON::stringify(&raw) {
+        Ok(json) => json.into(),
+        Err(_) => return HashMap::new(),
+    };
+
+    let entries = match serde_json::from_str::<HashMap<String, serde_json::Value>>(&json) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("couldn't load creep targets: {}", e);
+            return HashMap::new();
+        }
+    };
+
+    // each target loads on its own, so one that can't be read only costs that creep its
+    // target rather than everyone theirs
+    entries
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let target = serde_json::from_value::<CreepTarget>(value.clone())
+                .map(Some)
+                .or_else(|e| {
+                    serde_json::from_value::<LegacyTarget>(value)
+                        .map(LegacyTarget::upgrade)
+                        .map_err(|_| e)
+                })
+                .map_err(|e| warn!("couldn't load creep target for {name}: {}", e))
+                .ok()??;
+            target.resolves().then_some((name, target))
+        })
+        .collect()
+}
+
+// targets as they were saved before they carried more than an id, so ones already in
+// Memory still load after an upgrade
+#[derive(Deserialize)]
+enum LegacyTarget {
+    // always energy back then
+    Store(StoreTarget),
+    // from before mining tiles were held, so there's no tile to go back to
+    Harvest(ObjectId<Source>),
+}
+
+impl LegacyTarget {
+    // None for targets that can't be carried over, whose creeps just pick a new one
+    fn upgrade(self) -> Option<CreepTarget> {
+        match self {
+            LegacyTarget::Store(target) => Some(CreepTarget::store_energy(target)),
+            LegacyTarget::Harvest(_) => None,
+        }
+    }
+}
+
+fn save_creep_targets(creep_targets: &HashMap<String, CreepTarget>) {
+    let value = serde_json::to_string(creep_targets)
+        .map_err(|e| e.to_string())
+        .and_then(|json| js_sys::JSON::parse(&json).map_err(|e| format!("{:?}", e)));
+
+    match value {
+        Ok(value) => {
+            Reflect::set(&screeps::memory::ROOT, &"creep_targets".into(), &value).unwrap();
+        }
+        Err(e) => warn!("couldn't save creep targets: {}", e),
     }
 }
 
@@ -162,198 +387,781 @@ pub fn game_loop() {
     // info!("loop starting! CPU: {}", game::cpu::get_used());
     let starting_time = game::cpu::get_used();
     let current_tick = game::time();
+    let mut tick_timer = TimerLog::new("tick");
+
+    logging::apply_memory_level();
+    find_cache::check_structure_ids();
 
     if current_tick % 10 == 0 {
         CREEP_TARGETS.with_borrow(|ct_refcell| {
             info!("CREEP_TARGETS: {:#?}", ct_refcell);
         });
+        income::log_income();
+        progress::log_progress();
+        find_cache::log_structure_savings();
+    }
+
+    if current_tick % stats::HEAP_LOG_INTERVAL == 0 {
+        stats::log_heap();
+    }
+
+    let alive: HashSet<String> = game::creeps().keys().collect();
+    clean_creep_memory(&alive);
+    movement::forget_dead(&alive);
+    hauling::forget_dead(&alive);
+    status::forget_dead(&alive);
+
+    if current_tick % construction::SITE_CLEANUP_INTERVAL == 0 {
+        construction::clean_up_sites();
     }
 
-    if current_tick % 60 == 0 {
-        use js_sys::Reflect;
-        let alive_creeps: Vec<String> = game::creeps().keys().collect();
+    tick_timer.lap("housekeeping");
 
-        let raw_mem = screeps::memory::ROOT.clone();
+    energy::record_energy();
+    log_body_histogram();
 
-        // info!("{raw_mem:#?}");
+    for room in owned_rooms() {
+        run_room(&room, &config::room_config(room.name()));
+    }
 
-        let memory: Result<Memory, _> =
-            serde_wasm_bindgen::from_value(JsValue::from(raw_mem.clone()));
+    tick_timer.lap("rooms");
 
-        if let Ok(mut memory) = memory {
-            let starting = memory.creeps.keys().count();
-            let removed = memory
-                .creeps
-                .extract_if(|k, _v| !alive_creeps.contains(k))
-                .count();
+    power::run_power_creeps();
 
-            Reflect::set(
-                &screeps::memory::ROOT,
-                &"creeps".into(),
-                &serde_wasm_bindgen::to_value(&memory.creeps).unwrap(),
-            )
-            .unwrap();
+    if current_tick % nukes::NUKE_CHECK_INTERVAL == 0 {
+        nukes::check_nukes();
+    }
 
-            info!("\t| removed: {removed}\n\t| starting: {starting}");
-        } else {
-            warn!("Bad memory");
-        }
+    if current_tick % terminals::TERMINAL_INTERVAL == 0 {
+        terminals::run_terminals();
     }
 
-    for structure in game::structures().values() {
-        if let StructureObject::StructureTower(tower) = structure {
-            if let Some(target) = tower
-                .pos()
-                .find_closest_by_range(screeps::find::HOSTILE_CREEPS)
-            {
-                tower.attack(&target).unwrap_or_else(|e| {
-                    warn!("unable to attack target: {:?}", e);
-                });
-            }
-        }
+    // offset so deals never land on the same tick as a terminal send
+    if current_tick % market::MARKET_INTERVAL == market::MARKET_INTERVAL / 4 {
+        market::run_market();
+    }
+
+    if current_tick % labs::LAB_INTERVAL == 0 {
+        labs::run_labs();
+    }
+
+    if !CREEP_TARGETS_RESTORED.get() {
+        let restored = load_creep_targets();
+        info!("restored {} creep targets from memory", restored.len());
+        CREEP_TARGETS.set(restored);
+        CREEP_TARGETS_RESTORED.set(true);
     }
 
     // mutably borrow the creep_targets refcell, which is holding our creep target locks
     // in the wasm heap
-    CREEP_TARGETS.with_borrow_mut(|mut creep_targets| {
+    CREEP_TARGETS.with_borrow_mut(|creep_targets| {
         debug!("running creeps");
-        for creep in game::creeps().values() {
-            run_creep(&creep, &mut creep_targets);
+        let _timer = TimerLog::new("creeps");
+        // forget targets held by creeps that have since died, so they stop counting
+        // towards things like source assignments
+        creep_targets.retain(|name, _| alive.contains(name));
+        // and free up the mining tiles of any that aren't harvesting any more
+        sources::sync_harvest_spots(creep_targets.iter().filter_map(
+            |(name, target)| match target {
+                CreepTarget::Harvest { pos, .. } | CreepTarget::Mine { pos, .. } => {
+                    Some((*pos, name.clone()))
+                }
+                _ => None,
+            },
+        ));
+
+        flags::process_flags(creep_targets);
+        upgrading::rescue_controllers(creep_targets);
+
+        if current_tick % recycling::RECYCLE_INTERVAL == 0 {
+            recycling::recycle_surplus(creep_targets);
+            caps::recycle_over_cap(creep_targets);
+        }
+
+        // the economy goes first, so if cpu runs short it's the upgrading and building
+        // that waits a tick
+        let mut creeps: Vec<Creep> = game::creeps().values().collect();
+        creeps.sort_by_key(|creep| Role::of_creep(&creep.name()).priority());
+
+        let mut work_splits = work_splits(creep_targets);
+
+        let cpu_cutoff = game::cpu::tick_limit() - CREEP_CPU_RESERVE;
+        let mut skipped = 0;
+        for (i, creep) in creeps.iter().enumerate() {
+            // the rest keep their targets and just stand still until next tick
+            if game::cpu::get_used() > cpu_cutoff {
+                skipped = creeps.len() - i;
+                break;
+            }
+            run_creep(creep, creep_targets, &mut work_splits);
         }
+        if skipped > 0 {
+            warn!(
+                "skipped {skipped} creeps to stay under the cpu tick limit, {:.1} used",
+                game::cpu::get_used()
+            );
+        }
+
+        income::record_income(creep_targets);
     });
 
-    debug!("running spawns");
-    let mut additional = 0;
-    for spawn in game::spawns().values() {
-        debug!("running spawn {}", String::from(spawn.name()));
-
-        // TODO: improve this. Builder pattern maybe?
-        const THRESHOLDS: &[(usize, u32, &[Part])] = &[
-            (
-                6,
-                300,
-                &[Part::Move, Part::Move, Part::Carry, Part::Carry, Part::Work],
-            ),
-            (
-                20,
-                550,
-                &[
-                    Part::Move,
-                    Part::Move,
-                    Part::Move,
-                    Part::Carry,
-                    Part::Carry,
-                    Part::Work,
-                    Part::Work,
-                    Part::Work,
-                ],
-            ),
-        ];
-
-        if let Some(room) = spawn.room() {
-            let current_creeps = game::creeps().keys().count();
-            let energy_available = &room.energy_available();
-            let body_types = game::creeps()
-                .values()
-                .map(|c| c.body())
-                .map(|b| b.into_iter().map(|p| p.part()))
-                .map(|b| {
-                    b.map(|p| match p {
-                        Part::Move => "M",
-                        Part::Work => "W",
-                        Part::Carry => "C",
-                        Part::Attack => "A",
-                        Part::RangedAttack => "RA",
-                        Part::Tough => "T",
-                        Part::Heal => "H",
-                        Part::Claim => "C",
-                        _ => "?",
-                    })
-                    .join("")
-                })
-                .fold(HashMap::new(), |mut acc, key| {
-                    match acc.entry(key) {
-                        Entry::Occupied(mut e) => {
-                            let value = e.get();
-                            e.insert(value + 1);
-                        }
-                        Entry::Vacant(e) => {
-                            e.insert(0);
-                        }
-                    };
-                    acc
-                });
-
-            let total_bodies: u32 = body_types.values().sum();
-            let bars: HashMap<String, f64> = body_types
-                .into_iter()
-                .map(|(b, q)| (b, q as f64 / total_bodies as f64))
-                .collect();
-
-            for (name, ratio) in bars {
-                let hashes = (10.0 * ratio).round() as usize;
-                let mut bar = String::new();
-                bar.push_str(&"#".repeat(hashes));
-                bar.push_str(&" ".repeat(10 - hashes));
-                info!("{: >10}:[{}]", name, bar)
+    CREEP_TARGETS.with_borrow(save_creep_targets);
+
+    intershard::run_intershard(current_tick);
+
+    if current_tick % visuals::DASHBOARD_INTERVAL == 0 {
+        for room in owned_rooms() {
+            visuals::draw_dashboard(&room);
+        }
+    }
+
+    // a full bucket can't bank any more cpu, so trade it for a pixel instead
+    if game::cpu::bucket() >= PIXEL_CPU_COST as i32 && config::generate_pixels() {
+        match game::cpu::generate_pixel() {
+            Ok(()) => info!("generated a pixel, bucket: {}", game::cpu::bucket()),
+            Err(e) => warn!("couldn't generate a pixel: {:?}", e),
+        }
+    }
+
+    drop(tick_timer);
+
+    let cpu_used = game::cpu::get_used();
+    let stats = Stats::collect(cpu_used);
+    stats.export();
+
+    info!(
+        "done!\nloading_cpu: {:.2}\n engine_cpu: {:.2}\naverage_cpu: {:.2}",
+        starting_time,
+        cpu_used - starting_time,
+        stats.cpu_average()
+    );
+    timer::log_breakdown();
+}
+
+// everything a room we own runs for itself each tick. Creeps aren't part of it, they run
+// together afterwards wherever they happen to be
+fn run_room(room: &Room, config: &RoomConfig) {
+    if game::time() % 100 == 0 {
+        plan_room(room);
+    }
+
+    defense::run_defense(room);
+
+    for structure in find_cache::structures(room).iter() {
+        if let StructureObject::StructureTower(tower) = structure {
+            if tower.my() {
+                tower::run_tower(tower);
             }
+        }
+    }
+
+    links::run_links(room);
+
+    observer::run_observer(room, &config.remote_rooms);
+
+    run_spawns(room, config);
+}
+
+fn plan_room(room: &Room) {
+    let placed = planner::plan_source_containers(room);
+    if placed > 0 {
+        info!("placed {placed} container sites in {}", room.name());
+    }
+
+    let placed = planner::plan_extensions(room);
+    if placed > 0 {
+        info!("placed {placed} extension sites in {}", room.name());
+    }
 
-            info!("Current Creeps: {current_creeps} -- Energy Available: {energy_available}");
-
-            if let Some(body) = THRESHOLDS
-                .iter()
-                .skip_while(|(threshold, _, _)| &current_creeps > threshold)
-                .next()
-                .filter(|(_, cost, _)| cost <= energy_available)
-                .map(|(_, _, body)| body)
-            {
-                // create a unique name, spawn.
-                let name_base = game::time();
-                let name = format!("{}-{}", name_base, additional);
-                // TODO: handle pathfinding and caching manually
-                // note that this bot has a fatal flaw; spawning a creep
-                // creates Memory.creeps[creep_name] which will build up forever;
-                // these memory entries should be prevented (todo doc link on how) or cleaned up
-                //
-                // NOTE: to library author, this code isn't what adds entries to
-                // Memory.creeps[creep_name], it is actually the use of Creep.moveTo in the
-                // run_creep function
-                match spawn.spawn_creep(&body, &name) {
-                    Ok(()) => additional += 1,
-                    Err(e) => warn!("couldn't spawn: {:?}", e),
+    let placed = planner::plan_roads(room);
+    if placed > 0 {
+        info!("placed {placed} road sites in {}", room.name());
+    }
+
+    let placed = planner::plan_ramparts(room) + planner::plan_wall_lines(room);
+    if placed > 0 {
+        info!("placed {placed} rampart/wall sites in {}", room.name());
+    }
+}
+
+// the creeps in the room by role. Claimers, scouts and dismantlers spend their lives away
+// from the room that spawned them, so they count towards every room's queue wherever
+// they are, along with any another room has started spawning this tick. Otherwise every
+// room we own would answer the same flag
+fn live_roles(room_name: RoomName, now: u32) -> HashMap<Role, usize> {
+    let mut live: HashMap<Role, usize> = HashMap::new();
+    for creep in game::creeps().values() {
+        let role = Role::of_creep(&creep.name());
+        let here = creep.room().is_some_and(|room| room.name() == room_name);
+        if role.roams() || here {
+            *live.entry(role).or_default() += 1;
+        }
+    }
+    for (role, spawned) in spawning::spawned_roles(now) {
+        if role.roams() {
+            *live.entry(role).or_default() += spawned;
+        }
+    }
+    live
+}
+
+// every spawn in a room works off the one queue, each taking a different need, so
+// spawns sharing a room can't both fill the same slot in the same tick
+fn run_spawns(room: &Room, config: &RoomConfig) {
+    let room_name = room.name();
+    let _timer = TimerLog::new("spawns");
+
+    let mut spawns: Vec<StructureSpawn> = room.find(find::MY_SPAWNS, None);
+    // spawns still busy with a creep can't take anything on this tick
+    spawns.retain(|spawn| spawn.spawning().is_none());
+    spawns.sort_by_key(|spawn| String::from(spawn.name()));
+    let Some(first_spawn) = spawns.first() else {
+        return;
+    };
+    debug!("running {} idle spawns in {room_name}", spawns.len());
+
+    let now = game::time();
+    let energy_available = room.energy_available();
+    let energy_capacity = room.energy_capacity_available();
+    spawning::spawn_unstuck(first_spawn, energy_available);
+    let current_creeps = game::creeps().keys().count() + spawning::spawned_this_tick(now);
+
+    info!("Current Creeps: {current_creeps} -- Energy Available: {energy_available}");
+
+    // only the hard caps can hold back the emergency spawns below
+    let caps = CreepCaps::load();
+    let mut counts = caps::live_creeps(now);
+    let harvester_capped = caps
+        .blocked_by(
+            Role::Harvester,
+            current_creeps,
+            counts.get(&Role::Harvester).copied().unwrap_or(0),
+        )
+        .is_some();
+
+    // a room that's only just been claimed has nobody to get it going, so until it has a
+    // couple of creeps of its own it spawns workers out of whatever energy it has, down
+    // to the cheapest there is, whatever the thresholds or the bucket say
+    if room.find(find::MY_CREEPS, None).len() < spawning::BOOTSTRAP_WORKERS && !harvester_capped {
+        let body = build_body(Role::Harvester, energy_available);
+        if !body.is_empty() {
+            let name = spawning::creep_name(Role::Harvester, now);
+            match try_spawn(first_spawn, &body, &name) {
+                Ok(Some(name)) => {
+                    info!("bootstrapping {room_name}, spawning {name}");
+                    spawning::note_spawned(now, Role::Harvester);
                 }
+                Ok(None) => {}
+                Err(e) => log_spawn_error(first_spawn, e),
             }
         }
+        return;
     }
 
-    info!(
-        "done!\nloading_cpu: {:.2}\n engine_cpu: {:.2}",
-        starting_time,
-        game::cpu::get_used() - starting_time
-    )
+    // a room with nobody mining can never refill its spawn, so get a miner out the
+    // moment a minimal one is affordable, whatever the thresholds or the bucket say
+    let live = live_roles(room_name, now);
+    let count = |role| live.get(&role).copied().unwrap_or(0);
+    let stalled = count(Role::Harvester) == 0
+        && (count(Role::Miner) == 0 || count(Role::Hauler) == 0)
+        && !harvester_capped;
+    if stalled && energy_available >= config.emergency_spawn_energy {
+        renewal::spawn_needed(room_name, now);
+        let body = build_body(Role::Harvester, config.emergency_spawn_energy);
+        let name = spawning::creep_name(Role::Harvester, now);
+        match try_spawn(first_spawn, &body, &name) {
+            Ok(Some(name)) => {
+                warn!("emergency spawn: no harvesters in {room_name}, spawning {name}");
+                spawning::note_spawned(now, Role::Harvester);
+            }
+            Ok(None) => {}
+            Err(e) => log_spawn_error(first_spawn, e),
+        }
+        return;
+    }
+
+    // every spawn that goes ahead counts towards the cap for the next one
+    let creep_cap = config.creep_cap();
+    let bucket = game::cpu::bucket();
+    let allowed = (0..spawns.len())
+        .take_while(|&i| should_spawn(bucket, current_creeps + i, creep_cap))
+        .count();
+    if allowed == 0 {
+        if current_creeps < creep_cap {
+            info!(
+                "throttling spawns, bucket: {bucket} -- cpu limit: {}",
+                game::cpu::limit()
+            );
+        }
+        return;
+    }
+
+    let mut queue = SpawnQueue::for_room(room, &config.targets, &live);
+
+    // whatever the queue asks for, nothing goes past the hard caps
+    let mut total: usize = counts.values().sum();
+    queue.retain(|&role| {
+        let of_role = counts.entry(role).or_default();
+        match caps.blocked_by(role, total, *of_role) {
+            Some(cap) => {
+                info!(
+                    "{room_name} can't spawn another {}, it's at the {cap} creep cap",
+                    role.name()
+                );
+                false
+            }
+            None => {
+                *of_role += 1;
+                total += 1;
+                true
+            }
+        }
+    });
+
+    // anything left to spawn comes before renewing specialists
+    if queue.front().is_some() {
+        renewal::spawn_needed(room_name, now);
+    }
+
+    // once roads are going down, haulers are built for them
+    let roads = find_cache::structures(room)
+        .iter()
+        .any(|structure| matches!(structure, StructureObject::StructureRoad(_)));
+    let level = room.controller().map_or(0, |controller| controller.level());
+    let plan = SpawnPlan::new(
+        &config.spawn_tiers,
+        current_creeps,
+        &config.level_tiers,
+        level,
+        roads,
+    );
+    let mut assignments = queue.assign(allowed, energy_available, |role, available| {
+        plan.pick(role, energy_capacity, available)
+    });
+
+    // the front of the queue can't be paid for yet, so its energy is held for it
+    // rather than spent on anything smaller. If the room's income has stalled and it
+    // never fills up, the front settles for a body sized to what's there
+    if let Some(role) = queue.front().filter(|_| assignments.is_empty()) {
+        let cost = plan
+            .pick(role, energy_capacity, u32::MAX)
+            .map_or(0, |body| body.sum_parts());
+        let waited = spawning::reserve_spawn_energy(room_name, role, cost, now);
+
+        if spawning::spawn_stuck(first_spawn, now) {
+            // settling hasn't helped either, so stop counting on extensions that never
+            // seem to fill and size the body off the spawn's own store
+            let spawn_capacity = first_spawn.store().get_capacity(Some(ResourceType::Energy));
+            assignments = plan
+                .pick(role, spawn_capacity, energy_available)
+                .map(|body| vec![(role, body)])
+                .unwrap_or_default();
+        } else {
+            if spawning::hold_spawn_energy(energy_available, cost, waited) {
+                return;
+            }
+            assignments = queue.assign(allowed, energy_available, |role, available| {
+                plan.pick(role, energy_available, available)
+            });
+            if !assignments.is_empty() {
+                warn!(
+                    "{room_name} waited {waited} ticks on {cost} energy for a {}, settling for less",
+                    role.name()
+                );
+            }
+        }
+    }
+    if !assignments.is_empty() || queue.front().is_none() {
+        spawning::release_spawn_energy(room_name);
+    }
+
+    for (spawn, (role, body)) in spawns.iter().zip(assignments) {
+        // create a unique name, spawn.
+        let name = spawning::creep_name(role, now);
+        // TODO: handle pathfinding and caching manually
+        // note that this bot has a fatal flaw; spawning a creep
+        // creates Memory.creeps[creep_name] which will build up forever;
+        // these memory entries should be prevented (todo doc link on how) or cleaned up
+        //
+        // NOTE: to library author, this code isn't what adds entries to
+        // Memory.creeps[creep_name], it is actually the use of Creep.moveTo in the
+        // run_creep function
+        match try_spawn(spawn, &body, &name) {
+            Ok(Some(name)) => {
+                info!("spawning {name} from {}", String::from(spawn.name()));
+                spawning::note_spawned(now, role);
+            }
+            // whatever comes after this one is less important, so it waits too
+            Ok(None) => break,
+            Err(e) => {
+                log_spawn_error(spawn, e);
+                break;
+            }
+        }
+    }
+}
+
+// a rough bar chart of the body layouts currently alive
+fn log_body_histogram() {
+    for (name, ratio) in body_type_shares() {
+        info!("{: >10}:[{}]", name, text_bar(ratio))
+    }
+}
+
+// the share of living creeps with each body layout, keyed by the layout spelled out
+fn body_type_shares() -> HashMap<String, f64> {
+    let body_types = game::creeps()
+        .values()
+        .map(|c| c.body())
+        .map(|b| b.into_iter().map(|p| p.part()))
+        .map(|b| {
+            b.map(|p| match p {
+                Part::Move => "M",
+                Part::Work => "W",
+                Part::Carry => "C",
+                Part::Attack => "A",
+                Part::RangedAttack => "RA",
+                Part::Tough => "T",
+                Part::Heal => "H",
+                Part::Claim => "C",
+                _ => "?",
+            })
+            .join("")
+        })
+        .fold(HashMap::new(), |mut acc, key| {
+            match acc.entry(key) {
+                Entry::Occupied(mut e) => {
+                    let value = e.get();
+                    e.insert(value + 1);
+                }
+                Entry::Vacant(e) => {
+                    e.insert(0);
+                }
+            };
+            acc
+        });
+
+    let total_bodies: u32 = body_types.values().sum();
+    body_types
+        .into_iter()
+        .map(|(b, q)| (b, q as f64 / total_bodies as f64))
+        .collect()
+}
+
+// ten characters of `#` and space, filled in proportion to `ratio`
+fn text_bar(ratio: f64) -> String {
+    let hashes = (10.0 * ratio.clamp(0.0, 1.0)).round() as usize;
+    let mut bar = String::new();
+    bar.push_str(&"#".repeat(hashes));
+    bar.push_str(&" ".repeat(10 - hashes));
+    bar
 }
 
-fn run_creep(creep: &Creep, creep_targets: &mut HashMap<String, CreepTarget>) {
+// everything a creep with energy might spend it on, gathered in one pass over the
+// room's structures
+fn scan_sinks<'a>(creep: &Creep, structures: &'a [StructureObject]) -> Sinks<&'a StructureObject> {
+    let pos = creep.pos();
+    targeting::scan_sinks(structures.iter().map(|structure| {
+        let kind = match structure {
+            StructureObject::StructureController(_) => SinkKind::Controller,
+            StructureObject::StructureSpawn(_) => SinkKind::Spawn,
+            StructureObject::StructureExtension(_) => SinkKind::Extension,
+            StructureObject::StructureTower(_) => SinkKind::Tower,
+            _ => SinkKind::Other,
+        };
+        let store = match kind {
+            SinkKind::Spawn | SinkKind::Extension | SinkKind::Tower => structure.as_has_store(),
+            _ => None,
+        };
+        SinkCandidate {
+            item: structure,
+            id: structure.as_structure().id(),
+            kind,
+            range: pos.get_range_to(structure.pos()),
+            energy: store.map_or(0, |store| {
+                store.store().get_used_capacity(Some(ResourceType::Energy))
+            }),
+            free_energy: store.map_or(0, |store| {
+                store
+                    .store()
+                    .get_free_capacity(Some(ResourceType::Energy))
+                    .max(0) as u32
+            }),
+            repair: repair_threshold(structure).map(|threshold| {
+                (
+                    structure.as_structure().hits(),
+                    threshold,
+                    ticks_to_decay_death(structure),
+                )
+            }),
+        }
+    }))
+}
+
+// the parts of a room's snapshot that come out the same for every creep in it, so they're
+// only worked out for the first creep picking a target each tick
+#[derive(Clone, Copy)]
+struct SharedSnapshot {
+    under_attack: bool,
+    controller_container: Option<Position>,
+    parking: Option<Position>,
+}
+
+fn shared_snapshot(room: &Room) -> SharedSnapshot {
+    let now = game::time();
+    SHARED_SNAPSHOTS.with_borrow_mut(|(tick, snapshots)| {
+        if *tick != now {
+            *tick = now;
+            snapshots.clear();
+        }
+        *snapshots
+            .entry(room.name())
+            .or_insert_with(|| SharedSnapshot {
+                under_attack: defense::under_attack(room),
+                // only while it has room, after which haulers wait for it to empty
+                controller_container: structures::controller_container(room)
+                    .and_then(|id| id.resolve())
+                    .filter(|container| {
+                        container
+                            .store()
+                            .get_free_capacity(Some(ResourceType::Energy))
+                            > 0
+                    })
+                    .map(|container| container.pos()),
+                parking: parking::parking_spot(room),
+            })
+    })
+}
+
+// everything `targeting::choose_target` looks at, as seen by `creep`
+fn room_snapshot(
+    creep: &Creep,
+    room: &Room,
+    (building, upgrading): (usize, usize),
+) -> RoomSnapshot {
+    let structures = find_cache::structures(room);
+    let sinks = scan_sinks(creep, &structures);
+    let shared = shared_snapshot(room);
+    let id = |structure: &StructureObject| structure.as_structure().id();
+
+    RoomSnapshot {
+        controller: sinks.controller.and_then(|controller| match controller {
+            StructureObject::StructureController(controller) => Some(ControllerSnapshot {
+                id: controller.id(),
+                level: controller.level(),
+                ticks_to_downgrade: controller.ticks_to_downgrade(),
+            }),
+            _ => None,
+        }),
+        spawn: sinks.spawn.map(|spawn| id(spawn).into_type()),
+        extension: sinks.extension.map(|extension| id(extension).into_type()),
+        tower: sinks.tower.map(|tower| id(tower).into_type()),
+        emptiest_tower: sinks.emptiest_tower.map(|tower| id(tower).into_type()),
+        under_attack: shared.under_attack,
+        repair: sinks.repair.map(id),
+        closest_repair: sinks.closest_repair.map(id),
+        construction_site: construction::best_site(&find_cache::construction_sites(room))
+            .and_then(|site| site.try_id()),
+        storage: room
+            .storage()
+            .filter(|storage| {
+                storage
+                    .store()
+                    .get_free_capacity(Some(ResourceType::Energy))
+                    > 0
+            })
+            .map(|storage| storage.id()),
+        terminal: room
+            .terminal()
+            .filter(|terminal| {
+                terminal
+                    .store()
+                    .get_free_capacity(Some(ResourceType::Energy))
+                    > 0
+            })
+            .map(|terminal| terminal.id()),
+        controller_container: shared.controller_container,
+        parking: shared.parking,
+        building,
+        upgrading,
+        build_share: config::room_config(room.name()).build_share,
+    }
+}
+
+// hostiles in the room, leaving out any on the far side of our walls. Those are left to
+// the towers and ramparts rather than chased out through the gaps
+fn hostiles_inside(room: &Room) -> Vec<Creep> {
+    let hostiles = room.find(find::HOSTILE_CREEPS, None);
+    match perimeter::perimeter(room) {
+        Some(perimeter) => hostiles
+            .into_iter()
+            .filter(|hostile| perimeter.contains(hostile.pos().xy()))
+            .collect(),
+        None => hostiles,
+    }
+}
+
+// whether a hostile a combat creep was after has gone back out past the walls of the
+// room they're both in
+fn escaped_walls(creep: &Creep, hostile: &Creep) -> bool {
+    let role = Role::of_creep(&creep.name());
+    if !matches!(role, Role::RangedAttacker | Role::Defender) {
+        return false;
+    }
+    creep
+        .room()
+        .filter(|room| room.name() == hostile.pos().room_name())
+        .and_then(|room| perimeter::perimeter(&room))
+        .is_some_and(|perimeter| !perimeter.contains(hostile.pos().xy()))
+}
+
+// the next resource worth taking out of a store. Only energy is any use without a
+// storage to put the rest in
+fn lootable(store: &screeps::Store, has_storage: bool) -> Option<ResourceType> {
+    store.store_types().into_iter().find(|&resource| {
+        (has_storage || resource == ResourceType::Energy)
+            && store.get_used_capacity(Some(resource)) > 0
+    })
+}
+
+fn room_has_storage(creep: &Creep) -> bool {
+    creep.room().is_some_and(|room| room.storage().is_some())
+}
+
+// cpu held back from the creep loop for spawning, saving and everything else after it.
+// Running past the tick limit loses the whole tick, so creeps stop short of it
+const CREEP_CPU_RESERVE: f64 = 20.0;
+
+// how far an empty creep will go out of its way for dropped energy, a tombstone or a ruin
+const SCAVENGE_RANGE: u8 = 10;
+
+// creeps finishing a task next to a spawn get renewed once they drop below this
+const RENEW_THRESHOLD: u32 = 200;
+
+// renewing is much cheaper than building a replacement, so a creep that's wrapped up a
+// task right beside a spawn tops itself up before heading off again
+fn try_renew(creep: &Creep) {
+    if creep
+        .ticks_to_live()
+        .map_or(true, |ttl| ttl >= RENEW_THRESHOLD)
+    {
+        return;
+    }
+
+    // the game refuses to renew anything carrying claim parts
+    if creep.body().iter().any(|part| part.part() == Part::Claim) {
+        return;
+    }
+
+    if let Some(spawn) = creep.pos().find_in_range(find::MY_SPAWNS, 1).first() {
+        match spawn.renew_creep(creep) {
+            Ok(()) => {
+                debug!("renewing creep {}", creep.name());
+                boosts::boosts_lost(creep);
+            }
+            // the spawn is busy spawning or short on energy, try again next time around
+            Err(ErrorCode::Busy | ErrorCode::NotEnough) => {}
+            Err(e) => warn!("couldn't renew: {:?}", e),
+        }
+    }
+}
+
+fn run_creep(
+    creep: &Creep,
+    creep_targets: &mut HashMap<String, CreepTarget>,
+    work_splits: &mut HashMap<RoomName, (usize, usize)>,
+) {
     if creep.spawning() {
         return;
     }
     let name = creep.name();
     debug!("running creep {}", name);
+    let role = Role::of_creep(&name);
+
+    // keepers kill anything that isn't built to fight them, so get clear before anything
+    // else
+    if !matches!(role, Role::RangedAttacker | Role::Defender) && keepers::flee_keepers(creep) {
+        return;
+    }
+
+    // fighters that are badly hurt see to themselves rather than fight on
+    if matches!(role, Role::RangedAttacker | Role::Defender)
+        && creep
+            .room()
+            .is_some_and(|room| combat::look_after_self(creep, &config::room_config(room.name())))
+    {
+        return;
+    }
+
+    // a creep whose store just filled up or ran dry drops whatever it was doing for the
+    // old mode and picks something for the new one straight away
+    if role.gathers_energy() && working::update_working(creep) {
+        creep_targets.remove(&name);
+    }
+
+    // where it's switched on for their home room, haulers meeting on the way swap loads
+    // instead of both walking the whole trip, whichever room they meet in
+    if role == Role::Hauler
+        && spawning::home_room(creep).is_some_and(|home| config::room_config(home).bucket_brigade)
+        && hauling::hand_off(creep, creep_targets)
+    {
+        return;
+    }
+
+    // a creep that would die on its way back with whatever it's off to fetch gives up on
+    // it for something closer, or failing that spends what it's carrying already or goes
+    // to be recycled
+    if creep_targets
+        .get(&name)
+        .is_some_and(|target| !lifespan::can_finish(creep, target))
+    {
+        creep_targets.remove(&name);
+        if let Some(target) = lifespan::closer_errand(creep) {
+            creep_targets.insert(name.clone(), target);
+        } else if !working::work_with_what_it_has(creep) {
+            if let Some(target) = recycling::recycle_target(creep) {
+                info!("{name} won't live to finish fetching energy, recycling it");
+                creep_targets.insert(name.clone(), target);
+            }
+        }
+    }
+
+    // specialists worth keeping alive sit by a spawn being renewed once they're idle and
+    // getting on, rather than being left to die and replaced
+    if renewal::run_renewal(creep, creep_targets.get(&name)) {
+        creep_targets.remove(&name);
+        return;
+    }
+
+    // only needed when picking a new target, so skip the tally for creeps that have one
+    let (source_load, mined_sources, work_split) = if creep_targets.contains_key(&name) {
+        (HashMap::new(), HashSet::new(), (0, 0))
+    } else {
+        (
+            harvesters_per_source(creep_targets),
+            mined_sources(creep_targets),
+            creep.room().map_or((0, 0), |room| {
+                work_splits.get(&room.name()).copied().unwrap_or_default()
+            }),
+        )
+    };
 
     let target = creep_targets.entry(name);
     match target {
         Entry::Occupied(entry) => {
             let creep_target = entry.get();
+            status::announce_task(creep, creep_target);
             match creep_target {
                 CreepTarget::Upgrade(controller_id)
                     if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 =>
                 {
                     if let Some(controller) = controller_id.resolve() {
                         if creep.pos().in_range_to(controller.pos(), 3) {
-                            creep.upgrade_controller(&controller).unwrap_or_else(|e| {
-                                warn!("couldn't upgrade: {:?}", e);
-                                entry.remove();
-                            });
+                            intent("upgrade", creep.upgrade_controller(&controller))
+                                .unwrap_or_else(|e| {
+                                    warn!("couldn't upgrade: {:?}", e);
+                                    entry.remove();
+                                });
                         } else {
                             let _ = creep.default_move_to(&controller);
                         }
@@ -361,26 +1169,32 @@ fn run_creep(creep: &Creep, creep_targets: &mut HashMap<String, CreepTarget>) {
                         entry.remove();
                     }
                 }
-                CreepTarget::Harvest(source_id)
-                    if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 =>
-                {
+                CreepTarget::Harvest {
+                    source: source_id,
+                    pos,
+                } if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 => {
                     if let Some(source) = source_id.resolve() {
-                        if creep.pos().is_near_to(source.pos()) {
-                            creep.harvest(&source).unwrap_or_else(|e| {
+                        if !sources::is_mining_tile(&source, *pos) {
+                            // something's been built on the tile, so find another
+                            entry.remove();
+                        } else if creep.pos() == *pos {
+                            intent("harvest", creep.harvest(&source)).unwrap_or_else(|e| {
                                 warn!("couldn't harvest: {:?}", e);
                                 entry.remove();
                             });
                         } else {
-                            let _ = creep.default_move_to(&source);
+                            let _ = creep.default_move_to(pos);
                         }
                     } else {
                         entry.remove();
                     }
                 }
-                CreepTarget::Construct(source_id) => {
+                CreepTarget::Construct(source_id)
+                    if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 =>
+                {
                     if let Some(source) = source_id.resolve() {
                         if creep.pos().in_range_to(source.pos(), 3) {
-                            creep.build(&source).unwrap_or_else(|e| {
+                            intent("build", creep.build(&source)).unwrap_or_else(|e| {
                                 warn!("couldn't build: {:?}", e);
                                 entry.remove();
                             });
@@ -391,11 +1205,12 @@ fn run_creep(creep: &Creep, creep_targets: &mut HashMap<String, CreepTarget>) {
                         entry.remove();
                     }
                 }
-                CreepTarget::Store(source) => {
-                    if let Some(source) = source.resolve() {
+                CreepTarget::Store { target, resource }
+                    if creep.store().get_used_capacity(Some(*resource)) > 0 =>
+                {
+                    if let Some(source) = target.resolve() {
                         if creep.pos().is_near_to(source.pos()) {
-                            creep
-                                .transfer(&source, ResourceType::Energy, None)
+                            intent("transfer", creep.transfer(&source, *resource, None))
                                 .unwrap_or_else(|e| {
                                     warn!("couldn't transfer: {:?}", e);
                                     entry.remove();
@@ -410,7 +1225,7 @@ fn run_creep(creep: &Creep, creep_targets: &mut HashMap<String, CreepTarget>) {
                 CreepTarget::Repair(source) => {
                     if let Some(structure) = source.resolve() {
                         if creep.pos().in_range_to(structure.pos(), 3) {
-                            creep.repair(&structure).unwrap_or_else(|e| {
+                            intent("repair", creep.repair(&structure)).unwrap_or_else(|e| {
                                 warn!("couldn't repair: {:?}", e);
                             });
                             entry.remove();
@@ -419,6 +1234,278 @@ fn run_creep(creep: &Creep, creep_targets: &mut HashMap<String, CreepTarget>) {
                         }
                     }
                 }
+                CreepTarget::Pickup(resource_id)
+                    if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 =>
+                {
+                    if let Some(resource) = resource_id.resolve() {
+                        if creep.pos().is_near_to(resource.pos()) {
+                            intent("pickup", creep.pickup(&resource)).unwrap_or_else(|e| {
+                                warn!("couldn't pick up: {:?}", e);
+                            });
+                            entry.remove();
+                        } else {
+                            let _ = creep.default_move_to(&resource);
+                        }
+                    } else {
+                        entry.remove();
+                    }
+                }
+                CreepTarget::Loot(tombstone_id)
+                    if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 =>
+                {
+                    if let Some(tombstone) = tombstone_id.resolve() {
+                        if creep.pos().is_near_to(tombstone.pos()) {
+                            intent(
+                                "withdraw",
+                                creep.withdraw(&tombstone, ResourceType::Energy, None),
+                            )
+                            .unwrap_or_else(|e| {
+                                warn!("couldn't loot: {:?}", e);
+                            });
+                            entry.remove();
+                        } else {
+                            let _ = creep.default_move_to(&tombstone);
+                        }
+                    } else {
+                        entry.remove();
+                    }
+                }
+                CreepTarget::LootRuin(ruin_id) if creep.store().get_free_capacity(None) > 0 => {
+                    if let Some(ruin) = ruin_id.resolve() {
+                        if creep.pos().is_near_to(ruin.pos()) {
+                            match lootable(&ruin.store(), room_has_storage(creep)) {
+                                Some(resource) => {
+                                    match intent("withdraw", creep.withdraw(&ruin, resource, None))
+                                    {
+                                        Ok(()) => {}
+                                        Err(ErrorCode::NotEnough) => {
+                                            entry.remove();
+                                        }
+                                        Err(e) => {
+                                            warn!("couldn't loot ruin: {:?}", e);
+                                            entry.remove();
+                                        }
+                                    }
+                                }
+                                None => {
+                                    entry.remove();
+                                }
+                            }
+                        } else {
+                            let _ = creep.default_move_to(&ruin);
+                        }
+                    } else {
+                        entry.remove();
+                    }
+                }
+                CreepTarget::Attack(hostile_id) => {
+                    if let Some(hostile) = hostile_id
+                        .resolve()
+                        .filter(|hostile| !escaped_walls(creep, hostile))
+                    {
+                        if role == Role::RangedAttacker {
+                            combat::fight(creep, &hostile);
+                        } else if creep.pos().is_near_to(hostile.pos()) {
+                            intent("attack", creep.attack(&hostile)).unwrap_or_else(|e| {
+                                warn!("couldn't attack: {:?}", e);
+                                entry.remove();
+                            });
+                        } else {
+                            let _ = creep.default_move_to(&hostile);
+                        }
+                    } else {
+                        entry.remove();
+                    }
+                }
+                CreepTarget::AttackStructure(structure_id) => {
+                    if let Some(structure) = structure_id.resolve() {
+                        combat::siege(creep, structure);
+                    } else {
+                        entry.remove();
+                    }
+                }
+                CreepTarget::Dismantle(structure_id)
+                    if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 =>
+                {
+                    if let Some(structure) = structure_id.resolve() {
+                        if creep.pos().is_near_to(structure.pos()) {
+                            match StructureObject::from(structure).as_dismantleable() {
+                                Some(dismantleable) => {
+                                    intent("dismantle", creep.dismantle(dismantleable))
+                                        .unwrap_or_else(|e| {
+                                            warn!("couldn't dismantle: {:?}", e);
+                                            entry.remove();
+                                        })
+                                }
+                                None => {
+                                    entry.remove();
+                                }
+                            }
+                        } else {
+                            let _ = creep.default_move_to(&structure);
+                        }
+                    } else {
+                        entry.remove();
+                    }
+                }
+                CreepTarget::Drop(pos)
+                    if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 =>
+                {
+                    if creep.pos() == *pos {
+                        // dropped on a container's tile, it goes straight into the
+                        // container for as long as that has room
+                        intent("drop", creep.drop(ResourceType::Energy, None))
+                            .unwrap_or_else(|e| warn!("couldn't drop energy: {:?}", e));
+                        entry.remove();
+                    } else if creep.pos().is_near_to(*pos) {
+                        // a static upgrader parked on the tile keeps everyone else off it,
+                        // so the energy's handed straight to the container instead. Left
+                        // on the ground beside it, it would only rot
+                        let container = pos.look_for(look::STRUCTURES).ok().and_then(|found| {
+                            found.into_iter().find_map(|structure| match structure {
+                                StructureObject::StructureContainer(container) => Some(container),
+                                _ => None,
+                            })
+                        });
+                        let occupied = pos
+                            .look_for(look::CREEPS)
+                            .is_ok_and(|creeps| !creeps.is_empty());
+                        match container {
+                            Some(_) if !occupied => {
+                                let _ = creep.default_move_to(pos);
+                            }
+                            Some(container) => {
+                                intent(
+                                    "transfer",
+                                    creep.transfer(&container, ResourceType::Energy, None),
+                                )
+                                .unwrap_or_else(|e| warn!("couldn't transfer: {:?}", e));
+                                entry.remove();
+                            }
+                            // gone since the target was picked
+                            None => {
+                                entry.remove();
+                            }
+                        }
+                    } else {
+                        let _ = creep.default_move_to(pos);
+                    }
+                }
+                CreepTarget::Reserve(controller_id) => {
+                    if let Some(controller) = controller_id.resolve() {
+                        if creep.pos().is_near_to(controller.pos()) {
+                            intent("reserve", creep.reserve_controller(&controller))
+                                .unwrap_or_else(|e| {
+                                    warn!("couldn't reserve: {:?}", e);
+                                });
+                            entry.remove();
+                        } else {
+                            let _ = creep.default_move_to(&controller);
+                        }
+                    } else {
+                        entry.remove();
+                    }
+                }
+                CreepTarget::Claim(controller_id) => {
+                    if let Some(controller) = controller_id.resolve() {
+                        if creep.pos().is_near_to(controller.pos()) {
+                            match intent("claim", creep.claim_controller(&controller)) {
+                                Ok(()) => info!("{} claimed {}", creep.name(), controller.id()),
+                                Err(e) => warn!("couldn't claim: {:?}", e),
+                            }
+                            entry.remove();
+                        } else {
+                            let _ = creep.default_move_to(&controller);
+                        }
+                    } else {
+                        entry.remove();
+                    }
+                }
+                CreepTarget::Mine { source, pos } => {
+                    if let Some(source) = source.resolve() {
+                        if creep.pos() == *pos {
+                            // no carry parts, so everything harvested drops straight into
+                            // the container underneath, or onto the ground without one
+                            match intent("harvest", creep.harvest(&source)) {
+                                Ok(()) | Err(ErrorCode::NotEnough) => {}
+                                Err(e) => warn!("couldn't mine: {:?}", e),
+                            }
+                        } else {
+                            // miners make the same trip every time, so keep the path
+                            let _ = movement::move_to_cached(creep, pos, 0);
+                        }
+                    } else {
+                        entry.remove();
+                    }
+                }
+                CreepTarget::Withdraw(container_id)
+                    if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 =>
+                {
+                    if let Some(container) = container_id.resolve() {
+                        if creep.pos().is_near_to(container.pos()) {
+                            intent(
+                                "withdraw",
+                                creep.withdraw(&container, ResourceType::Energy, None),
+                            )
+                            .unwrap_or_else(|e| {
+                                warn!("couldn't withdraw: {:?}", e);
+                            });
+                            entry.remove();
+                        } else {
+                            let _ = creep.default_move_to(&container);
+                        }
+                    } else {
+                        entry.remove();
+                    }
+                }
+                CreepTarget::WithdrawStorage(storage_id)
+                    if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0 =>
+                {
+                    if let Some(storage) = storage_id.resolve() {
+                        if creep.pos().is_near_to(storage.pos()) {
+                            intent(
+                                "withdraw",
+                                creep.withdraw(&storage, ResourceType::Energy, None),
+                            )
+                            .unwrap_or_else(|e| {
+                                warn!("couldn't withdraw from storage: {:?}", e);
+                            });
+                            entry.remove();
+                        } else {
+                            let _ = creep.default_move_to(&storage);
+                        }
+                    } else {
+                        entry.remove();
+                    }
+                }
+                CreepTarget::Recycle(spawn_id) => {
+                    let give_up = match spawn_id.resolve() {
+                        Some(spawn) => recycling::recycle(creep, &spawn),
+                        None => true,
+                    };
+                    if give_up {
+                        entry.remove();
+                    }
+                }
+                CreepTarget::Boost { lab, compound } => {
+                    let done = match lab.resolve() {
+                        Some(lab) => boosts::apply_boost(creep, &lab, *compound),
+                        None => true,
+                    };
+                    if done {
+                        entry.remove();
+                    }
+                }
+                CreepTarget::Idle(spot) => {
+                    // the whole room's idle creeps share the one spot, so anywhere near it
+                    // counts as parked. Once there, it looks for real work again every
+                    // other tick
+                    if creep.pos().in_range_to(*spot, parking::PARKING_SLACK) {
+                        entry.remove();
+                    } else {
+                        let _ = creep.default_move_to(spot);
+                    }
+                }
                 _ => {
                     entry.remove();
                 }
@@ -426,126 +1513,376 @@ fn run_creep(creep: &Creep, creep_targets: &mut HashMap<String, CreepTarget>) {
         }
         Entry::Vacant(entry) => {
             // no target, let's find one depending on if we have energy
-            let room = creep.room().expect("couldn't resolve creep room");
-            'temp: {
-                if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 {
-                    let all_structures = room.find(find::STRUCTURES, None);
-
-                    // if controller needs a timer reset, fill it
-                    for structure in all_structures.iter() {
-                        if let StructureObject::StructureController(controller) = structure {
-                            let time_to_downgrade = match controller.level() {
-                                1 => 20_000,
-                                2 => 10_000,
-                                3 => 20_000,
-                                4 => 40_000,
-                                5 => 80_000,
-                                6 => 120_000,
-                                7 => 150_000,
-                                8 => 200_000,
-                                _ => 20_000,
-                            };
-                            if controller.ticks_to_downgrade() < time_to_downgrade - 5000 {
-                                entry.insert(CreepTarget::Upgrade(controller.id()));
-                                break 'temp;
-                            }
+            let Some(room) = creep.room() else {
+                warn!("couldn't resolve room for creep {}", creep.name());
+                return;
+            };
+
+            try_renew(creep);
+
+            // new creeps pick up whatever boosts the labs have for them before anything else
+            if let Some(target) = boosts::boost_target(creep, role) {
+                entry.insert(target);
+                return;
+            }
+
+            match role {
+                Role::Claimer => {
+                    if let Some(target) = flags::claim_flag_target(creep) {
+                        entry.insert(target);
+                    }
+                    return;
+                }
+                Role::Scout => {
+                    intel::run_scout(creep);
+                    return;
+                }
+                Role::StaticUpgrader => {
+                    upgrading::run_static_upgrader(creep);
+                    return;
+                }
+                Role::RangedAttacker => {
+                    let hostiles = hostiles_inside(&room);
+                    if let Some(hostile) = combat::select_ranged_target(creep, &hostiles) {
+                        if let Some(id) = hostile.try_id() {
+                            entry.insert(CreepTarget::Attack(id));
                         }
+                    } else if let Some(target) = flags::clear_flag_target(creep) {
+                        entry.insert(target);
                     }
-
-                    // fill spawners
-                    for structure in all_structures.iter() {
-                        if let StructureObject::StructureSpawn(spawn) = structure {
-                            if spawn.store().get_free_capacity(Some(ResourceType::Energy)) > 0 {
-                                entry.insert(CreepTarget::Store(StoreTarget::Spawn(spawn.id())));
-                                break 'temp;
-                            }
+                    return;
+                }
+                Role::Defender => {
+                    // go for whatever's closest. With the room quiet, help clear any
+                    // flagged hostile structures, otherwise wait behind the walls for the
+                    // next one
+                    if let Some(id) = hostiles_inside(&room)
+                        .iter()
+                        .min_by_key(|hostile| creep.pos().get_range_to(hostile.pos()))
+                        .and_then(|hostile| hostile.try_id())
+                    {
+                        entry.insert(CreepTarget::Attack(id));
+                    } else if flags::clear_flags().next().is_some() {
+                        if let Some(target) = flags::clear_flag_target(creep) {
+                            entry.insert(target);
+                        }
+                    } else if let Some(spot) =
+                        perimeter::rally_point(&room).or_else(|| parking::parking_spot(&room))
+                    {
+                        entry.insert(CreepTarget::Idle(spot));
+                    }
+                    return;
+                }
+                // dismantlers gather by taking flagged structures apart, then spend the
+                // energy that gets them like any worker would, below
+                // a flag in a room out of sight has the dismantler walking there with no
+                // target yet, which it keeps at rather than being parked in between
+                Role::Dismantler if !working::is_working(creep) => {
+                    if flags::dismantle_flags().next().is_some() {
+                        if let Some(target) = flags::dismantle_flag_target(creep) {
+                            entry.insert(target);
+                        }
+                    } else if flags::clear_flags().next().is_some() {
+                        if let Some(target) = flags::clear_flag_target(creep) {
+                            entry.insert(target);
+                        }
+                    } else if !working::work_with_what_it_has(creep) {
+                        if let Some(spot) = parking::parking_spot(&room) {
+                            entry.insert(CreepTarget::Idle(spot));
                         }
                     }
+                    return;
+                }
+                Role::Miner => {
+                    // the first source no other miner has taken, standing on its container
+                    // if it has one. Without one the miner takes any free tile next to it
+                    // and its energy drops on the ground for the haulers
+                    let structures = find_cache::structures(&room);
+                    let post = room
+                        .find(find::SOURCES, None)
+                        .into_iter()
+                        .filter(|source| !mined_sources.contains(&source.id()))
+                        .find_map(|source| {
+                            let pos = match source_container(&source, &structures) {
+                                Some(container) => container.pos(),
+                                None => sources::reserve_harvest_spot(&source, creep)?,
+                            };
+                            Some((source, pos))
+                        });
+                    if let Some((source, pos)) = post {
+                        entry.insert(CreepTarget::Mine {
+                            source: source.id(),
+                            pos,
+                        });
+                    }
+                    return;
+                }
+                _ => {}
+            }
 
-                    // fill extensions
-                    for structure in all_structures.iter() {
-                        if let StructureObject::StructureExtension(extension) = structure {
-                            if extension
+            'temp: {
+                // anything other than energy picked up while looting goes into storage
+                if let Some(resource) = creep
+                    .store()
+                    .store_types()
+                    .into_iter()
+                    .find(|&resource| resource != ResourceType::Energy)
+                {
+                    if let Some(storage) = room.storage() {
+                        entry.insert(CreepTarget::Store {
+                            target: StoreTarget::Storage(storage.id()),
+                            resource,
+                        });
+                        break 'temp;
+                    }
+                }
+
+                let energy = creep.store().get_used_capacity(Some(ResourceType::Energy));
+                if energy > 0 && working::is_working(creep) {
+                    let snapshot = room_snapshot(creep, &room, work_split);
+                    if let Some(target) =
+                        targeting::choose_target(&snapshot, &CreepSnapshot { role, energy })
+                    {
+                        // so the next creep to pick in the room counts this one
+                        let (building, upgrading) = work_splits.entry(room.name()).or_default();
+                        match target {
+                            CreepTarget::Construct(_) => *building += 1,
+                            CreepTarget::Upgrade(_) => *upgrading += 1,
+                            _ => {}
+                        }
+                        entry.insert(target);
+                    }
+                } else {
+                    // scoop up energy lying around nearby before mining any more of it,
+                    // going for whichever pile is biggest
+                    let dropped = creep
+                        .pos()
+                        .find_in_range(find::DROPPED_RESOURCES, SCAVENGE_RANGE)
+                        .into_iter()
+                        .filter(|resource| resource.resource_type() == ResourceType::Energy)
+                        .max_by_key(|resource| resource.amount());
+                    let tombstone = creep
+                        .pos()
+                        .find_in_range(find::TOMBSTONES, SCAVENGE_RANGE)
+                        .into_iter()
+                        .map(|tombstone| {
+                            let energy = tombstone
                                 .store()
-                                .get_free_capacity(Some(ResourceType::Energy))
-                                > 0
-                            {
-                                entry.insert(CreepTarget::Store(StoreTarget::Extension(
-                                    extension.id(),
-                                )));
-                                break 'temp;
-                            }
+                                .get_used_capacity(Some(ResourceType::Energy));
+                            (tombstone, energy)
+                        })
+                        .filter(|(_, energy)| *energy > 0)
+                        .max_by_key(|(_, energy)| *energy);
+
+                    match (dropped, tombstone) {
+                        (Some(resource), Some((_, energy))) if resource.amount() >= energy => {
+                            entry.insert(CreepTarget::Pickup(resource.id()));
+                            break 'temp;
+                        }
+                        (_, Some((tombstone, _))) => {
+                            entry.insert(CreepTarget::Loot(tombstone.id()));
+                            break 'temp;
                         }
+                        (Some(resource), None) => {
+                            entry.insert(CreepTarget::Pickup(resource.id()));
+                            break 'temp;
+                        }
+                        (None, None) => {}
                     }
 
-                    for structure in all_structures.iter() {
-                        if let StructureObject::StructureTower(tower) = structure {
-                            if tower.store().get_free_capacity(Some(ResourceType::Energy)) > 0 {
-                                entry.insert(CreepTarget::Store(StoreTarget::Tower(tower.id())));
-                                break 'temp;
-                            }
+                    // ruins left behind by dead structures are worth emptying too
+                    let has_storage = room.storage().is_some();
+                    if let Some(ruin) = creep
+                        .pos()
+                        .find_in_range(find::RUINS, SCAVENGE_RANGE)
+                        .into_iter()
+                        .filter(|ruin| lootable(&ruin.store(), has_storage).is_some())
+                        .max_by_key(|ruin| ruin.store().get_used_capacity(None))
+                    {
+                        entry.insert(CreepTarget::LootRuin(ruin.id()));
+                        break 'temp;
+                    }
+
+                    // with plenty in storage, haulers feed the room straight out of it
+                    if role == Role::Hauler
+                        && storage::storage_mode(&room) == Some(StorageMode::Drain)
+                    {
+                        let all_structures = find_cache::structures(&room);
+                        let sinks = scan_sinks(creep, &all_structures);
+                        let hungry = sinks.spawn.is_some()
+                            || sinks.extension.is_some()
+                            || sinks.tower.is_some();
+                        if let Some(storage) = room.storage().filter(|storage| {
+                            hungry
+                                && storage
+                                    .store()
+                                    .get_used_capacity(Some(ResourceType::Energy))
+                                    > 0
+                        }) {
+                            entry.insert(CreepTarget::WithdrawStorage(storage.id()));
+                            break 'temp;
                         }
                     }
 
-                    for structure in all_structures.iter() {
-                        if let StructureObject::StructureRoad(road) = structure {
-                            info!("checking for terrain");
-                            if let Ok(Some(terrain)) = road
-                                .pos()
-                                .look_for(screeps::look::TERRAIN)
-                                .map(|l| l.into_iter().take(1).last())
-                            {
-                                let threshold = match terrain {
-                                    Terrain::Plain => 5_000,
-                                    Terrain::Swamp => 25_000,
-                                    Terrain::Wall => 750_000,
-                                };
-                                let threshold = threshold * 8 / 10;
-                                info!("threshold: {threshold}");
-
-                                if road.hits() < threshold {
-                                    let structure: &Structure = road.as_ref();
-                                    entry.insert(CreepTarget::Repair(structure.id()));
-                                    break 'temp;
-                                }
-                            }
+                    // haulers don't mine, they keep emptying the source container they've
+                    // been given, or pick up after the drop miners at sources without one
+                    if role == Role::Hauler {
+                        let structures = find_cache::structures(&room);
+                        let containers: Vec<StructureContainer> = room
+                            .find(find::SOURCES, None)
+                            .iter()
+                            .filter_map(|source| source_container(source, &structures))
+                            .collect();
+                        if let Some(container) =
+                            hauling::home_container(creep, &containers).filter(|container| {
+                                container
+                                    .store()
+                                    .get_used_capacity(Some(ResourceType::Energy))
+                                    > 0
+                            })
+                        {
+                            entry.insert(CreepTarget::Withdraw(container.id()));
+                        } else if let Some(pile) = sources::dropped_at_sources(&room) {
+                            entry.insert(CreepTarget::Pickup(pile.id()));
+                        } else {
+                            working::work_with_what_it_has(creep);
                         }
+                        break 'temp;
                     }
 
-                    // build things
-                    for site in room.find(find::CONSTRUCTION_SITES, None).iter() {
-                        if let Some(id) = site.try_id() {
-                            entry.insert(CreepTarget::Construct(id));
+                    let active_sources = find_cache::sources_active(&room);
+
+                    if active_sources.is_empty() {
+                        // every source is tapped out until it regenerates, so get out of
+                        // the way instead of wandering around
+                        if working::work_with_what_it_has(creep) {
                             break 'temp;
                         }
+                        if let Some(spot) = parking::parking_spot(&room) {
+                            entry.insert(CreepTarget::Idle(spot));
+                        }
+                        break 'temp;
                     }
 
-                    // default case, upgrade controller
-                    for structure in all_structures.iter() {
-                        if let StructureObject::StructureController(controller) = structure {
-                            entry.insert(CreepTarget::Upgrade(controller.id()));
+                    // only consider sources with a free mining spot left
+                    let sources: Vec<Source> = active_sources
+                        .iter()
+                        .filter(|source| sources::has_free_harvest_spot(source))
+                        .cloned()
+                        .collect();
+
+                    if sources.is_empty() {
+                        // every source is saturated, park rather than clumping up around
+                        // a source
+                        if working::work_with_what_it_has(creep) {
                             break 'temp;
                         }
+                        if let Some(spot) = parking::parking_spot(&room) {
+                            entry.insert(CreepTarget::Idle(spot));
+                        }
+                        break 'temp;
                     }
-                } else {
-                    let sources = room.find(find::SOURCES_ACTIVE, None).clone();
 
-                    let random_in_range: usize = RNG.with_borrow_mut({
-                        let max = sources.len();
-                        move |rng| {
-                            let mut gen = move || rng.gen_range(0..max);
-                            [gen(), gen()].into_iter().max().unwrap()
-                        }
+                    // go to whichever source has the fewest creeps mining it, so load stays
+                    // even across sources instead of drifting towards whichever one find
+                    // happens to list first (or last)
+                    let source = RNG.with_borrow_mut(|rng| {
+                        least_loaded(
+                            &sources,
+                            |source| source_load.get(&source.id()).copied().unwrap_or(0),
+                            rng,
+                        )
                     });
-                    info!("random value: {random_in_range}");
 
-                    let random_source = sources.get(random_in_range);
-
-                    if let Some(source) = random_source {
-                        entry.insert(CreepTarget::Harvest(source.id()));
+                    if let Some(source) = source {
+                        if let Some(pos) = sources::reserve_harvest_spot(source, creep) {
+                            entry.insert(CreepTarget::Harvest {
+                                source: source.id(),
+                                pos,
+                            });
+                        }
                     }
                 }
             }
         }
     }
 }
+
+fn mined_sources(creep_targets: &HashMap<String, CreepTarget>) -> HashSet<ObjectId<Source>> {
+    creep_targets
+        .values()
+        .filter_map(|target| match target {
+            CreepTarget::Mine { source, .. } => Some(*source),
+            _ => None,
+        })
+        .collect()
+}
+
+// how many creeps are building in each room, and how many upgrading its controller. Worked
+// out once a tick, with run_creep counting the targets it hands out after that
+fn work_splits(creep_targets: &HashMap<String, CreepTarget>) -> HashMap<RoomName, (usize, usize)> {
+    let sites: HashMap<ObjectId<ConstructionSite>, RoomName> = game::construction_sites()
+        .values()
+        .filter_map(|site| Some((site.try_id()?, site.pos().room_name())))
+        .collect();
+    let controllers: HashMap<ObjectId<StructureController>, RoomName> = game::rooms()
+        .values()
+        .filter_map(|room| Some((room.controller()?.id(), room.name())))
+        .collect();
+
+    let mut splits: HashMap<RoomName, (usize, usize)> = HashMap::new();
+    for target in creep_targets.values() {
+        match target {
+            CreepTarget::Construct(id) => {
+                if let Some(&room_name) = sites.get(id) {
+                    splits.entry(room_name).or_default().0 += 1;
+                }
+            }
+            CreepTarget::Upgrade(id) => {
+                if let Some(&room_name) = controllers.get(id) {
+                    splits.entry(room_name).or_default().1 += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    splits
+}
+
+fn harvesters_per_source(
+    creep_targets: &HashMap<String, CreepTarget>,
+) -> HashMap<ObjectId<Source>, usize> {
+    creep_targets
+        .values()
+        .filter_map(|target| match target {
+            CreepTarget::Harvest { source, .. } => Some(*source),
+            _ => None,
+        })
+        .counts()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_store_targets_load_as_energy() {
+        let old = r#"{"Store":{"Spawn":"000000000000000000000002"}}"#;
+        assert!(serde_json::from_str::<CreepTarget>(old).is_err());
+
+        let target = serde_json::from_str::<LegacyTarget>(old).unwrap().upgrade();
+        let spawn = "000000000000000000000002".parse().unwrap();
+        assert_eq!(
+            target,
+            Some(CreepTarget::store_energy(StoreTarget::Spawn(spawn)))
+        );
+    }
+
+    #[test]
+    fn old_harvest_targets_are_dropped() {
+        let old = r#"{"Harvest":"000000000000000000000003"}"#;
+        assert!(serde_json::from_str::<CreepTarget>(old).is_err());
+        let legacy = serde_json::from_str::<LegacyTarget>(old).unwrap();
+        assert_eq!(legacy.upgrade(), None);
+    }
+}
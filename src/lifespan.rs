@@ -0,0 +1,139 @@
+use screeps::{
+    constants::{Part, ResourceType, HARVEST_POWER},
+    enums::StructureObject,
+    find,
+    local::Position,
+    objects::Creep,
+    prelude::*,
+};
+
+use crate::{find_cache, CreepTarget};
+
+// paths wind around walls and swamps, so they tend to run this much longer than the
+// straight range
+const PATH_STRETCH: f64 = 1.5;
+// nothing takes longer than this, so creeps with more life left don't need checking
+const LATE_LIFE_TICKS: u32 = 300;
+
+// a rough count of the ticks it takes to walk `range` tiles out to a task, spend
+// `work_ticks` on it and walk about as far back again with whatever it got
+pub fn task_ticks(range: u32, work_ticks: u32) -> u32 {
+    (2.0 * range as f64 * PATH_STRETCH).ceil() as u32 + work_ticks
+}
+
+// whether a creep with `ticks_to_live` left would see such a task through
+pub fn outlives_task(ticks_to_live: u32, range: u32, work_ticks: u32) -> bool {
+    ticks_to_live >= task_ticks(range, work_ticks)
+}
+
+// whether the creep will live long enough to bring back what it's setting out to get.
+// Only fetching energy is checked, since anything else the creep is already carrying
+// where it's needed
+pub fn can_finish(creep: &Creep, target: &CreepTarget) -> bool {
+    let Some(ticks_to_live) = creep
+        .ticks_to_live()
+        .filter(|&ticks| ticks < LATE_LIFE_TICKS)
+    else {
+        return true;
+    };
+
+    let errand: Option<(Position, u32)> = match target {
+        CreepTarget::Harvest { source, pos } => source.resolve().map(|_| {
+            let per_tick = creep.get_active_bodyparts(Part::Work) as u32 * HARVEST_POWER;
+            let free = creep.store().get_free_capacity(Some(ResourceType::Energy)) as u32;
+            (*pos, free.div_ceil(per_tick.max(1)))
+        }),
+        CreepTarget::Withdraw(id) => id.resolve().map(|container| (container.pos(), 1)),
+        CreepTarget::WithdrawStorage(id) => id.resolve().map(|storage| (storage.pos(), 1)),
+        CreepTarget::Pickup(id) => id.resolve().map(|resource| (resource.pos(), 1)),
+        CreepTarget::Loot(id) => id.resolve().map(|tombstone| (tombstone.pos(), 1)),
+        CreepTarget::LootRuin(id) => id.resolve().map(|ruin| (ruin.pos(), 1)),
+        _ => None,
+    };
+
+    errand.map_or(true, |(pos, work_ticks)| {
+        outlives_task(ticks_to_live, creep.pos().get_range_to(pos), work_ticks)
+    })
+}
+
+// the closest of `candidates`, given as (range, work ticks, errand), that a creep with
+// `ticks_to_live` left would still see through
+pub fn closest_errand<T>(ticks_to_live: u32, candidates: Vec<(u32, u32, T)>) -> Option<T> {
+    candidates
+        .into_iter()
+        .filter(|&(range, work_ticks, _)| outlives_task(ticks_to_live, range, work_ticks))
+        .min_by_key(|&(range, _, _)| range)
+        .map(|(_, _, errand)| errand)
+}
+
+// something closer for a creep that won't live to finish fetching energy where it was
+// headed: energy on the ground or in a container near enough to get back with
+pub fn closer_errand(creep: &Creep) -> Option<CreepTarget> {
+    let ticks_to_live = creep.ticks_to_live()?;
+    let room = creep.room()?;
+    let pos = creep.pos();
+
+    let dropped = room
+        .find(find::DROPPED_RESOURCES, None)
+        .into_iter()
+        .filter(|resource| resource.resource_type() == ResourceType::Energy)
+        .filter_map(|resource| {
+            let id = resource.try_id()?;
+            Some((pos.get_range_to(resource.pos()), 1, CreepTarget::Pickup(id)))
+        });
+    let containers = find_cache::structures(&room)
+        .iter()
+        .filter_map(|structure| match structure {
+            StructureObject::StructureContainer(container)
+                if container
+                    .store()
+                    .get_used_capacity(Some(ResourceType::Energy))
+                    > 0 =>
+            {
+                Some((
+                    pos.get_range_to(container.pos()),
+                    1,
+                    CreepTarget::Withdraw(container.id()),
+                ))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    closest_errand(ticks_to_live, dropped.chain(containers).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn task_ticks_cover_the_round_trip() {
+        assert_eq!(task_ticks(0, 0), 0);
+        assert_eq!(task_ticks(0, 5), 5);
+        // 10 out and 10 back, stretched by half again
+        assert_eq!(task_ticks(10, 0), 30);
+        assert_eq!(task_ticks(10, 4), 34);
+        // rounded up, so a creep is never sent off a tick short
+        assert_eq!(task_ticks(1, 0), 3);
+    }
+
+    #[test]
+    fn outlives_task_at_the_cutoff() {
+        assert!(outlives_task(34, 10, 4));
+        assert!(!outlives_task(33, 10, 4));
+        assert!(outlives_task(0, 0, 0));
+    }
+
+    #[test]
+    fn closest_errand_skips_what_it_would_die_on() {
+        let candidates = vec![(20, 1, "far"), (5, 1, "near"), (8, 1, "middle")];
+        assert_eq!(closest_errand(100, candidates.clone()), Some("near"));
+        // 5 tiles out and back is 15 ticks, plus one to pick it up
+        assert_eq!(closest_errand(16, candidates.clone()), Some("near"));
+        assert_eq!(closest_errand(15, candidates), None);
+
+        let slow_near = vec![(2, 50, "near"), (6, 1, "far")];
+        assert_eq!(closest_errand(30, slow_near), Some("far"));
+    }
+}
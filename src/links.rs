@@ -0,0 +1,123 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use log::*;
+use screeps::{
+    constants::ResourceType,
+    enums::StructureObject,
+    find,
+    local::{ObjectId, RoomName},
+    objects::{Room, StructureLink},
+    prelude::*,
+};
+
+// links this close to a source get filled by miners and only ever send
+const SOURCE_LINK_RANGE: u32 = 2;
+// links this close to the controller or storage only ever receive
+const SINK_LINK_RANGE: u32 = 3;
+// a source link waits until it holds this much before sending, so each transfer (and
+// the cooldown it costs) moves a worthwhile amount
+const SEND_THRESHOLD: u32 = 600;
+
+#[derive(Clone, Default)]
+struct LinkNetwork {
+    link_count: usize,
+    sources: Vec<ObjectId<StructureLink>>,
+    sinks: Vec<ObjectId<StructureLink>>,
+}
+
+thread_local! {
+    // links don't move, so classifying them only needs redoing when one is built or lost
+    static LINK_NETWORKS: RefCell<HashMap<RoomName, LinkNetwork>> = RefCell::new(HashMap::new());
+}
+
+pub fn run_links(room: &Room) {
+    let links: Vec<StructureLink> = room
+        .find(find::MY_STRUCTURES, None)
+        .into_iter()
+        .filter_map(|structure| match structure {
+            StructureObject::StructureLink(link) => Some(link),
+            _ => None,
+        })
+        .collect();
+
+    if links.len() < 2 {
+        return;
+    }
+
+    let network = LINK_NETWORKS.with_borrow_mut(|networks| {
+        let network = networks.entry(room.name()).or_default();
+        if network.link_count != links.len() {
+            *network = classify_links(room, &links);
+        }
+        network.clone()
+    });
+
+    let mut sinks: Vec<(StructureLink, u32)> = network
+        .sinks
+        .iter()
+        .filter_map(|id| id.resolve())
+        .map(|link| {
+            let free = link.store().get_free_capacity(Some(ResourceType::Energy)) as u32;
+            (link, free)
+        })
+        .collect();
+
+    for source in network.sources.iter().filter_map(|id| id.resolve()) {
+        let energy = source.store().get_used_capacity(Some(ResourceType::Energy));
+        if source.cooldown() > 0 || energy < SEND_THRESHOLD {
+            continue;
+        }
+
+        let Some((sink, free)) = sinks.iter_mut().max_by_key(|(_, free)| *free) else {
+            break;
+        };
+        if *free == 0 {
+            break;
+        }
+
+        let amount = energy.min(*free);
+        match source.transfer_energy(sink, Some(amount)) {
+            Ok(()) => {
+                info!(
+                    "link {} sent {amount} energy to link {}",
+                    source.id(),
+                    sink.id()
+                );
+                *free -= amount;
+            }
+            Err(e) => warn!("couldn't transfer link energy: {:?}", e),
+        }
+    }
+}
+
+fn classify_links(room: &Room, links: &[StructureLink]) -> LinkNetwork {
+    let sources = room.find(find::SOURCES, None);
+    let hubs: Vec<_> = room
+        .controller()
+        .map(|controller| controller.pos())
+        .into_iter()
+        .chain(room.storage().map(|storage| storage.pos()))
+        .collect();
+
+    let mut network = LinkNetwork {
+        link_count: links.len(),
+        ..Default::default()
+    };
+
+    for link in links {
+        if sources
+            .iter()
+            .any(|source| link.pos().in_range_to(source.pos(), SOURCE_LINK_RANGE))
+        {
+            network.sources.push(link.id());
+        } else if hubs
+            .iter()
+            .any(|&hub| link.pos().in_range_to(hub, SINK_LINK_RANGE))
+        {
+            network.sinks.push(link.id());
+        }
+    }
+
+    network
+}
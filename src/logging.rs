@@ -1,7 +1,8 @@
 use core::panic::PanicInfo;
+use std::cell::RefCell;
 use std::{fmt::Write, panic};
 
-use js_sys::JsString;
+use js_sys::{JsString, Reflect};
 use log::*;
 use screeps::game;
 use wasm_bindgen::prelude::wasm_bindgen;
@@ -9,6 +10,11 @@ use web_sys::console;
 
 pub use log::LevelFilter::*;
 
+thread_local! {
+    // Memory.log_level as of the last time it was applied, None before the first check
+    static MEMORY_LEVEL: RefCell<Option<Option<String>>> = RefCell::new(None);
+}
+
 struct JsLog;
 struct JsNotify;
 
@@ -32,8 +38,10 @@ impl log::Log for JsNotify {
 }
 
 pub fn setup_logging(verbosity: log::LevelFilter) {
+    // let everything through the dispatcher itself and filter on the global max level
+    // instead, so the verbosity can be changed later without rebuilding the logger
     fern::Dispatch::new()
-        .level(verbosity)
+        .level(log::LevelFilter::Trace)
         .format(|out, message, record| {
             out.finish(format_args!(
                 "({}) {}: {}",
@@ -54,9 +62,43 @@ pub fn setup_logging(verbosity: log::LevelFilter) {
         )
         .apply()
         .expect("expected setup_logging to only ever be called once per instance");
+    set_level(verbosity);
     panic::set_hook(Box::new(panic_hook));
 }
 
+pub fn set_level(verbosity: log::LevelFilter) {
+    log::set_max_level(verbosity);
+}
+
+// applies Memory.log_level whenever it changes, falling back to Info when it's missing or
+// can't be parsed. Only acting on changes leaves anything set from the console alone
+// until Memory says otherwise
+pub fn apply_memory_level() {
+    let level = Reflect::get(&screeps::memory::ROOT, &"log_level".into())
+        .ok()
+        .and_then(|value| value.as_string());
+
+    let changed = MEMORY_LEVEL.with_borrow_mut(|last| {
+        let changed = last.as_ref() != Some(&level);
+        *last = Some(level.clone());
+        changed
+    });
+    if !changed {
+        return;
+    }
+
+    match level {
+        None => set_level(Info),
+        Some(level) => match level.parse::<log::LevelFilter>() {
+            Ok(level) => set_level(level),
+            Err(_) => {
+                set_level(Info);
+                warn!("unknown Memory.log_level {level:?}, using info");
+            }
+        },
+    }
+}
+
 fn panic_hook(info: &PanicInfo) {
     // import JS Error API to get backtrace info (backtraces don't work in wasm)
     // Node 8 does support this API: https://nodejs.org/docs/latest-v8.x/api/errors.html#errors_error_stack
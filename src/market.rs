@@ -0,0 +1,200 @@
+use js_sys::JsString;
+use log::*;
+use screeps::{
+    constants::{MarketResourceType, OrderType, ResourceType},
+    game::{self, market::Order},
+    local::LodashFilter,
+    objects::{Room, StructureTerminal},
+};
+
+use crate::{
+    config::{room_config, RoomConfig},
+    owned_rooms,
+};
+
+// how often terminals look for deals. Order books don't move fast
+pub const MARKET_INTERVAL: u32 = 100;
+// the engine won't take more deals than this in one tick
+const MAX_DEALS_PER_TICK: usize = 10;
+// no single deal moves more than this, so one bad price can't cost too much
+const MAX_DEAL_AMOUNT: u32 = 5_000;
+// anything smaller isn't worth the terminal cooldown
+const MIN_DEAL_AMOUNT: u32 = 100;
+// transfer costs scale linearly, so they're worked out once per order for this many units
+const COST_SAMPLE: u32 = 1_000;
+
+// how much of `wanted` a deal can move when the terminal holds `terminal_energy` and
+// every unit takes `energy_per_unit` of it, counting the unit itself when it's energy
+// being sold. None if that's too little to bother with
+pub fn deal_amount(wanted: u32, terminal_energy: u32, energy_per_unit: f64) -> Option<u32> {
+    let affordable = if energy_per_unit > 0.0 {
+        (terminal_energy as f64 / energy_per_unit).floor() as u32
+    } else {
+        wanted
+    };
+    let amount = wanted.min(affordable).min(MAX_DEAL_AMOUNT);
+    (amount >= MIN_DEAL_AMOUNT).then_some(amount)
+}
+
+fn orders(resource: ResourceType, order_type: OrderType) -> Vec<Order> {
+    let filter = LodashFilter::new();
+    filter.resource_type(MarketResourceType::Resource(resource));
+    game::market::get_all_orders(Some(&filter))
+        .into_iter()
+        .filter(|order| order.order_type() == order_type && order.remaining_amount() > 0)
+        .collect()
+}
+
+// energy it costs per unit to trade with an order, None for orders with no room
+fn cost_per_unit(room: &Room, order: &Order) -> Option<f64> {
+    let from = JsString::from(room.name().to_string());
+    let to = order.room_name()?;
+    Some(game::market::calc_transaction_cost(COST_SAMPLE, &from, &to) as f64 / COST_SAMPLE as f64)
+}
+
+// sells whatever the room's terminal holds past its sell rules into the best buy orders,
+// and buys energy in while its storage is running dry. Each terminal makes one deal at
+// most, its cooldown rules out a second
+pub fn run_market() {
+    let mut deals = 0;
+    for room in owned_rooms() {
+        if deals >= MAX_DEALS_PER_TICK {
+            break;
+        }
+        let Some(terminal) = room.terminal() else {
+            continue;
+        };
+        if terminal.cooldown() > 0 {
+            continue;
+        }
+
+        let config = room_config(room.name());
+        if sell_surplus(&room, &terminal, &config) || buy_energy(&room, &terminal, &config) {
+            deals += 1;
+        }
+    }
+}
+
+fn sell_surplus(room: &Room, terminal: &StructureTerminal, config: &RoomConfig) -> bool {
+    let store = terminal.store();
+    let terminal_energy = store.get_used_capacity(Some(ResourceType::Energy));
+
+    for rule in &config.market_sell {
+        let Some(surplus) = store
+            .get_used_capacity(Some(rule.resource))
+            .checked_sub(rule.keep)
+            .filter(|&surplus| surplus >= MIN_DEAL_AMOUNT)
+        else {
+            continue;
+        };
+
+        let Some(order) = orders(rule.resource, OrderType::Buy)
+            .into_iter()
+            .filter(|order| order.price() >= rule.min_price)
+            .max_by(|a, b| a.price().total_cmp(&b.price()))
+        else {
+            continue;
+        };
+        let Some(cost) = cost_per_unit(room, &order) else {
+            continue;
+        };
+
+        // selling energy sends the energy itself on top of the transfer cost
+        let energy_per_unit = if rule.resource == ResourceType::Energy {
+            1.0 + cost
+        } else {
+            cost
+        };
+        let Some(amount) = deal_amount(
+            surplus.min(order.remaining_amount()),
+            terminal_energy,
+            energy_per_unit,
+        ) else {
+            continue;
+        };
+
+        match game::market::deal(&order.id(), amount, Some(room.name())) {
+            Ok(()) => {
+                info!(
+                    "sold {amount} {:?} from {} at {:.3} for {:.0} credits ({:.0} energy to send)",
+                    rule.resource,
+                    room.name(),
+                    order.price(),
+                    amount as f64 * order.price(),
+                    amount as f64 * cost
+                );
+                return true;
+            }
+            // the terminal's still free, so the next rule gets a go
+            Err(e) => warn!(
+                "couldn't sell {:?} from {}: {:?}",
+                rule.resource,
+                room.name(),
+                e
+            ),
+        }
+    }
+    false
+}
+
+fn buy_energy(room: &Room, terminal: &StructureTerminal, config: &RoomConfig) -> bool {
+    let (Some(threshold), Some(storage)) = (config.market_buy_energy_below, room.storage()) else {
+        return false;
+    };
+    let stored = storage
+        .store()
+        .get_used_capacity(Some(ResourceType::Energy));
+    let Some(wanted) = threshold.checked_sub(stored) else {
+        return false;
+    };
+    let credits = game::market::credits() - config.market_credit_reserve;
+    if credits <= 0.0 {
+        return false;
+    }
+
+    // the energy spent receiving it comes out of what's bought, so compare orders on what
+    // each unit that actually arrives costs
+    let Some((order, cost, effective_price)) = orders(ResourceType::Energy, OrderType::Sell)
+        .into_iter()
+        .filter(|order| order.price() <= config.market_max_energy_price)
+        .filter_map(|order| {
+            let cost = cost_per_unit(room, &order)?;
+            (cost < 1.0).then(|| {
+                let effective_price = order.price() / (1.0 - cost);
+                (order, cost, effective_price)
+            })
+        })
+        .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+    else {
+        return false;
+    };
+
+    let affordable = (credits / order.price()).floor() as u32;
+    let terminal_energy = terminal
+        .store()
+        .get_used_capacity(Some(ResourceType::Energy));
+    let Some(amount) = deal_amount(
+        wanted.min(order.remaining_amount()).min(affordable),
+        terminal_energy,
+        cost,
+    ) else {
+        return false;
+    };
+
+    match game::market::deal(&order.id(), amount, Some(room.name())) {
+        Ok(()) => {
+            info!(
+                "bought {amount} energy into {} at {:.3} ({:.3} delivered) for {:.0} credits",
+                room.name(),
+                order.price(),
+                effective_price,
+                amount as f64 * order.price()
+            );
+            true
+        }
+        Err(e) => {
+            warn!("couldn't buy energy into {}: {:?}", room.name(), e);
+            false
+        }
+    }
+}
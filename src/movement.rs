@@ -0,0 +1,467 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use js_sys::Reflect;
+use log::*;
+use rand::seq::SliceRandom;
+use screeps::{
+    constants::{Direction, ErrorCode},
+    enums::StructureObject,
+    find, game,
+    local::{LocalCostMatrix, Position, RoomName, RoomXY},
+    objects::Creep,
+    pathfinder::{self, MultiRoomCostResult, SearchOptions},
+    prelude::*,
+    LineDrawStyle, MoveToOptions, PolyStyle,
+};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+use crate::timer::intent;
+use crate::RNG;
+use crate::{find_cache, keepers};
+
+// above this much bucket paths get recomputed often for better routing, and below the
+// other they're held onto for as long as they work
+const REUSE_SHORT_BUCKET: i32 = 9_500;
+const REUSE_LONG_BUCKET: i32 = 2_000;
+
+// how many ticks a creep can fail to move before we throw away its cached path
+const STUCK_REPATH_TICKS: u8 = 3;
+// if a fresh path still doesn't get it moving, step somewhere random to break the jam
+const STUCK_SHUFFLE_TICKS: u8 = 6;
+
+// how long a room's cost matrix is trusted before it's rebuilt. Creeps move every tick,
+// so their costs go stale quickly, but structures hardly ever change
+const COST_MATRIX_TTL: u32 = 20;
+// roads get preferred over plains, which get preferred over swamps
+pub const ROAD_COST: u8 = 1;
+pub const PLAIN_COST: u8 = 2;
+pub const SWAMP_COST: u8 = 10;
+// worth a short detour, but not a blocked path
+const CREEP_COST: u8 = 20;
+// around keeper lairs, only walked through when there's truly no other way
+const KEEPER_COST: u8 = 250;
+const OBSTACLE_COST: u8 = 255;
+
+pub const DIRECTIONS: [Direction; 8] = [
+    Direction::Top,
+    Direction::TopRight,
+    Direction::Right,
+    Direction::BottomRight,
+    Direction::Bottom,
+    Direction::BottomLeft,
+    Direction::Left,
+    Direction::TopLeft,
+];
+
+struct MoveTracker {
+    pos: Position,
+    tick: u32,
+    stuck: u8,
+}
+
+impl MoveTracker {
+    // moves the count on to `tick`, for a creep at `pos` with `fatigue`. It only counts a
+    // tick spent in place if the creep had just tried to move the tick before and wasn't
+    // too tired to go anywhere
+    fn update(&mut self, pos: Position, tick: u32, fatigue: u32) -> u8 {
+        if self.tick == tick {
+            return self.stuck;
+        }
+
+        if self.pos != pos || self.tick + 1 != tick {
+            self.stuck = 0;
+        } else if fatigue == 0 {
+            self.stuck = self.stuck.saturating_add(1);
+        }
+
+        self.pos = pos;
+        self.tick = tick;
+        self.stuck
+    }
+}
+
+// what a creep does about having gone a number of ticks without moving
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Unstick {
+    // carry on along its path
+    Nothing,
+    // throw the cached path away for a fresh one
+    Repath,
+    // step somewhere random
+    Shuffle,
+}
+
+fn unstick(stuck: u8) -> Unstick {
+    if stuck >= STUCK_SHUFFLE_TICKS {
+        Unstick::Shuffle
+    } else if stuck >= STUCK_REPATH_TICKS {
+        Unstick::Repath
+    } else {
+        Unstick::Nothing
+    }
+}
+
+// a path kept in a creep's memory, as packed positions so it stays small
+#[derive(Serialize, Deserialize)]
+struct CachedPath {
+    target: u32,
+    path: Vec<u32>,
+}
+
+thread_local! {
+    static MOVE_TRACKERS: RefCell<HashMap<String, MoveTracker>> = RefCell::new(HashMap::new());
+
+    // each visible room's cost matrix, along with the tick it was built on
+    static COST_MATRICES: RefCell<HashMap<RoomName, (u32, LocalCostMatrix)>> = RefCell::new(HashMap::new());
+}
+
+pub trait DefaultMove {
+    fn default_move_to<T>(&self, target: &T) -> Result<(), ErrorCode>
+    where
+        T: HasPosition,
+    {
+        self.move_adaptive(target)
+    }
+
+    // moves with a path reuse picked from how much cpu we have to spare
+    fn move_adaptive<T>(&self, target: &T) -> Result<(), ErrorCode>
+    where
+        T: HasPosition;
+}
+
+impl DefaultMove for Creep {
+    fn move_adaptive<T>(&self, target: &T) -> Result<(), ErrorCode>
+    where
+        T: HasPosition,
+    {
+        let stuck = track_stuck(self);
+        if let Some(result) = tired_move(self.fatigue()) {
+            return result;
+        }
+
+        let action = unstick(stuck);
+        if action == Unstick::Shuffle {
+            let direction = RNG.with_borrow_mut(|rng| *DIRECTIONS.choose(rng).unwrap());
+            info!(
+                "{} stuck for {stuck} ticks, shuffling {direction:?}",
+                self.name()
+            );
+            return intent("move", self.move_direction(direction));
+        }
+
+        let reuse_path = if action == Unstick::Repath {
+            0
+        } else {
+            reuse_path_for_bucket(game::cpu::bucket())
+        };
+
+        let result = self.move_to_with_options(
+            target.pos(),
+            Some(
+                MoveToOptions::new()
+                    .reuse_path(reuse_path)
+                    .visualize_path_style(
+                        PolyStyle::default()
+                            .fill("black")
+                            .stroke_width(0.15)
+                            .opacity(0.1)
+                            .line_style(LineDrawStyle::Dashed),
+                    ),
+            ),
+        );
+        intent("move", result)
+    }
+}
+
+// a tired creep can't move this tick whatever we ask, so the move is skipped before
+// paying for a path. It's not a failure either, the target still stands
+fn tired_move(fatigue: u32) -> Option<Result<(), ErrorCode>> {
+    (fatigue > 0).then_some(Ok(()))
+}
+
+// a full bucket can afford fresh paths every couple of ticks, a draining one needs to
+// lean on cached paths for as long as they hold up
+pub fn reuse_path_for_bucket(bucket: i32) -> u32 {
+    if bucket >= REUSE_SHORT_BUCKET {
+        2
+    } else if bucket < REUSE_LONG_BUCKET {
+        15
+    } else {
+        5
+    }
+}
+
+// counts how many consecutive ticks the creep has tried to move without going anywhere.
+// ticks spent tired don't count, and neither does a gap since the last move attempt
+fn track_stuck(creep: &Creep) -> u8 {
+    let pos = creep.pos();
+    let tick = game::time();
+
+    MOVE_TRACKERS.with_borrow_mut(|trackers| {
+        trackers
+            .entry(creep.name())
+            .or_insert(MoveTracker {
+                pos,
+                tick,
+                stuck: 0,
+            })
+            .update(pos, tick, creep.fatigue())
+    })
+}
+
+// roads cheap, things that can't be walked through blocked, and creeps and keeper
+// territory expensive. Everything else is left at 0 so the search falls back on the
+// terrain costs
+pub fn build_cost_matrix(
+    roads: &[RoomXY],
+    obstacles: &[RoomXY],
+    creeps: &[RoomXY],
+    keeper_danger: &HashSet<RoomXY>,
+) -> LocalCostMatrix {
+    let mut matrix = LocalCostMatrix::new();
+    for &xy in roads {
+        matrix.set(xy, ROAD_COST);
+    }
+    for &xy in creeps {
+        matrix.set(xy, CREEP_COST);
+    }
+    for &xy in keeper_danger {
+        matrix.set(xy, KEEPER_COST);
+    }
+    // obstacles go last, so nothing else can make a blocked tile look passable
+    for &xy in obstacles {
+        matrix.set(xy, OBSTACLE_COST);
+    }
+    matrix
+}
+
+fn room_costs(room_name: RoomName) -> MultiRoomCostResult {
+    let Some(room) = game::rooms().get(room_name) else {
+        return MultiRoomCostResult::Default;
+    };
+
+    let matrix = COST_MATRICES.with_borrow_mut(|matrices| {
+        let now = game::time();
+        if let Some((built, matrix)) = matrices.get(&room_name) {
+            if now < built + COST_MATRIX_TTL {
+                return matrix.clone();
+            }
+        }
+
+        let mut roads = Vec::new();
+        let mut obstacles = Vec::new();
+        for structure in find_cache::structures(&room).iter() {
+            match structure {
+                StructureObject::StructureRoad(road) => roads.push(road.pos().xy()),
+                StructureObject::StructureContainer(_) => {}
+                StructureObject::StructureRampart(rampart)
+                    if rampart.my() || rampart.is_public() => {}
+                _ => obstacles.push(structure.pos().xy()),
+            }
+        }
+        let creeps: Vec<RoomXY> = room
+            .find(find::CREEPS, None)
+            .iter()
+            .map(|creep| creep.pos().xy())
+            .collect();
+
+        let matrix = build_cost_matrix(&roads, &obstacles, &creeps, &keepers::danger_tiles(&room));
+        matrices.insert(room_name, (now, matrix.clone()));
+        matrix
+    });
+
+    MultiRoomCostResult::CostMatrix(matrix.into())
+}
+
+fn load_path(creep: &Creep) -> Option<CachedPath> {
+    let value = Reflect::get(&creep.memory(), &JsValue::from_str("path")).ok()?;
+    if value.is_undefined() {
+        return None;
+    }
+
+    let json = js_sys::JSON::stringify(&value).ok()?;
+    serde_json::from_str(&String::from(json))
+        .map_err(|e| warn!("couldn't read path for {}: {}", creep.name(), e))
+        .ok()
+}
+
+fn save_path(creep: &Creep, path: &CachedPath) {
+    let Ok(json) = serde_json::to_string(path) else {
+        return;
+    };
+    if let Ok(value) = js_sys::JSON::parse(&json) {
+        let _ = Reflect::set(&creep.memory(), &JsValue::from_str("path"), &value);
+    }
+}
+
+// the tile after the one the creep is standing on, or the first tile if it's only just
+// setting off. None if it's wandered off the path
+fn next_step(pos: Position, path: &[u32]) -> Option<Position> {
+    let packed = pos.packed_repr();
+    match path.iter().position(|&step| step == packed) {
+        Some(i) => path.get(i + 1).copied().map(Position::from_packed),
+        None => path
+            .first()
+            .copied()
+            .map(Position::from_packed)
+            .filter(|&first| pos.is_near_to(first)),
+    }
+}
+
+// moves along a path found with our own cost matrices and kept in the creep's memory,
+// so a creep heading somewhere far only pays for the search once. Repaths when the
+// target changes, the creep leaves the path, or it's been stuck for a while
+pub fn move_to_cached<T>(creep: &Creep, target: &T, range: u32) -> Result<(), ErrorCode>
+where
+    T: HasPosition,
+{
+    let pos = creep.pos();
+    let goal = target.pos();
+    if pos.get_range_to(goal) <= range {
+        return Ok(());
+    }
+
+    let stuck = track_stuck(creep);
+    if let Some(result) = tired_move(creep.fatigue()) {
+        return result;
+    }
+    let cached = load_path(creep)
+        .filter(|cached| cached.target == goal.packed_repr() && stuck < STUCK_REPATH_TICKS)
+        .and_then(|cached| next_step(pos, &cached.path));
+
+    let next = match cached {
+        Some(next) => next,
+        None => {
+            let options = SearchOptions::new(room_costs)
+                .plain_cost(PLAIN_COST)
+                .swamp_cost(SWAMP_COST);
+            let path = pathfinder::search(pos, goal, range, Some(options)).path();
+            let next = *path.first().ok_or(ErrorCode::NoPath)?;
+            save_path(
+                creep,
+                &CachedPath {
+                    target: goal.packed_repr(),
+                    path: path.iter().map(|step| step.packed_repr()).collect(),
+                },
+            );
+            next
+        }
+    };
+
+    intent(
+        "move",
+        creep.move_direction(pos.get_direction_to(next).ok_or(ErrorCode::NoPath)?),
+    )
+}
+
+pub fn forget_dead(alive: &HashSet<String>) {
+    MOVE_TRACKERS.with_borrow_mut(|trackers| trackers.retain(|name, _| alive.contains(name)));
+}
+
+#[cfg(test)]
+mod tests {
+    use screeps::local::RoomCoordinate;
+
+    use super::*;
+
+    fn xy(x: u8, y: u8) -> RoomXY {
+        RoomXY::try_from((x, y)).unwrap()
+    }
+
+    fn pos(x: u8, y: u8) -> Position {
+        Position::new(
+            RoomCoordinate::new(x).unwrap(),
+            RoomCoordinate::new(y).unwrap(),
+            "W1N1".parse().unwrap(),
+        )
+    }
+
+    fn tracker() -> MoveTracker {
+        MoveTracker {
+            pos: pos(10, 10),
+            tick: 100,
+            stuck: 0,
+        }
+    }
+
+    #[test]
+    fn stuck_counts_ticks_in_place() {
+        let mut tracker = tracker();
+        assert_eq!(tracker.update(pos(10, 10), 101, 0), 1);
+        assert_eq!(tracker.update(pos(10, 10), 102, 0), 2);
+        // asking again on the same tick doesn't count twice
+        assert_eq!(tracker.update(pos(10, 10), 102, 0), 2);
+        assert_eq!(tracker.update(pos(10, 10), 103, 0), 3);
+    }
+
+    #[test]
+    fn tired_ticks_dont_count_as_stuck() {
+        let mut tracker = tracker();
+        assert_eq!(tracker.update(pos(10, 10), 101, 0), 1);
+        assert_eq!(tracker.update(pos(10, 10), 102, 4), 1);
+        assert_eq!(tracker.update(pos(10, 10), 103, 2), 1);
+        assert_eq!(tracker.update(pos(10, 10), 104, 0), 2);
+    }
+
+    #[test]
+    fn moving_or_a_gap_starts_over() {
+        let mut tracker = tracker();
+        tracker.update(pos(10, 10), 101, 0);
+        tracker.update(pos(10, 10), 102, 0);
+        assert_eq!(tracker.update(pos(11, 10), 103, 0), 0);
+        assert_eq!(tracker.update(pos(11, 10), 104, 0), 1);
+        // it wasn't asked to move for a few ticks, so standing still is no surprise
+        assert_eq!(tracker.update(pos(11, 10), 110, 0), 0);
+    }
+
+    #[test]
+    fn stuck_creeps_repath_then_shuffle() {
+        assert_eq!(unstick(0), Unstick::Nothing);
+        assert_eq!(unstick(STUCK_REPATH_TICKS - 1), Unstick::Nothing);
+        assert_eq!(unstick(STUCK_REPATH_TICKS), Unstick::Repath);
+        assert_eq!(unstick(STUCK_SHUFFLE_TICKS - 1), Unstick::Repath);
+        assert_eq!(unstick(STUCK_SHUFFLE_TICKS), Unstick::Shuffle);
+        assert_eq!(unstick(u8::MAX), Unstick::Shuffle);
+    }
+
+    #[test]
+    fn tired_creeps_skip_the_move() {
+        assert_eq!(tired_move(0), None);
+        // skipped, but as a success so the target's kept
+        assert_eq!(tired_move(1), Some(Ok(())));
+        assert_eq!(tired_move(10), Some(Ok(())));
+    }
+
+    #[test]
+    fn cost_matrix_marks_each_kind_of_tile() {
+        let danger: HashSet<RoomXY> = [xy(4, 4)].into_iter().collect();
+        let matrix = build_cost_matrix(&[xy(1, 1)], &[xy(2, 2)], &[xy(3, 3)], &danger);
+
+        assert_eq!(matrix.get(xy(1, 1)), ROAD_COST);
+        assert_eq!(matrix.get(xy(2, 2)), OBSTACLE_COST);
+        assert_eq!(matrix.get(xy(3, 3)), CREEP_COST);
+        assert_eq!(matrix.get(xy(4, 4)), KEEPER_COST);
+        // left to the terrain costs
+        assert_eq!(matrix.get(xy(5, 5)), 0);
+    }
+
+    #[test]
+    fn obstacles_win_over_everything_else() {
+        let tile = xy(10, 10);
+        let danger: HashSet<RoomXY> = [tile].into_iter().collect();
+        let matrix = build_cost_matrix(&[tile], &[tile], &[tile], &danger);
+        assert_eq!(matrix.get(tile), OBSTACLE_COST);
+    }
+
+    #[test]
+    fn path_reuse_follows_bucket() {
+        assert_eq!(reuse_path_for_bucket(10_000), 2);
+        assert_eq!(reuse_path_for_bucket(REUSE_SHORT_BUCKET), 2);
+        assert_eq!(reuse_path_for_bucket(5_000), 5);
+        assert_eq!(reuse_path_for_bucket(REUSE_LONG_BUCKET), 5);
+        assert_eq!(reuse_path_for_bucket(REUSE_LONG_BUCKET - 1), 15);
+        assert_eq!(reuse_path_for_bucket(0), 15);
+    }
+}
@@ -0,0 +1,134 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use log::*;
+use screeps::{
+    enums::StructureObject,
+    find, game,
+    local::{ObjectId, RoomXY},
+    objects::{Nuke, Room},
+    prelude::*,
+};
+
+use crate::{find_cache, owned_rooms, planner};
+
+// how often rooms are checked for incoming nukes. They take tens of thousands of ticks to
+// land, so there's no rush
+pub const NUKE_CHECK_INTERVAL: u32 = 10;
+// a nuke damages everything within this range of where it lands, a 5x5 square
+const NUKE_IMPACT_RANGE: i32 = 2;
+
+thread_local! {
+    // nukes that have already been shouted about
+    static SEEN_NUKES: RefCell<HashSet<ObjectId<Nuke>>> = RefCell::new(HashSet::new());
+}
+
+// every tile a nuke landing on `landing` will hit, clipped to the room
+pub fn impact_zone(landing: RoomXY) -> Vec<RoomXY> {
+    let (x, y) = (landing.x.u8() as i32, landing.y.u8() as i32);
+    (-NUKE_IMPACT_RANGE..=NUKE_IMPACT_RANGE)
+        .flat_map(|dx| (-NUKE_IMPACT_RANGE..=NUKE_IMPACT_RANGE).map(move |dy| (x + dx, y + dy)))
+        .filter_map(|(x, y)| RoomXY::try_from((u8::try_from(x).ok()?, u8::try_from(y).ok()?)).ok())
+        .collect()
+}
+
+// whether anything is on its way to land on the room. Rooms in this state only spawn what
+// they need to keep running and defend themselves
+pub fn nuke_incoming(room: &Room) -> bool {
+    !room.find(find::NUKES, None).is_empty()
+}
+
+// structures worth a rampart to see them through a nuke. Everything else is cheap enough
+// to rebuild
+fn is_critical(structure: &StructureObject) -> bool {
+    matches!(
+        structure,
+        StructureObject::StructureSpawn(_)
+            | StructureObject::StructureTower(_)
+            | StructureObject::StructureStorage(_)
+            | StructureObject::StructureTerminal(_)
+            | StructureObject::StructureLab(_)
+            | StructureObject::StructurePowerSpawn(_)
+            | StructureObject::StructureFactory(_)
+            | StructureObject::StructureNuker(_)
+    )
+}
+
+// warns about every nuke headed for one of our rooms the first time it's seen, and
+// ramparts up the critical structures it's going to hit
+pub fn check_nukes() {
+    for room in owned_rooms() {
+        let nukes = room.find(find::NUKES, None);
+        if nukes.is_empty() {
+            continue;
+        }
+
+        let mut zone = HashSet::new();
+        for nuke in &nukes {
+            let landing = nuke.pos().xy();
+            zone.extend(impact_zone(landing));
+
+            if SEEN_NUKES.with_borrow_mut(|seen| seen.insert(nuke.id())) {
+                error!(
+                    "NUKE incoming on {} at {} from {}, landing at tick {} ({} ticks)",
+                    room.name(),
+                    landing,
+                    nuke.launch_room_name(),
+                    game::time() + nuke.time_to_land(),
+                    nuke.time_to_land()
+                );
+            }
+        }
+
+        let critical: Vec<RoomXY> = find_cache::structures(&room)
+            .iter()
+            .filter(|structure| is_critical(structure))
+            .map(|structure| structure.pos().xy())
+            .filter(|xy| zone.contains(xy))
+            .collect();
+        let placed = planner::plan_ramparts_over(&room, &critical);
+        if placed > 0 {
+            info!(
+                "placed {placed} rampart sites under nukes in {}",
+                room.name()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xy(x: u8, y: u8) -> RoomXY {
+        RoomXY::try_from((x, y)).unwrap()
+    }
+
+    #[test]
+    fn blast_reaches_two_tiles_out() {
+        let zone = impact_zone(xy(25, 25));
+        assert_eq!(zone.len(), 25);
+        for tile in [xy(23, 23), xy(27, 27), xy(23, 27), xy(25, 23)] {
+            assert!(zone.contains(&tile), "{tile:?} should be hit");
+        }
+        for tile in [xy(22, 25), xy(28, 25), xy(25, 22), xy(25, 28)] {
+            assert!(!zone.contains(&tile), "{tile:?} should be spared");
+        }
+    }
+
+    #[test]
+    fn blast_is_clipped_at_the_room_edge() {
+        let corner = impact_zone(xy(0, 0));
+        assert_eq!(corner.len(), 9);
+        assert!(corner
+            .iter()
+            .all(|tile| tile.x.u8() <= 2 && tile.y.u8() <= 2));
+
+        let far_edge = impact_zone(xy(49, 30));
+        assert_eq!(far_edge.len(), 15);
+        assert!(far_edge.contains(&xy(49, 32)));
+        assert!(far_edge.iter().all(|tile| tile.x.u8() >= 47));
+
+        assert_eq!(impact_zone(xy(1, 25)).len(), 20);
+    }
+}
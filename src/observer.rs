@@ -0,0 +1,76 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use log::*;
+use screeps::{
+    enums::StructureObject,
+    find, game,
+    local::RoomName,
+    objects::{Room, StructureObserver},
+};
+
+use crate::intel;
+
+// what each room's observer is up to
+#[derive(Default)]
+struct ObserverState {
+    // the room asked for, and the tick it was asked for on. It only becomes visible on the
+    // tick after
+    pending: Option<(RoomName, u32)>,
+    // where in the room's list of remotes to look next
+    next: usize,
+}
+
+thread_local! {
+    static OBSERVERS: RefCell<HashMap<RoomName, ObserverState>> = RefCell::new(HashMap::new());
+}
+
+// has the room's observer record intel on the room it looked at last tick, then look at
+// the next of the room's remotes
+pub fn run_observer(room: &Room, remotes: &[RoomName]) {
+    if remotes.is_empty() {
+        return;
+    }
+    let Some(observer) = room
+        .find(find::MY_STRUCTURES, None)
+        .into_iter()
+        .find_map(|structure| match structure {
+            StructureObject::StructureObserver(observer) => Some(observer),
+            _ => None,
+        })
+    else {
+        return;
+    };
+
+    let now = game::time();
+    OBSERVERS.with_borrow_mut(|observers| {
+        let state = observers.entry(room.name()).or_default();
+
+        if let Some((target, tick)) = state.pending.take() {
+            if tick + 1 == now {
+                match game::rooms().get(target) {
+                    Some(observed) => intel::record(&observed),
+                    None => warn!("observed {target} but it isn't visible"),
+                }
+            }
+        }
+
+        observe_next(&observer, remotes, state, now);
+    });
+}
+
+fn observe_next(
+    observer: &StructureObserver,
+    remotes: &[RoomName],
+    state: &mut ObserverState,
+    now: u32,
+) {
+    let target = remotes[state.next % remotes.len()];
+    // move on either way, so a room that's out of range doesn't hold up the rest
+    state.next = (state.next + 1) % remotes.len();
+
+    match observer.observe_room(target) {
+        Ok(()) => state.pending = Some((target, now)),
+        Err(e) => warn!("couldn't observe {target}: {:?}", e),
+    }
+}
@@ -0,0 +1,73 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use log::*;
+use screeps::{
+    constants::Terrain,
+    local::{LocalRoomTerrain, Position, RoomName, RoomXY},
+    objects::Room,
+    prelude::*,
+};
+
+use crate::{config::room_config, find_cache};
+
+// how far from the controller the default parking spot sits, close enough that idle
+// creeps are handy for upgrading without standing in the way of the upgraders
+const PARKING_RANGE: i16 = 3;
+// idle creeps stop anywhere this close to the spot, so they don't queue up for the one tile
+pub const PARKING_SLACK: u32 = 2;
+
+thread_local! {
+    // the default parking spot for each room, along with how many structures were in the
+    // room when it was picked
+    static PARKING: RefCell<HashMap<RoomName, (usize, Option<RoomXY>)>> = RefCell::new(HashMap::new());
+}
+
+// where creeps with nothing to do should wait in this room
+pub fn parking_spot(room: &Room) -> Option<Position> {
+    let xy = configured_spot(room.name()).or_else(|| default_spot(room))?;
+    Some(Position::new(xy.x, xy.y, room.name()))
+}
+
+// the idle position set in the room's config, if any
+fn configured_spot(room_name: RoomName) -> Option<RoomXY> {
+    let spot = room_config(room_name).idle_position?;
+    RoomXY::try_from((spot.x, spot.y))
+        .map_err(|e| warn!("bad idle position for {}: {:?}", room_name, e))
+        .ok()
+}
+
+fn default_spot(room: &Room) -> Option<RoomXY> {
+    let structures = find_cache::structures(room);
+
+    PARKING.with_borrow_mut(|parking| {
+        let (count, spot) = parking.entry(room.name()).or_insert((0, None));
+        if *count != structures.len() {
+            *count = structures.len();
+            *spot = pick_spot(room);
+        }
+        *spot
+    })
+}
+
+// the first walkable tile with nothing built on it (roads included, so idle creeps stay
+// out of traffic) at PARKING_RANGE from the controller
+fn pick_spot(room: &Room) -> Option<RoomXY> {
+    let controller = room.controller()?.pos().xy();
+    let terrain = LocalRoomTerrain::from(room.get_terrain());
+    let built: HashSet<RoomXY> = find_cache::structures(room)
+        .iter()
+        .map(|structure| structure.pos().xy())
+        .collect();
+
+    let (cx, cy) = (controller.x.u8() as i16, controller.y.u8() as i16);
+    (-PARKING_RANGE..=PARKING_RANGE)
+        .flat_map(|dx| (-PARKING_RANGE..=PARKING_RANGE).map(move |dy| (dx, dy)))
+        .filter(|(dx, dy)| dx.abs().max(dy.abs()) == PARKING_RANGE)
+        .filter_map(|(dx, dy)| {
+            let x = u8::try_from(cx + dx).ok()?;
+            let y = u8::try_from(cy + dy).ok()?;
+            RoomXY::try_from((x, y)).ok()
+        })
+        .find(|xy| terrain.get(*xy) != Terrain::Wall && !built.contains(xy))
+}
@@ -0,0 +1,197 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+
+use screeps::{
+    constants::{Terrain, ROOM_SIZE},
+    enums::StructureObject,
+    local::{LocalRoomTerrain, Position, RoomName, RoomXY},
+    objects::Room,
+    prelude::*,
+};
+
+use crate::find_cache;
+use crate::sources::adjacent_tiles;
+
+// how far from the spawn, in steps, defenders wait for something to come through the walls
+const RALLY_DISTANCE: u16 = 3;
+
+// the part of a room our walls and ramparts close off
+pub struct Perimeter {
+    // steps from the spawn to each tile inside, indexed by x * ROOM_SIZE + y
+    distance: Vec<Option<u16>>,
+    rally: Option<RoomXY>,
+}
+
+impl Perimeter {
+    pub fn contains(&self, xy: RoomXY) -> bool {
+        self.distance[index(xy)].is_some()
+    }
+}
+
+// a room's perimeter, None while its walls don't close, along with how many structures
+// it had when it was worked out. Anything built can move the rally point, not just walls
+#[derive(Default)]
+struct CachedPerimeter {
+    structure_count: usize,
+    perimeter: Option<Rc<Perimeter>>,
+}
+
+thread_local! {
+    static PERIMETERS: RefCell<HashMap<RoomName, CachedPerimeter>> = RefCell::new(HashMap::new());
+}
+
+fn index(xy: RoomXY) -> usize {
+    xy.x.u8() as usize * ROOM_SIZE as usize + xy.y.u8() as usize
+}
+
+fn on_edge(xy: RoomXY) -> bool {
+    let edge = ROOM_SIZE - 1;
+    xy.x.u8() == 0 || xy.x.u8() == edge || xy.y.u8() == 0 || xy.y.u8() == edge
+}
+
+// flood fills out from `start` through every tile that isn't `blocked`, giving the steps
+// to each tile reached. None if the fill makes it to the room's edge, since then there's
+// no closed perimeter and everything's effectively outside
+pub fn flood_inside(start: RoomXY, blocked: impl Fn(RoomXY) -> bool) -> Option<Vec<Option<u16>>> {
+    let mut distance = vec![None; ROOM_SIZE as usize * ROOM_SIZE as usize];
+    distance[index(start)] = Some(0);
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(xy) = queue.pop_front() {
+        if on_edge(xy) {
+            return None;
+        }
+        let steps = distance[index(xy)].unwrap_or(0);
+        for next in adjacent_tiles(xy) {
+            if distance[index(next)].is_none() && !blocked(next) {
+                distance[index(next)] = Some(steps + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+    Some(distance)
+}
+
+fn find_perimeter(room: &Room, structures: &[StructureObject]) -> Option<Perimeter> {
+    let terrain = LocalRoomTerrain::from(room.get_terrain());
+    let mut walls = HashSet::new();
+    // tiles the rally point can't go on: anything creeps can't stand on, and the tiles
+    // around every spawn, which new creeps need to get out
+    let mut avoid = HashSet::new();
+    let mut spawn = None;
+    for structure in structures {
+        let xy = structure.pos().xy();
+        match structure {
+            StructureObject::StructureWall(_) => {
+                walls.insert(xy);
+            }
+            StructureObject::StructureRampart(rampart) if rampart.my() => {
+                walls.insert(xy);
+            }
+            StructureObject::StructureSpawn(_) => {
+                spawn.get_or_insert(xy);
+                avoid.insert(xy);
+                avoid.extend(adjacent_tiles(xy));
+            }
+            StructureObject::StructureRoad(_) | StructureObject::StructureContainer(_) => {}
+            _ => {
+                avoid.insert(xy);
+            }
+        }
+    }
+
+    let distance = flood_inside(spawn?, |xy| {
+        terrain.get(xy) == Terrain::Wall || walls.contains(&xy)
+    })?;
+
+    let rally = rally_tile(&distance, &avoid);
+    Some(Perimeter { distance, rally })
+}
+
+// somewhere open inside, as close to RALLY_DISTANCE steps from the spawn as there is,
+// that isn't one of the `avoid` tiles
+pub fn rally_tile(distance: &[Option<u16>], avoid: &HashSet<RoomXY>) -> Option<RoomXY> {
+    (0..ROOM_SIZE)
+        .flat_map(|x| (0..ROOM_SIZE).map(move |y| (x, y)))
+        .filter_map(|(x, y)| RoomXY::try_from((x, y)).ok())
+        .filter(|xy| !avoid.contains(xy))
+        .filter_map(|xy| Some((xy, distance[index(xy)]?)))
+        .min_by_key(|(_, steps)| steps.abs_diff(RALLY_DISTANCE))
+        .map(|(xy, _)| xy)
+}
+
+// the area the room's walls and ramparts close off around its spawn, None if they don't
+// close (or there's no spawn to start from)
+pub fn perimeter(room: &Room) -> Option<Rc<Perimeter>> {
+    let structures = find_cache::structures(room);
+
+    PERIMETERS.with_borrow_mut(|perimeters| {
+        let cached = perimeters.entry(room.name()).or_default();
+        if cached.structure_count != structures.len() {
+            cached.structure_count = structures.len();
+            cached.perimeter = find_perimeter(room, &structures).map(Rc::new);
+        }
+        cached.perimeter.clone()
+    })
+}
+
+// where defenders wait inside the walls
+pub fn rally_point(room: &Room) -> Option<Position> {
+    let xy = perimeter(room)?.rally?;
+    Some(Position::new(xy.x, xy.y, room.name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xy(x: u8, y: u8) -> RoomXY {
+        RoomXY::try_from((x, y)).unwrap()
+    }
+
+    // a closed 9x9 box of walls from (10, 10) to (18, 18) with the spawn in the middle
+    fn walled_box() -> Vec<Option<u16>> {
+        flood_inside(xy(14, 14), |tile| {
+            let (x, y) = (tile.x.u8(), tile.y.u8());
+            ((x == 10 || x == 18) && (10..=18).contains(&y))
+                || ((y == 10 || y == 18) && (10..=18).contains(&x))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn rally_stays_off_the_spawns_and_buildings() {
+        let distance = walled_box();
+        let spawns = [xy(14, 14), xy(12, 14)];
+        let mut avoid: HashSet<RoomXY> = spawns
+            .iter()
+            .flat_map(|&spawn| adjacent_tiles(spawn).chain([spawn]))
+            .collect();
+        // fill every tile RALLY_DISTANCE out on one side with extensions
+        avoid.extend((11..=17).map(|y| xy(11, y)));
+
+        let rally = rally_tile(&distance, &avoid).unwrap();
+        assert!(!avoid.contains(&rally));
+        assert_eq!(distance[index(rally)], Some(RALLY_DISTANCE));
+    }
+
+    #[test]
+    fn rally_falls_back_to_the_nearest_distance() {
+        let distance = walled_box();
+        // nothing three steps out is free, so the rally point settles for two
+        let avoid: HashSet<RoomXY> = (0..ROOM_SIZE)
+            .flat_map(|x| (0..ROOM_SIZE).map(move |y| xy(x, y)))
+            .filter(|&tile| distance[index(tile)] == Some(RALLY_DISTANCE))
+            .chain(adjacent_tiles(xy(14, 14)))
+            .collect();
+
+        let rally = rally_tile(&distance, &avoid).unwrap();
+        assert_eq!(distance[index(rally)], Some(RALLY_DISTANCE - 1));
+    }
+
+    #[test]
+    fn open_rooms_have_no_perimeter() {
+        assert!(flood_inside(xy(14, 14), |_| false).is_none());
+    }
+}
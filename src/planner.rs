@@ -0,0 +1,484 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use log::*;
+use screeps::{
+    constants::{Color, ErrorCode, StructureType, Terrain},
+    enums::StructureObject,
+    find, game,
+    local::{LocalRoomTerrain, Position, RoomName, RoomXY},
+    objects::{Flag, Room},
+    pathfinder::{self, MultiRoomCostResult, SearchOptions},
+    prelude::*,
+};
+
+use crate::movement::{build_cost_matrix, PLAIN_COST, ROAD_COST, SWAMP_COST};
+use crate::sources::adjacent_tiles;
+
+// upgraders work from this far out, so the road only has to reach that close
+const CONTROLLER_ROAD_RANGE: u32 = 3;
+
+// the road tiles planned for a room, along with what they were planned from: every
+// route's ends and how many obstacles were in the way. Planning again only happens when
+// one of those changes
+#[derive(Default)]
+struct CachedRoads {
+    key: Vec<u32>,
+    tiles: Vec<RoomXY>,
+}
+
+thread_local! {
+    static ROAD_PLANS: RefCell<HashMap<RoomName, CachedRoads>> = RefCell::new(HashMap::new());
+}
+
+// places a container construction site next to every source that doesn't already have a
+// container or a pending site for one, returning how many sites were created
+pub fn plan_source_containers(room: &Room) -> usize {
+    let terrain = LocalRoomTerrain::from(room.get_terrain());
+    let structures = room.find(find::STRUCTURES, None);
+    let sites = room.find(find::MY_CONSTRUCTION_SITES, None);
+
+    // roads and ramparts can share a tile with a container, anything else can't
+    let blocked: HashSet<RoomXY> = structures
+        .iter()
+        .filter(|structure| {
+            !matches!(
+                structure.structure_type(),
+                StructureType::Road | StructureType::Rampart
+            )
+        })
+        .map(|structure| structure.pos().xy())
+        .chain(sites.iter().map(|site| site.pos().xy()))
+        .collect();
+    let open = |xy: &RoomXY| terrain.get(*xy) != Terrain::Wall && !blocked.contains(xy);
+
+    let mut placed = 0;
+    for source in room.find(find::SOURCES, None) {
+        let has_container = structures.iter().any(|structure| {
+            matches!(structure, StructureObject::StructureContainer(_))
+                && structure.pos().is_near_to(source.pos())
+        }) || sites.iter().any(|site| {
+            site.structure_type() == StructureType::Container && site.pos().is_near_to(source.pos())
+        });
+
+        if has_container {
+            continue;
+        }
+
+        // prefer the tile with the most open space around it, so haulers have somewhere
+        // to stand while they empty the container
+        let Some(tile) = adjacent_tiles(source.pos().xy())
+            .filter(open)
+            .max_by_key(|&xy| adjacent_tiles(xy).filter(open).count())
+        else {
+            warn!("no open tile for a container by source {}", source.id());
+            continue;
+        };
+
+        match room.create_construction_site(
+            tile.x.u8(),
+            tile.y.u8(),
+            StructureType::Container,
+            None,
+        ) {
+            Ok(()) => placed += 1,
+            Err(e) => warn!("couldn't place container site at {}: {:?}", tile, e),
+        }
+    }
+
+    placed
+}
+
+// covers every spawn, tower and storage with a rampart, returning how many sites were
+// created. Keeping them topped up is left to the repair thresholds
+pub fn plan_ramparts(room: &Room) -> usize {
+    let critical: Vec<RoomXY> = room
+        .find(find::STRUCTURES, None)
+        .iter()
+        .filter(|structure| {
+            matches!(
+                structure,
+                StructureObject::StructureSpawn(_)
+                    | StructureObject::StructureTower(_)
+                    | StructureObject::StructureStorage(_)
+            )
+        })
+        .map(|structure| structure.pos().xy())
+        .collect();
+
+    plan_ramparts_over(room, &critical)
+}
+
+// places a rampart site on each of `tiles` that isn't already covered by a rampart or a
+// site for one
+pub fn plan_ramparts_over(room: &Room, tiles: &[RoomXY]) -> usize {
+    let structures = room.find(find::STRUCTURES, None);
+    let sites = room.find(find::MY_CONSTRUCTION_SITES, None);
+
+    let covered: HashSet<RoomXY> = structures
+        .iter()
+        .filter(|structure| structure.structure_type() == StructureType::Rampart)
+        .map(|structure| structure.pos().xy())
+        .chain(
+            sites
+                .iter()
+                .filter(|site| site.structure_type() == StructureType::Rampart)
+                .map(|site| site.pos().xy()),
+        )
+        .collect();
+
+    let uncovered: Vec<RoomXY> = tiles
+        .iter()
+        .copied()
+        .filter(|xy| !covered.contains(xy))
+        .collect();
+
+    place_sites(room, &uncovered, StructureType::Rampart)
+}
+
+// grey flags mark the ends of wall lines, e.g. across an exit. Flags in a room are paired
+// up in name order and a wall is planned on every walkable tile between each pair
+pub fn plan_wall_lines(room: &Room) -> usize {
+    let mut flags: Vec<Flag> = room
+        .find(find::FLAGS, None)
+        .into_iter()
+        .filter(|flag| flag.color() == Color::Grey)
+        .collect();
+    flags.sort_by_key(|flag| String::from(flag.name()));
+
+    let terrain = LocalRoomTerrain::from(room.get_terrain());
+    let taken: HashSet<RoomXY> = room
+        .find(find::STRUCTURES, None)
+        .iter()
+        .map(|structure| structure.pos().xy())
+        .chain(
+            room.find(find::CONSTRUCTION_SITES, None)
+                .iter()
+                .map(|site| site.pos().xy()),
+        )
+        .collect();
+
+    let tiles: Vec<RoomXY> = flags
+        .chunks_exact(2)
+        .flat_map(|pair| line_between(pair[0].pos().xy(), pair[1].pos().xy()))
+        .filter(|xy| terrain.get(*xy) != Terrain::Wall && !taken.contains(xy))
+        .collect();
+
+    place_sites(room, &tiles, StructureType::Wall)
+}
+
+// extensions go on every other tile in rings around the spawn, out to this range. The
+// tiles between them are left open as walkways, so every extension can be reached and
+// filled from a road. Enough rings for all 60 at RCL 8 with room to skip blocked tiles
+const EXTENSION_STAMP_RANGE: i8 = 6;
+
+// the extension stamp as offsets from the anchor, nearest ring first. The ring right
+// next to the anchor stays clear so the spawn is never boxed in
+pub fn extension_stamp() -> Vec<(i8, i8)> {
+    let range = EXTENSION_STAMP_RANGE;
+    let mut offsets: Vec<(i8, i8)> = (-range..=range)
+        .flat_map(|dx| (-range..=range).map(move |dy| (dx, dy)))
+        .filter(|&(dx, dy)| dx.abs().max(dy.abs()) >= 2 && (dx + dy) % 2 == 0)
+        .collect();
+    offsets.sort_by_key(|&(dx, dy)| (dx.abs().max(dy.abs()), dx.abs() + dy.abs()));
+    offsets
+}
+
+// places extension sites on the stamp around the room's first spawn, as many as the
+// controller level allows on top of the extensions and sites already there. Returns how
+// many sites were created
+pub fn plan_extensions(room: &Room) -> usize {
+    let Some(controller) = room.controller() else {
+        return 0;
+    };
+    let Some(anchor) = room.find(find::MY_SPAWNS, None).into_iter().next() else {
+        return 0;
+    };
+
+    let structures = room.find(find::STRUCTURES, None);
+    let sites = room.find(find::MY_CONSTRUCTION_SITES, None);
+
+    let allowed =
+        StructureType::Extension.controller_structures(controller.level() as u32) as usize;
+    let existing = structures
+        .iter()
+        .filter(|structure| structure.structure_type() == StructureType::Extension)
+        .count()
+        + sites
+            .iter()
+            .filter(|site| site.structure_type() == StructureType::Extension)
+            .count();
+    let wanted = allowed.saturating_sub(existing);
+    if wanted == 0 {
+        return 0;
+    }
+
+    let terrain = LocalRoomTerrain::from(room.get_terrain());
+    let taken: HashSet<RoomXY> = structures
+        .iter()
+        .map(|structure| structure.pos().xy())
+        .chain(sites.iter().map(|site| site.pos().xy()))
+        .collect();
+    // harvesters and upgraders need the tiles next to what they work on
+    let keep_clear: HashSet<RoomXY> = room
+        .find(find::SOURCES, None)
+        .iter()
+        .map(|source| source.pos().xy())
+        .chain(std::iter::once(controller.pos().xy()))
+        .flat_map(adjacent_tiles)
+        .collect();
+
+    let tiles = extension_tiles(anchor.pos().xy(), wanted, |xy| {
+        terrain.get(xy) == Terrain::Wall || taken.contains(&xy) || keep_clear.contains(&xy)
+    });
+
+    place_sites(room, &tiles, StructureType::Extension)
+}
+
+// the first `wanted` tiles of the extension stamp around `anchor` that aren't `blocked`
+fn extension_tiles(anchor: RoomXY, wanted: usize, blocked: impl Fn(RoomXY) -> bool) -> Vec<RoomXY> {
+    extension_stamp()
+        .into_iter()
+        .filter_map(|(dx, dy)| {
+            let x = u8::try_from(anchor.x.u8() as i8 + dx).ok()?;
+            let y = u8::try_from(anchor.y.u8() as i8 + dy).ok()?;
+            // nothing can be built on the exit tiles, and the tiles next to them are
+            // better left for walls
+            if !(2..=47).contains(&x) || !(2..=47).contains(&y) {
+                return None;
+            }
+            RoomXY::try_from((x, y)).ok()
+        })
+        .filter(|&xy| !blocked(xy))
+        .take(wanted)
+        .collect()
+}
+
+// the routes the base's road network has to cover: from the first spawn out to every
+// source, the controller and the storage. Each is the goal and how close to it to get
+fn key_routes(room: &Room) -> Option<(Position, Vec<(Position, u32)>)> {
+    let spawn = room.find(find::MY_SPAWNS, None).into_iter().next()?;
+
+    let mut goals: Vec<(Position, u32)> = room
+        .find(find::SOURCES, None)
+        .iter()
+        .map(|source| (source.pos(), 1))
+        .collect();
+    if let Some(controller) = room.controller() {
+        goals.push((controller.pos(), CONTROLLER_ROAD_RANGE));
+    }
+    if let Some(storage) = room.storage() {
+        goals.push((storage.pos(), 1));
+    }
+    Some((spawn.pos(), goals))
+}
+
+// paths each route in turn, inside this room only. Tiles an earlier route took count as
+// roads for the later ones, so routes heading the same way share a road instead of
+// running side by side
+fn route_tiles(
+    room: &Room,
+    start: Position,
+    goals: &[(Position, u32)],
+    roads: &[RoomXY],
+    obstacles: &[RoomXY],
+) -> Vec<RoomXY> {
+    let room_name = room.name();
+    let mut matrix = build_cost_matrix(roads, obstacles, &[], &HashSet::new());
+    let mut tiles: Vec<RoomXY> = Vec::new();
+
+    for &(goal, range) in goals {
+        let costs = matrix.clone();
+        let options = SearchOptions::new(move |name| {
+            if name == room_name {
+                MultiRoomCostResult::CostMatrix(costs.clone().into())
+            } else {
+                MultiRoomCostResult::Impassable
+            }
+        })
+        .max_rooms(1)
+        .plain_cost(PLAIN_COST)
+        .swamp_cost(SWAMP_COST);
+
+        let search = pathfinder::search(start, goal, range, Some(options));
+        if search.incomplete() {
+            warn!("no road route from {} to {} in {}", start, goal, room_name);
+            continue;
+        }
+        for step in search.path() {
+            let xy = step.xy();
+            if !tiles.contains(&xy) {
+                matrix.set(xy, ROAD_COST);
+                tiles.push(xy);
+            }
+        }
+    }
+    tiles
+}
+
+// places road sites along the paths between the base's key points, wherever there isn't
+// a road or a site yet, returning how many were created. The paths are only searched
+// again once the key points or the obstacles around them change
+pub fn plan_roads(room: &Room) -> usize {
+    let Some((start, goals)) = key_routes(room) else {
+        return 0;
+    };
+
+    let structures = room.find(find::STRUCTURES, None);
+    let sites = room.find(find::MY_CONSTRUCTION_SITES, None);
+
+    // roads can run under containers and ramparts, but nothing else
+    let walkable = |structure_type| {
+        matches!(
+            structure_type,
+            StructureType::Road | StructureType::Container | StructureType::Rampart
+        )
+    };
+    let roads: Vec<RoomXY> = structures
+        .iter()
+        .filter(|structure| structure.structure_type() == StructureType::Road)
+        .map(|structure| structure.pos().xy())
+        .collect();
+    let obstacles: Vec<RoomXY> = structures
+        .iter()
+        .filter(|structure| !walkable(structure.structure_type()))
+        .map(|structure| structure.pos().xy())
+        .chain(
+            sites
+                .iter()
+                .filter(|site| !walkable(site.structure_type()))
+                .map(|site| site.pos().xy()),
+        )
+        .collect();
+
+    let key: Vec<u32> = std::iter::once(start.packed_repr())
+        .chain(goals.iter().map(|(goal, _)| goal.packed_repr()))
+        .chain(std::iter::once(obstacles.len() as u32))
+        .collect();
+
+    let tiles = ROAD_PLANS.with_borrow_mut(|plans| {
+        let cached = plans.entry(room.name()).or_default();
+        if cached.key != key {
+            cached.tiles = route_tiles(room, start, &goals, &roads, &obstacles);
+            cached.key = key;
+        }
+        cached.tiles.clone()
+    });
+
+    let taken: HashSet<RoomXY> = roads
+        .iter()
+        .chain(obstacles.iter())
+        .copied()
+        .chain(
+            sites
+                .iter()
+                .filter(|site| site.structure_type() == StructureType::Road)
+                .map(|site| site.pos().xy()),
+        )
+        .collect();
+    let missing: Vec<RoomXY> = tiles.into_iter().filter(|xy| !taken.contains(xy)).collect();
+
+    place_sites(room, &missing, StructureType::Road)
+}
+
+// every tile on the straight line from `a` to `b`, both ends included
+pub fn line_between(a: RoomXY, b: RoomXY) -> Vec<RoomXY> {
+    let (ax, ay) = (a.x.u8() as i32, a.y.u8() as i32);
+    let (dx, dy) = (b.x.u8() as i32 - ax, b.y.u8() as i32 - ay);
+    let steps = dx.abs().max(dy.abs());
+    if steps == 0 {
+        return vec![a];
+    }
+
+    (0..=steps)
+        .filter_map(|step| {
+            let x = ax + (dx as f32 * step as f32 / steps as f32).round() as i32;
+            let y = ay + (dy as f32 * step as f32 / steps as f32).round() as i32;
+            RoomXY::try_from((u8::try_from(x).ok()?, u8::try_from(y).ok()?)).ok()
+        })
+        .collect()
+}
+
+fn place_sites(room: &Room, tiles: &[RoomXY], structure_type: StructureType) -> usize {
+    let mut placed = 0;
+    for tile in tiles {
+        match room.create_construction_site(tile.x.u8(), tile.y.u8(), structure_type, None) {
+            Ok(()) => placed += 1,
+            // not unlocked at this controller level yet, or we're at the site cap,
+            // nothing to do but wait
+            Err(ErrorCode::RclNotEnough | ErrorCode::Full) => break,
+            Err(e) => warn!(
+                "couldn't place {:?} site at {}: {:?}",
+                structure_type, tile, e
+            ),
+        }
+    }
+
+    if placed > 0 {
+        debug!(
+            "{} {:?} sites at tick {}",
+            placed,
+            structure_type,
+            game::time()
+        );
+    }
+    placed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xy(x: u8, y: u8) -> RoomXY {
+        RoomXY::try_from((x, y)).unwrap()
+    }
+
+    fn ring((dx, dy): (i8, i8)) -> i8 {
+        dx.abs().max(dy.abs())
+    }
+
+    #[test]
+    fn stamp_is_a_checkerboard_clear_of_the_anchor() {
+        let stamp = extension_stamp();
+        assert!(stamp.len() >= 60);
+        assert!(stamp
+            .iter()
+            .all(|&offset| (2..=EXTENSION_STAMP_RANGE).contains(&ring(offset))));
+        // every other tile, so the ones between are left as walkways
+        assert!(stamp.iter().all(|&(dx, dy)| (dx + dy) % 2 == 0));
+        assert!(!stamp.contains(&(2, 1)));
+        assert!(stamp.contains(&(2, 0)) && stamp.contains(&(1, 3)));
+    }
+
+    #[test]
+    fn stamp_fills_the_nearest_rings_first() {
+        let stamp = extension_stamp();
+        assert!(stamp.windows(2).all(|pair| ring(pair[0]) <= ring(pair[1])));
+        // the square's sides before its corners
+        assert_eq!(stamp[0], (-2, 0));
+        assert!(stamp[..8].iter().all(|&offset| ring(offset) == 2));
+    }
+
+    #[test]
+    fn blocked_tiles_are_skipped() {
+        let anchor = xy(25, 25);
+        let open = extension_tiles(anchor, 5, |_| false);
+        assert_eq!(open.len(), 5);
+
+        let blocked = [open[0], open[2]];
+        let tiles = extension_tiles(anchor, 5, |xy| blocked.contains(&xy));
+        assert_eq!(tiles.len(), 5);
+        assert!(tiles.iter().all(|xy| !blocked.contains(xy)));
+        assert_eq!(tiles[..3], [open[1], open[3], open[4]]);
+    }
+
+    #[test]
+    fn stamp_stays_off_the_exits() {
+        let tiles = extension_tiles(xy(3, 3), 100, |_| false);
+        assert!(!tiles.is_empty());
+        assert!(tiles
+            .iter()
+            .all(|tile| (2..=47).contains(&tile.x.u8()) && (2..=47).contains(&tile.y.u8())));
+        assert!(extension_tiles(xy(25, 25), 10, |_| true).is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+use js_sys::Date;
+use log::*;
+use screeps::{
+    constants::{ErrorCode, PowerType, POWER_CREEP_LIFE_TIME},
+    enums::StructureObject,
+    find, game,
+    objects::{AccountPowerCreep, PowerCreep, Room, StructurePowerSpawn},
+    prelude::*,
+};
+use wasm_bindgen::JsCast;
+
+use crate::owned_rooms;
+
+// power creeps head back to a power spawn to renew once they get this low
+const POWER_RENEW_TICKS: u32 = POWER_CREEP_LIFE_TIME / 10;
+
+fn power_spawn(room: &Room) -> Option<StructurePowerSpawn> {
+    room.find(find::MY_STRUCTURES, None)
+        .into_iter()
+        .find_map(|structure| match structure {
+            StructureObject::StructurePowerSpawn(power_spawn) => Some(power_spawn),
+            _ => None,
+        })
+}
+
+// spawns any of our power creeps that aren't out yet, and has the ones that are keep their
+// room's spawn operated. Does nothing at all for accounts without power creeps
+pub fn run_power_creeps() {
+    let shard = game::shard::name();
+    let power_creeps = game::power_creeps();
+    for name in power_creeps.keys() {
+        let Some(account) = power_creeps.get(name.clone()) else {
+            continue;
+        };
+        match account.shard().map(String::from) {
+            None => spawn_power_creep(&name, &account),
+            // out on this shard, so it's a live power creep we can give orders to
+            Some(creep_shard) if creep_shard == shard => {
+                run_power_creep(&account.unchecked_into::<PowerCreep>())
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+fn spawn_power_creep(name: &str, account: &AccountPowerCreep) {
+    // a power creep that died recently has to wait out its spawn cooldown
+    if account
+        .spawn_cooldown_time()
+        .is_some_and(|cooldown| cooldown > Date::now())
+    {
+        return;
+    }
+
+    let Some(power_spawn) = owned_rooms().find_map(|room| power_spawn(&room)) else {
+        return;
+    };
+
+    match account.spawn(&power_spawn) {
+        Ok(()) => info!(
+            "spawning power creep {name} in {}",
+            power_spawn.pos().room_name()
+        ),
+        Err(e) => warn!("couldn't spawn power creep {name}: {:?}", e),
+    }
+}
+
+fn run_power_creep(creep: &PowerCreep) {
+    let Some(room) = creep.room() else {
+        return;
+    };
+
+    if creep
+        .ticks_to_live()
+        .is_some_and(|ticks| ticks < POWER_RENEW_TICKS)
+    {
+        if let Some(power_spawn) = power_spawn(&room) {
+            if creep.pos().is_near_to(power_spawn.pos()) {
+                creep.renew(&power_spawn).unwrap_or_else(|e| {
+                    warn!("couldn't renew power creep {}: {:?}", creep.name(), e);
+                });
+            } else {
+                let _ = creep.move_to(&power_spawn);
+            }
+            return;
+        }
+    }
+
+    // powers only work in rooms whose controller has them switched on
+    let Some(controller) = room.controller().filter(|controller| controller.my()) else {
+        return;
+    };
+    if !controller.is_power_enabled() {
+        if creep.pos().is_near_to(controller.pos()) {
+            creep.enable_room(&controller).unwrap_or_else(|e| {
+                warn!("couldn't enable power in {}: {:?}", room.name(), e);
+            });
+        } else {
+            let _ = creep.move_to(&controller);
+        }
+        return;
+    }
+
+    let powers = creep.powers();
+    let ready = |power| {
+        powers
+            .get(power)
+            .is_some_and(|info: screeps::PowerInfo| info.cooldown() == 0)
+    };
+
+    // ops are what every other power runs on, so keep them coming in. Only one power can
+    // be used a tick
+    if ready(PowerType::GenerateOps) {
+        creep
+            .use_power(PowerType::GenerateOps, None)
+            .unwrap_or_else(|e| warn!("couldn't generate ops: {:?}", e));
+        return;
+    }
+
+    if !ready(PowerType::OperateSpawn) {
+        return;
+    }
+    let Some(spawn) = room.find(find::MY_SPAWNS, None).into_iter().next() else {
+        return;
+    };
+    if creep.pos().in_range_to(spawn.pos(), 3) {
+        match creep.use_power(PowerType::OperateSpawn, Some(spawn.as_ref())) {
+            // not enough ops yet, the next GenerateOps will sort that out
+            Ok(()) | Err(ErrorCode::NotEnough) => {}
+            Err(e) => warn!("couldn't operate spawn in {}: {:?}", room.name(), e),
+        }
+    } else {
+        let _ = creep.move_to(&spawn);
+    }
+}
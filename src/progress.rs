@@ -0,0 +1,84 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use log::*;
+use screeps::game;
+
+use crate::owned_rooms;
+
+// rough wall-clock length of a tick on the official servers, only used to make ETAs
+// readable
+const SECONDS_PER_TICK: f64 = 3.0;
+
+thread_local! {
+    // (tick, progress) as of the last report, keyed by "gcl" or a room name
+    static LAST_SAMPLES: RefCell<HashMap<String, (u32, f64)>> = RefCell::new(HashMap::new());
+}
+
+// ticks left until `total` at the rate progress moved between the two samples, or None
+// if it didn't move forward at all
+pub fn eta_ticks(previous: (u32, f64), current: (u32, f64), total: f64) -> Option<u32> {
+    let elapsed = current.0.checked_sub(previous.0)?;
+    let gained = current.1 - previous.1;
+    if elapsed == 0 || gained <= 0.0 {
+        return None;
+    }
+
+    let rate = gained / elapsed as f64;
+    Some(((total - current.1).max(0.0) / rate).ceil() as u32)
+}
+
+pub fn format_eta(ticks: Option<u32>) -> String {
+    match ticks {
+        Some(ticks) => format!(
+            "{ticks} ticks (~{:.1}h)",
+            ticks as f64 * SECONDS_PER_TICK / 3600.0
+        ),
+        None => "unknown".to_string(),
+    }
+}
+
+pub fn log_progress() {
+    let tick = game::time();
+
+    report(
+        tick,
+        "gcl".to_string(),
+        "gcl".to_string(),
+        game::gcl::level(),
+        game::gcl::progress(),
+        game::gcl::progress_total(),
+    );
+
+    for room in owned_rooms() {
+        let Some(controller) = room.controller() else {
+            continue;
+        };
+
+        // a maxed out controller has nothing left to progress towards
+        if controller.level() == 8 {
+            info!("{} rcl 8", room.name());
+            continue;
+        }
+
+        report(
+            tick,
+            room.name().to_string(),
+            format!("{} rcl", room.name()),
+            controller.level() as u32,
+            controller.progress() as f64,
+            controller.progress_total() as f64,
+        );
+    }
+}
+
+fn report(tick: u32, key: String, label: String, level: u32, progress: f64, total: f64) {
+    let previous = LAST_SAMPLES.with_borrow_mut(|samples| samples.insert(key, (tick, progress)));
+    let eta = previous.and_then(|previous| eta_ticks(previous, (tick, progress), total));
+
+    info!(
+        "{label} {level}: {progress:.0}/{total:.0} ({:.1}%), next level in {}",
+        100.0 * progress / total.max(1.0),
+        format_eta(eta)
+    );
+}
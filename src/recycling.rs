@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use log::*;
+use screeps::{
+    constants::ErrorCode,
+    find, game,
+    objects::{Creep, StructureSpawn},
+    prelude::*,
+};
+
+use crate::body::Role;
+use crate::config::room_config;
+use crate::movement::DefaultMove;
+use crate::spawning::RoomNeeds;
+use crate::{owned_rooms, CreepTarget};
+
+// how often rooms are checked for creeps they no longer need
+pub const RECYCLE_INTERVAL: u32 = 10;
+// surplus creeps with less than this left to live get recycled. Younger ones still have
+// time to be useful if a role opens back up
+const RECYCLE_TTL: u32 = 200;
+
+// which of a role's creeps, as (name, ticks to live), to recycle when the room only wants
+// `desired` of them: the oldest of the surplus, as long as they're close to dying anyway
+pub fn surplus(mut creeps: Vec<(String, u32)>, desired: usize) -> Vec<String> {
+    let extra = creeps.len().saturating_sub(desired);
+    creeps.sort_by_key(|(_, ticks)| *ticks);
+    creeps
+        .into_iter()
+        .take(extra)
+        .filter(|(_, ticks)| *ticks < RECYCLE_TTL)
+        .map(|(name, _)| name)
+        .collect()
+}
+
+// the spawn a creep should go and get recycled at, the closest in its room or failing
+// that any of ours
+pub fn recycle_target(creep: &Creep) -> Option<CreepTarget> {
+    let spawn = creep
+        .room()
+        .and_then(|room| {
+            room.find(find::MY_SPAWNS, None)
+                .into_iter()
+                .min_by_key(|spawn| creep.pos().get_range_to(spawn.pos()))
+        })
+        .or_else(|| game::spawns().values().next())?;
+    Some(CreepTarget::Recycle(spawn.id()))
+}
+
+// sends each room's surplus creeps off to be recycled. Creeps that roam don't belong to
+// any one room, so they're left alone
+pub fn recycle_surplus(creep_targets: &mut HashMap<String, CreepTarget>) {
+    for room in owned_rooms() {
+        let mut by_role: HashMap<Role, Vec<(String, u32)>> = HashMap::new();
+        for creep in room.find(find::MY_CREEPS, None) {
+            let name = creep.name();
+            let role = Role::of_creep(&name);
+            if creep.spawning()
+                || role.roams()
+                || matches!(creep_targets.get(&name), Some(CreepTarget::Recycle(_)))
+            {
+                continue;
+            }
+            let ticks = creep.ticks_to_live().unwrap_or(u32::MAX);
+            by_role.entry(role).or_default().push((name, ticks));
+        }
+
+        let desired = room_config(room.name())
+            .targets
+            .desired(&RoomNeeds::of_room(&room));
+        for (role, creeps) in by_role {
+            let want = desired
+                .iter()
+                .find(|(desired_role, _)| *desired_role == role)
+                .map_or(0, |(_, want)| *want);
+            for name in surplus(creeps, want) {
+                let Some(creep) = game::creeps().get(name.clone()) else {
+                    continue;
+                };
+                if let Some(target) = recycle_target(&creep) {
+                    info!("{name} isn't needed in {}, recycling it", room.name());
+                    creep_targets.insert(name, target);
+                }
+            }
+        }
+    }
+}
+
+// one tick of getting recycled, returning whether the creep should give up on it
+pub fn recycle(creep: &Creep, spawn: &StructureSpawn) -> bool {
+    if !creep.pos().is_near_to(spawn.pos()) {
+        let _ = creep.default_move_to(spawn);
+        return false;
+    }
+
+    match spawn.recycle_creep(creep) {
+        Ok(()) => {
+            info!("recycled {}", creep.name());
+            false
+        }
+        // the spawn's busy spawning something, wait for it
+        Err(ErrorCode::Busy) => false,
+        Err(e) => {
+            warn!("couldn't recycle {}: {:?}", creep.name(), e);
+            true
+        }
+    }
+}
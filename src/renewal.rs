@@ -0,0 +1,239 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use log::*;
+use screeps::{
+    constants::{ErrorCode, Part, CREEP_LIFE_TIME},
+    find, game,
+    local::{ObjectId, Position, RoomName},
+    objects::{Creep, StructureSpawn},
+    prelude::*,
+};
+
+use crate::movement::DefaultMove;
+use crate::{boosts, spawning, CreepTarget};
+
+// creeps whose bodies cost at least this much are worth keeping alive rather than
+// replacing
+const SPECIALIST_BODY_COST: u32 = 1_200;
+// specialists head for a renewal station once they're down to this many ticks
+const STATION_TTL: u32 = 600;
+// and go back to work once they're back up to this many
+const RENEWED_TTL: u32 = CREEP_LIFE_TIME - 100;
+
+thread_local! {
+    // the spawn each specialist is being renewed at, by creep name. A spawn only ever
+    // looks after one at a time
+    static RENEWALS: RefCell<HashMap<String, ObjectId<StructureSpawn>>> = RefCell::new(HashMap::new());
+    // the last tick each room had something queued to spawn, which always comes first
+    static SPAWN_NEEDED: RefCell<HashMap<RoomName, u32>> = RefCell::new(HashMap::new());
+}
+
+// whether a creep with `ttl` ticks left is getting on enough to be renewed, if it's a
+// specialist
+fn due_for_station(ttl: u32) -> bool {
+    ttl < STATION_TTL
+}
+
+// whether a creep with `ttl` ticks left should go and be renewed. Whether it's a
+// `specialist` is only asked once it's old enough to matter, since that means looking
+// over its whole body
+fn wants_station(ttl: u32, specialist: impl FnOnce() -> bool) -> bool {
+    due_for_station(ttl) && specialist()
+}
+
+pub fn renewed_enough(ttl: u32) -> bool {
+    ttl >= RENEWED_TTL
+}
+
+// boosted creeps and big bodies are both expensive to replace. Renewing strips the boosts,
+// but the creep goes back to the labs for them afterwards (see boosts::boosts_lost),
+// which still beats spawning the body all over again. The game refuses to renew anything
+// carrying claim parts. The body is given as (part, boosted) for each part
+fn is_specialist(body: &[(Part, bool)]) -> bool {
+    !body.iter().any(|&(part, _)| part == Part::Claim)
+        && (body.iter().any(|&(_, boosted)| boosted)
+            || body.iter().map(|&(part, _)| part.cost()).sum::<u32>() >= SPECIALIST_BODY_COST)
+}
+
+fn creep_is_specialist(creep: &Creep) -> bool {
+    let body: Vec<(Part, bool)> = creep
+        .body()
+        .iter()
+        .map(|part| (part.part(), part.boost().is_some()))
+        .collect();
+    is_specialist(&body)
+}
+
+// the spawn-adjacent tile kept for renewals: the least open of the spawn's exits, so
+// fresh creeps only step out onto it when there's nowhere else. A spawn with a single
+// way out needs it for spawning and has no station
+fn station(spawn: &StructureSpawn) -> Option<Position> {
+    let directions = spawning::spawn_directions(spawn);
+    if directions.len() < 2 {
+        return None;
+    }
+    let direction = *directions.last()?;
+    spawn.pos().checked_add_direction(direction).ok()
+}
+
+// called by the spawns whenever their room has something queued, so renewals there
+// wait their turn
+pub fn spawn_needed(room_name: RoomName, now: u32) {
+    SPAWN_NEEDED.with_borrow_mut(|needed| needed.insert(room_name, now));
+}
+
+fn spawning_first(room_name: RoomName, now: u32) -> bool {
+    SPAWN_NEEDED.with_borrow(|needed| needed.get(&room_name) == Some(&now))
+}
+
+// a spawn in the creep's room with a station and nobody else renewing at it
+fn free_station(creep: &Creep) -> Option<StructureSpawn> {
+    let room = creep.room()?;
+    RENEWALS.with_borrow_mut(|renewals| {
+        renewals.retain(|name, _| game::creeps().get(name.clone()).is_some());
+        room.find(find::MY_SPAWNS, None)
+            .into_iter()
+            .filter(|spawn| !renewals.values().any(|id| *id == spawn.id()))
+            .filter(|spawn| station(spawn).is_some())
+            .min_by_key(|spawn| creep.pos().get_range_to(spawn.pos()))
+    })
+}
+
+// sends an idle specialist that's getting on to a renewal station and keeps it there,
+// renewed every tick the spawn can spare, until it's close to full again. Returns
+// whether the creep is at or on its way to the station, in which case it's done for
+// the tick
+pub fn run_renewal(creep: &Creep, target: Option<&CreepTarget>) -> bool {
+    let name = creep.name();
+    let Some(ttl) = creep.ticks_to_live() else {
+        return false;
+    };
+
+    let assigned = RENEWALS.with_borrow(|renewals| renewals.get(&name).copied());
+    let spawn = match assigned {
+        // being recycled trumps being renewed
+        Some(_) if matches!(target, Some(CreepTarget::Recycle(_))) => {
+            RENEWALS.with_borrow_mut(|renewals| renewals.remove(&name));
+            return false;
+        }
+        Some(id) => id.resolve(),
+        None => {
+            let idle = matches!(target, None | Some(CreepTarget::Idle(_)));
+            if !idle || !wants_station(ttl, || creep_is_specialist(creep)) {
+                return false;
+            }
+            let Some(spawn) = free_station(creep) else {
+                return false;
+            };
+            info!(
+                "{name} has {ttl} ticks left, off to be renewed at {}",
+                spawn.name()
+            );
+            RENEWALS.with_borrow_mut(|renewals| renewals.insert(name.clone(), spawn.id()));
+            Some(spawn)
+        }
+    };
+
+    let finish = |reason: &str| {
+        info!("{name} {reason}, back to work");
+        RENEWALS.with_borrow_mut(|renewals| renewals.remove(&name));
+        false
+    };
+
+    let Some(spawn) = spawn else {
+        return finish("lost its renewal spawn");
+    };
+    if renewed_enough(ttl) {
+        return finish("is renewed");
+    }
+    let Some(station) = station(&spawn) else {
+        return finish("lost its renewal station");
+    };
+    if creep.pos() != station {
+        let _ = creep.default_move_to(&station);
+        return true;
+    }
+
+    // the spawn's own job comes first, so while it's busy or its room has something to
+    // spawn the creep just waits on the station
+    if spawn.spawning().is_some() || spawning_first(spawn.pos().room_name(), game::time()) {
+        return true;
+    }
+    match spawn.renew_creep(creep) {
+        Ok(()) => {
+            boosts::boosts_lost(creep);
+            true
+        }
+        Err(ErrorCode::NotEnough | ErrorCode::Busy) => true,
+        Err(ErrorCode::Full) => finish("is renewed"),
+        Err(e) => {
+            warn!("couldn't renew {name} at its station: {:?}", e);
+            finish("couldn't be renewed")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn station_is_due_below_the_ttl() {
+        assert!(due_for_station(0));
+        assert!(due_for_station(STATION_TTL - 1));
+        assert!(!due_for_station(STATION_TTL));
+        assert!(!due_for_station(CREEP_LIFE_TIME));
+    }
+
+    #[test]
+    fn renewal_stops_short_of_full() {
+        assert!(!renewed_enough(STATION_TTL));
+        assert!(renewed_enough(RENEWED_TTL));
+        assert!(renewed_enough(CREEP_LIFE_TIME));
+    }
+
+    fn body(parts: &[(Part, usize)], boosted: bool) -> Vec<(Part, bool)> {
+        parts
+            .iter()
+            .flat_map(|&(part, count)| std::iter::repeat((part, boosted)).take(count))
+            .collect()
+    }
+
+    #[test]
+    fn boosted_creeps_go_back_to_renew() {
+        // cheap, but the boosts are worth holding on to
+        let boosted = body(&[(Part::Work, 2), (Part::Move, 1)], true);
+        assert!(is_specialist(&boosted));
+        assert!(wants_station(STATION_TTL - 1, || is_specialist(&boosted)));
+        assert!(!wants_station(STATION_TTL, || is_specialist(&boosted)));
+
+        let plain = body(&[(Part::Work, 2), (Part::Move, 1)], false);
+        assert!(!is_specialist(&plain));
+        assert!(!wants_station(0, || is_specialist(&plain)));
+    }
+
+    #[test]
+    fn big_bodies_are_specialists_but_claimers_never_are() {
+        let big = body(&[(Part::Work, 10), (Part::Move, 5)], false);
+        assert!(is_specialist(&big));
+        let claimer = body(&[(Part::Claim, 2), (Part::Move, 2)], true);
+        assert!(!is_specialist(&claimer));
+    }
+
+    #[test]
+    fn ttl_decides_before_the_body_is_looked_at() {
+        let looked = Cell::new(false);
+        let specialist = || {
+            looked.set(true);
+            true
+        };
+        assert!(!wants_station(STATION_TTL, specialist));
+        assert!(!looked.get());
+
+        assert!(wants_station(STATION_TTL - 1, specialist));
+        assert!(looked.get());
+    }
+}
@@ -0,0 +1,183 @@
+use screeps::{
+    constants::{
+        Terrain, CONTAINER_DECAY, CONTAINER_DECAY_TIME, CONTAINER_DECAY_TIME_OWNED, CONTAINER_HITS,
+        RAMPART_DECAY_AMOUNT, RAMPART_DECAY_TIME, ROAD_DECAY_AMOUNT, ROAD_DECAY_TIME, ROAD_HITS,
+    },
+    enums::StructureObject,
+    prelude::*,
+};
+
+use crate::config::room_config;
+use crate::structures::terrain_at;
+
+// the hits below which a structure should get repaired, or None for structures we
+// don't maintain
+pub fn repair_threshold(structure: &StructureObject) -> Option<u32> {
+    match structure {
+        StructureObject::StructureRoad(road) => {
+            Some(road_repair_threshold(terrain_at(road.pos())?))
+        }
+        StructureObject::StructureRampart(rampart) if rampart.my() => {
+            let room = rampart.room()?;
+            let per_rcl = room_config(room.name()).rampart_hits_per_rcl;
+            let rcl = room.controller()?.level();
+            Some(wall_repair_threshold(rcl, per_rcl).min(rampart.hits_max()))
+        }
+        StructureObject::StructureWall(wall) => {
+            let room = wall.room()?;
+            let per_rcl = room_config(room.name()).wall_hits_per_rcl;
+            let rcl = room.controller()?.level();
+            Some(wall_repair_threshold(rcl, per_rcl).min(wall.hits_max()))
+        }
+        StructureObject::StructureContainer(_) => Some(CONTAINER_HITS * 8 / 10),
+        _ => None,
+    }
+}
+
+// roads get repaired once they drop below 80% of their max hits, which depends on the
+// terrain they were built over
+pub fn road_repair_threshold(terrain: Terrain) -> u32 {
+    let max_hits = match terrain {
+        Terrain::Plain => 5_000,
+        Terrain::Swamp => 25_000,
+        Terrain::Wall => 750_000,
+    };
+    max_hits * 8 / 10
+}
+
+// walls and ramparts scale up with the controller, as there's more energy to spare
+pub fn wall_repair_threshold(rcl: u8, hits_per_rcl: u32) -> u32 {
+    hits_per_rcl * rcl as u32
+}
+
+// ticks until decay alone wears `hits` down to nothing, losing `amount` every `interval`
+// ticks with the next loss `next` ticks away
+pub fn decay_death_ticks(hits: u32, amount: u32, interval: u32, next: u32) -> u32 {
+    let decays = hits.div_ceil(amount.max(1));
+    if decays == 0 {
+        return 0;
+    }
+    next.saturating_add((decays - 1).saturating_mul(interval))
+}
+
+// what a road with `hits_max` loses each time it decays. Roads over swamp and walls have
+// more hits, and lose them as much faster
+fn road_decay_amount(hits_max: u32) -> u32 {
+    ROAD_DECAY_AMOUNT * (hits_max / ROAD_HITS).max(1)
+}
+
+// how often a container decays. They last longer in rooms we own
+fn container_decay_time(owned: bool) -> u32 {
+    if owned {
+        CONTAINER_DECAY_TIME_OWNED
+    } else {
+        CONTAINER_DECAY_TIME
+    }
+}
+
+// how long the structure has left if nobody repairs it. Anything that doesn't decay
+// lasts forever as far as this goes
+pub fn ticks_to_decay_death(structure: &StructureObject) -> u32 {
+    match structure {
+        StructureObject::StructureRoad(road) => decay_death_ticks(
+            road.hits(),
+            road_decay_amount(road.hits_max()),
+            ROAD_DECAY_TIME,
+            road.ticks_to_decay(),
+        ),
+        StructureObject::StructureContainer(container) => {
+            let owned = container
+                .room()
+                .and_then(|room| room.controller())
+                .is_some_and(|controller| controller.my());
+            decay_death_ticks(
+                container.hits(),
+                CONTAINER_DECAY,
+                container_decay_time(owned),
+                container.ticks_to_decay(),
+            )
+        }
+        StructureObject::StructureRampart(rampart) => decay_death_ticks(
+            rampart.hits(),
+            RAMPART_DECAY_AMOUNT,
+            RAMPART_DECAY_TIME,
+            rampart.ticks_to_decay(),
+        ),
+        _ => u32::MAX,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn road_threshold_follows_terrain() {
+        assert_eq!(road_repair_threshold(Terrain::Plain), 4_000);
+        assert_eq!(road_repair_threshold(Terrain::Swamp), 20_000);
+        assert_eq!(road_repair_threshold(Terrain::Wall), 600_000);
+    }
+
+    #[test]
+    fn rampart_threshold_scales_with_level() {
+        assert_eq!(wall_repair_threshold(0, 100_000), 0);
+        assert_eq!(wall_repair_threshold(1, 100_000), 100_000);
+        assert_eq!(wall_repair_threshold(8, 100_000), 800_000);
+    }
+
+    #[test]
+    fn one_hit_road_dies_at_its_next_decay() {
+        let amount = road_decay_amount(ROAD_HITS);
+        assert_eq!(amount, ROAD_DECAY_AMOUNT);
+        assert_eq!(decay_death_ticks(1, amount, ROAD_DECAY_TIME, 7), 7);
+        // swamp roads take five times the decay each time
+        assert_eq!(road_decay_amount(ROAD_HITS * 5), ROAD_DECAY_AMOUNT * 5);
+    }
+
+    #[test]
+    fn full_road_lasts_every_decay() {
+        let decays = ROAD_HITS / ROAD_DECAY_AMOUNT;
+        assert_eq!(
+            decay_death_ticks(ROAD_HITS, ROAD_DECAY_AMOUNT, ROAD_DECAY_TIME, 1),
+            1 + (decays - 1) * ROAD_DECAY_TIME
+        );
+    }
+
+    #[test]
+    fn owned_containers_last_longer() {
+        let ticks = |owned| {
+            decay_death_ticks(
+                CONTAINER_HITS,
+                CONTAINER_DECAY,
+                container_decay_time(owned),
+                1,
+            )
+        };
+        let decays = CONTAINER_HITS / CONTAINER_DECAY;
+        assert_eq!(ticks(false), 1 + (decays - 1) * CONTAINER_DECAY_TIME);
+        assert_eq!(ticks(true), 1 + (decays - 1) * CONTAINER_DECAY_TIME_OWNED);
+        assert!(ticks(true) > ticks(false));
+    }
+
+    #[test]
+    fn rampart_decays_in_steps() {
+        // a partial step still takes a whole decay to go
+        let hits = RAMPART_DECAY_AMOUNT * 3 + 1;
+        assert_eq!(
+            decay_death_ticks(hits, RAMPART_DECAY_AMOUNT, RAMPART_DECAY_TIME, 10),
+            10 + 3 * RAMPART_DECAY_TIME
+        );
+    }
+
+    #[test]
+    fn zero_hits_is_already_dead() {
+        assert_eq!(
+            decay_death_ticks(0, ROAD_DECAY_AMOUNT, ROAD_DECAY_TIME, 50),
+            0
+        );
+        assert_eq!(
+            decay_death_ticks(0, RAMPART_DECAY_AMOUNT, RAMPART_DECAY_TIME, 50),
+            0
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+use rand::{seq::SliceRandom, Rng};
+use screeps::{
+    constants::{ResourceType, StructureType, Terrain},
+    enums::StructureObject,
+    find, game,
+    local::{LocalRoomTerrain, ObjectId, Position, RoomXY},
+    objects::{Creep, Resource, Room, Source, StructureContainer},
+    prelude::*,
+};
+
+use crate::find_cache;
+
+thread_local! {
+    // sources never move and terrain never changes, so each source only gets measured
+    // again when something's built or torn down in its room
+    static SOURCE_TILES: RefCell<HashMap<ObjectId<Source>, MiningTiles>> =
+        RefCell::new(HashMap::new());
+    // mining tiles held by creeps harvesting from them, and which creep holds each
+    static HARVEST_SPOTS: RefCell<HashMap<Position, String>> = RefCell::new(HashMap::new());
+}
+
+// a source's mining tiles, along with how many structures were in the room when they were
+// worked out
+struct MiningTiles {
+    structure_count: usize,
+    tiles: Vec<Position>,
+}
+
+// the (up to) eight tiles surrounding a position, skipping anything off the room's edge
+pub fn adjacent_tiles(xy: RoomXY) -> impl Iterator<Item = RoomXY> {
+    let (x, y) = (xy.x.u8() as i16, xy.y.u8() as i16);
+
+    (-1..=1)
+        .cartesian_product(-1..=1)
+        .filter(|&offset| offset != (0, 0))
+        .filter_map(move |(dx, dy)| {
+            let x = u8::try_from(x + dx).ok()?;
+            let y = u8::try_from(y + dy).ok()?;
+            RoomXY::try_from((x, y)).ok()
+        })
+}
+
+// the tiles a creep can stand on to mine the source at `source`, i.e. the tiles around it
+// that aren't walls or `blocked` by something built on them. How many there are is how
+// many creeps can mine it at once
+pub fn source_tiles(
+    source: RoomXY,
+    terrain: &LocalRoomTerrain,
+    blocked: &HashSet<RoomXY>,
+) -> Vec<RoomXY> {
+    adjacent_tiles(source)
+        .filter(|&xy| terrain.get(xy) != Terrain::Wall && !blocked.contains(&xy))
+        .collect()
+}
+
+// the biggest pile of energy a drop miner has left lying next to any of the room's
+// sources
+pub fn dropped_at_sources(room: &Room) -> Option<Resource> {
+    room.find(find::SOURCES, None)
+        .iter()
+        .flat_map(|source| source.pos().find_in_range(find::DROPPED_RESOURCES, 1))
+        .filter(|resource| resource.resource_type() == ResourceType::Energy)
+        .max_by_key(|resource| resource.amount())
+}
+
+// the container a miner for this source would stand on, if one's been built
+pub fn source_container(
+    source: &Source,
+    structures: &[StructureObject],
+) -> Option<StructureContainer> {
+    structures.iter().find_map(|structure| match structure {
+        StructureObject::StructureContainer(container)
+            if container.pos().is_near_to(source.pos()) =>
+        {
+            Some(container.clone())
+        }
+        _ => None,
+    })
+}
+
+fn cached_source_tiles(source: &Source) -> Vec<Position> {
+    let Some(room) = source.room() else {
+        return Vec::new();
+    };
+    let structures = find_cache::structures(&room);
+
+    SOURCE_TILES.with_borrow_mut(|cache| {
+        if let Some(cached) = cache.get(&source.id()) {
+            if cached.structure_count == structures.len() {
+                return cached.tiles.clone();
+            }
+        }
+
+        let room_name = room.name();
+        let terrain = game::map::get_room_terrain(room_name);
+        let blocked: HashSet<RoomXY> = structures
+            .iter()
+            .filter(|structure| {
+                !matches!(
+                    structure.structure_type(),
+                    StructureType::Road | StructureType::Container | StructureType::Rampart
+                )
+            })
+            .map(|structure| structure.pos().xy())
+            .collect();
+        let tiles: Vec<Position> = source_tiles(
+            source.pos().xy(),
+            &LocalRoomTerrain::from(terrain),
+            &blocked,
+        )
+        .into_iter()
+        .map(|xy| Position::new(xy.x, xy.y, room_name))
+        .collect();
+        cache.insert(
+            source.id(),
+            MiningTiles {
+                structure_count: structures.len(),
+                tiles: tiles.clone(),
+            },
+        );
+        tiles
+    })
+}
+
+// whether `pos` is still somewhere a creep can stand to mine `source`. Building over a
+// mining tile takes it away from whoever had it
+pub fn is_mining_tile(source: &Source, pos: Position) -> bool {
+    cached_source_tiles(source).contains(&pos)
+}
+
+// replaces the held mining tiles with the ones creeps' targets still point at. Anything
+// held by a creep that's since died or moved on to something else is free again
+pub fn sync_harvest_spots(held: impl Iterator<Item = (Position, String)>) {
+    HARVEST_SPOTS.set(held.collect());
+}
+
+pub fn has_free_harvest_spot(source: &Source) -> bool {
+    HARVEST_SPOTS.with_borrow(|spots| {
+        cached_source_tiles(source)
+            .iter()
+            .any(|tile| !spots.contains_key(tile))
+    })
+}
+
+// hands `creep` the free tile around `source` closest to it, None if they're all taken
+pub fn reserve_harvest_spot(source: &Source, creep: &Creep) -> Option<Position> {
+    HARVEST_SPOTS.with_borrow_mut(|spots| {
+        let tile = cached_source_tiles(source)
+            .into_iter()
+            .filter(|tile| !spots.contains_key(tile))
+            .min_by_key(|tile| (creep.pos().get_range_to(*tile), tile.x(), tile.y()))?;
+        spots.insert(tile, creep.name());
+        Some(tile)
+    })
+}
+
+// the candidate with the lowest load, with the rng only breaking ties between equally
+// loaded candidates. An empty slice gives None rather than panicking the way
+// gen_range(0..0) would
+pub fn least_loaded<'a, T>(
+    candidates: &'a [T],
+    load: impl Fn(&T) -> usize,
+    rng: &mut impl Rng,
+) -> Option<&'a T> {
+    let least_load = candidates.iter().map(&load).min()?;
+    let tied: Vec<&T> = candidates
+        .iter()
+        .filter(|candidate| load(candidate) == least_load)
+        .collect();
+
+    tied.choose(rng).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use super::*;
+
+    fn xy(x: u8, y: u8) -> RoomXY {
+        RoomXY::try_from((x, y)).unwrap()
+    }
+
+    fn terrain_with_walls(walls: &[(u8, u8)]) -> LocalRoomTerrain {
+        let mut bits = Box::new([0; 2500]);
+        for &(x, y) in walls {
+            bits[y as usize * 50 + x as usize] = 1;
+        }
+        LocalRoomTerrain::new_from_bits(bits)
+    }
+
+    #[test]
+    fn no_sources_picks_nothing() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let sources: [usize; 0] = [];
+        assert_eq!(least_loaded(&sources, |&load| load, &mut rng), None);
+    }
+
+    #[test]
+    fn least_loaded_source_wins() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        assert_eq!(least_loaded(&[3, 1, 2], |&load| load, &mut rng), Some(&1));
+    }
+
+    #[test]
+    fn open_source_has_eight_tiles() {
+        let terrain = terrain_with_walls(&[]);
+        assert_eq!(source_tiles(xy(10, 10), &terrain, &HashSet::new()).len(), 8);
+    }
+
+    #[test]
+    fn walls_take_tiles_away() {
+        let terrain = terrain_with_walls(&[(9, 9), (10, 9), (11, 9), (11, 10)]);
+        let tiles = source_tiles(xy(10, 10), &terrain, &HashSet::new());
+        assert_eq!(tiles.len(), 4);
+        assert!(!tiles.contains(&xy(10, 9)));
+        assert!(tiles.contains(&xy(9, 11)));
+    }
+
+    #[test]
+    fn walled_in_source_has_none() {
+        let walls: Vec<(u8, u8)> = adjacent_tiles(xy(10, 10))
+            .map(|tile| (tile.x.u8(), tile.y.u8()))
+            .collect();
+        assert!(source_tiles(xy(10, 10), &terrain_with_walls(&walls), &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn built_over_tiles_are_left_out() {
+        let terrain = terrain_with_walls(&[(9, 9)]);
+        let blocked: HashSet<RoomXY> = [xy(11, 11), xy(10, 11)].into_iter().collect();
+        let tiles = source_tiles(xy(10, 10), &terrain, &blocked);
+        assert_eq!(tiles.len(), 5);
+        assert!(!tiles.contains(&xy(11, 11)));
+    }
+}
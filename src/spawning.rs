@@ -0,0 +1,941 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use js_sys::{Object, Reflect};
+use log::*;
+use screeps::{
+    constants::{Direction, ErrorCode, Part, ResourceType, StructureType, Terrain},
+    enums::StructureObject,
+    find,
+    local::{LocalRoomTerrain, ObjectId, RoomName, RoomXY},
+    objects::{Creep, Room, StructureSpawn},
+    prelude::*,
+    SpawnOptions,
+};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+use crate::body::{build_body, build_hauler_body, validate_body, Role};
+use crate::defense::{self, is_threat, ThreatAssessment, ThreatLevel};
+use crate::energy::{self, adjusted};
+use crate::movement::DIRECTIONS;
+use crate::sources::{adjacent_tiles, source_container};
+use crate::{find_cache, flags, intel, nukes, upgrading, SumParts};
+
+// below this much bucket, every new creep's pathfinding just digs the hole deeper
+pub const BUCKET_SPAWN_FLOOR: i32 = 2_000;
+// above this, the bucket is close enough to full that spare cpu is better spent on creeps
+pub const BUCKET_SURPLUS: i32 = 9_500;
+// how far past the normal creep cap a surplus bucket lets us go
+pub const SURPLUS_EXTRA_CREEPS: usize = 2;
+// how long the front of a room's queue holds its energy for the full body before it
+// settles for whatever the room can pay for right now
+const SPAWN_RESERVATION_TIMEOUT: u32 = 300;
+// a room with fewer creeps than this in it is just getting started, and spawns workers
+// out of whatever energy it has
+pub const BOOTSTRAP_WORKERS: usize = 2;
+// a spawn that still can't afford what it's after this long, even settling for less,
+// most likely has extensions nobody can get to, so until its room fills past what the
+// spawn holds by itself it sizes bodies off its own store
+const SPAWN_STUCK_TICKS: u32 = 1_000;
+
+pub fn should_spawn(bucket: i32, current: usize, cap: usize) -> bool {
+    if bucket < BUCKET_SPAWN_FLOOR {
+        return false;
+    }
+
+    if bucket >= BUCKET_SURPLUS {
+        return current < cap + SURPLUS_EXTRA_CREEPS;
+    }
+
+    current < cap
+}
+
+// the economy roles' counts for a controller level
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoleCounts {
+    // per source with a container, or per source at all when drop mining
+    pub haulers: usize,
+    pub upgraders: usize,
+    pub builders: usize,
+}
+
+const fn counts(haulers: usize, upgraders: usize, builders: usize) -> RoleCounts {
+    RoleCounts {
+        haulers,
+        upgraders,
+        builders,
+    }
+}
+
+// indexed by controller level. Young rooms can't feed many creeps, mid levels have the
+// most to build and upgrade, and at level 8 upgrading is capped so one upgrader does,
+// the static upgrader once the controller has a store. Harvesters aren't in here, drop
+// mining or container mining takes over every source from the start
+const LEVEL_COUNTS: [RoleCounts; 9] = [
+    counts(1, 1, 1),
+    counts(1, 1, 1),
+    counts(1, 2, 2),
+    counts(1, 2, 2),
+    counts(2, 2, 2),
+    counts(2, 3, 2),
+    counts(2, 3, 1),
+    counts(2, 2, 1),
+    counts(2, 1, 1),
+];
+
+// the economy a room at `rcl` wants, with no builders unless there's something to build
+pub fn desired_counts(rcl: u8, has_construction: bool) -> RoleCounts {
+    let counts = LEVEL_COUNTS[(rcl as usize).min(LEVEL_COUNTS.len() - 1)];
+    RoleCounts {
+        builders: if has_construction { counts.builders } else { 0 },
+        ..counts
+    }
+}
+
+// what a room's desired counts are worked out from
+pub struct RoomNeeds {
+    // 0 for a room without a controller of ours
+    pub controller_level: u8,
+    pub sources: usize,
+    // sources with a container next to them, which get a miner and a hauler instead of
+    // harvesters
+    pub container_sources: usize,
+    pub construction_sites: usize,
+    pub claim_flags: usize,
+    pub dismantle_flags: usize,
+    pub needs_scouting: bool,
+    // hostiles that can do damage, plus hostile structures flagged for clearing
+    pub threats: usize,
+    // a level 8 controller with a container or link to upgrade out of
+    pub controller_store: bool,
+    // how far harvester and hauler counts get pushed from the rules by how starved or
+    // saturated the room's energy has been
+    pub energy_adjustment: i32,
+    // a nuke is on its way, so only the roles that keep the room running are wanted
+    pub nuke_incoming: bool,
+    // hostiles in the room right now, which get defenders spawned ahead of everything else
+    pub defense: ThreatAssessment,
+}
+
+impl RoomNeeds {
+    pub fn of_room(room: &Room) -> Self {
+        let structures = find_cache::structures(room);
+        let sources = room.find(find::SOURCES, None);
+        RoomNeeds {
+            controller_level: room
+                .controller()
+                .filter(|controller| controller.my())
+                .map_or(0, |controller| controller.level()),
+            sources: sources.len(),
+            container_sources: sources
+                .iter()
+                .filter(|source| source_container(source, &structures).is_some())
+                .count(),
+            construction_sites: room.find(find::MY_CONSTRUCTION_SITES, None).len(),
+            claim_flags: flags::claim_flags().count(),
+            dismantle_flags: flags::dismantle_flags().count(),
+            needs_scouting: intel::next_room_to_scout().is_some(),
+            threats: room
+                .find(find::HOSTILE_CREEPS, None)
+                .iter()
+                .filter(|creep| {
+                    let parts: Vec<Part> = creep.body().iter().map(|part| part.part()).collect();
+                    is_threat(&parts)
+                })
+                .count()
+                + flags::clear_flags().count(),
+            controller_store: room
+                .controller()
+                .is_some_and(|controller| controller.level() == 8)
+                && upgrading::controller_store(room).is_some(),
+            energy_adjustment: energy::adjustment(room.name()),
+            nuke_incoming: nukes::nuke_incoming(room),
+            defense: defense::assess_room(room),
+        }
+    }
+}
+
+// how many of each role a room wants. Haulers, upgraders and builders come from the
+// controller level's row of the table above unless set here
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpawnRules {
+    // per source without a container, unless drop mining
+    pub harvesters_per_source: usize,
+    // per source with a container, or per source at all when drop mining
+    pub miners_per_container: usize,
+    pub haulers_per_container: Option<usize>,
+    // sources without a container get a miner dropping its energy on the ground for the
+    // haulers, instead of harvesters walking back and forth
+    pub drop_mining: bool,
+    pub upgraders: Option<usize>,
+    // only wanted at level 8, once the controller has a store to upgrade out of
+    pub static_upgraders: usize,
+    // only wanted while there's something to build
+    pub builders: Option<usize>,
+    // per claim flag
+    pub claimers: usize,
+    // only wanted while something's flagged to be dismantled
+    pub dismantlers: usize,
+    // only wanted while a neighbouring room is due a visit
+    pub scouts: usize,
+    // only wanted while there's something to fight
+    pub ranged_attackers: usize,
+}
+
+impl Default for SpawnRules {
+    fn default() -> Self {
+        SpawnRules {
+            harvesters_per_source: 2,
+            miners_per_container: 1,
+            haulers_per_container: None,
+            drop_mining: true,
+            upgraders: None,
+            static_upgraders: 1,
+            builders: None,
+            claimers: 1,
+            dismantlers: 1,
+            scouts: 1,
+            ranged_attackers: 1,
+        }
+    }
+}
+
+impl SpawnRules {
+    // desired counts per role, highest priority first
+    pub fn desired(&self, needs: &RoomNeeds) -> Vec<(Role, usize)> {
+        let mined_sources = if self.drop_mining {
+            needs.sources
+        } else {
+            needs.container_sources
+        };
+        let harvested_sources = needs.sources.saturating_sub(mined_sources);
+        let (ranged_defenders, melee_defenders) = needs.defense.defenders();
+        let under_attack = needs.defense.level > ThreatLevel::Harmless;
+        let has_construction = needs.construction_sites > 0;
+        let counts = desired_counts(needs.controller_level, has_construction);
+        let haulers_per_container = self.haulers_per_container.unwrap_or(counts.haulers);
+        let builders = if has_construction && !under_attack {
+            self.builders.unwrap_or(counts.builders)
+        } else {
+            0
+        };
+        let static_upgraders = if needs.controller_store {
+            self.static_upgraders
+        } else {
+            0
+        };
+        let scouts = if needs.needs_scouting { self.scouts } else { 0 };
+        // builders stay on while a nuke is incoming, they're what gets the ramparts up
+        // and under attack only the roles that pay for defenders keep going
+        // the static upgrader is one of the table's upgraders, not one on top of them
+        let upgraders = self
+            .upgraders
+            .unwrap_or(counts.upgraders.saturating_sub(static_upgraders));
+        let (upgraders, static_upgraders, claimers, dismantlers, scouts) =
+            if needs.nuke_incoming || under_attack {
+                (0, 0, 0, 0, 0)
+            } else {
+                (
+                    upgraders,
+                    static_upgraders,
+                    self.claimers * needs.claim_flags,
+                    if needs.dismantle_flags > 0 {
+                        self.dismantlers
+                    } else {
+                        0
+                    },
+                    scouts,
+                )
+            };
+        let ranged_attackers = if needs.threats > 0 {
+            self.ranged_attackers
+        } else {
+            0
+        }
+        .max(ranged_defenders);
+
+        let mut desired = vec![
+            (
+                Role::Harvester,
+                adjusted(
+                    self.harvesters_per_source * harvested_sources,
+                    needs.energy_adjustment,
+                ),
+            ),
+            (Role::Miner, self.miners_per_container * mined_sources),
+            (
+                Role::Hauler,
+                adjusted(
+                    haulers_per_container * mined_sources,
+                    needs.energy_adjustment,
+                ),
+            ),
+            (Role::RangedAttacker, ranged_attackers),
+            (Role::Defender, melee_defenders),
+            (Role::Upgrader, upgraders),
+            (Role::StaticUpgrader, static_upgraders),
+            (Role::Builder, builders),
+            (Role::Claimer, claimers),
+            (Role::Dismantler, dismantlers),
+            (Role::Scout, scouts),
+        ];
+        // defenders jump the queue until the threat's gone
+        if under_attack {
+            desired.sort_by_key(|(role, _)| !matches!(role, Role::RangedAttacker | Role::Defender));
+        }
+        desired
+    }
+}
+
+// the roles a room is missing, highest priority at the front
+pub struct SpawnQueue(VecDeque<Role>);
+
+impl SpawnQueue {
+    pub fn new(desired: &[(Role, usize)], live: &HashMap<Role, usize>) -> Self {
+        SpawnQueue(
+            desired
+                .iter()
+                .flat_map(|&(role, want)| {
+                    let have = live.get(&role).copied().unwrap_or(0);
+                    std::iter::repeat(role).take(want.saturating_sub(have))
+                })
+                .collect(),
+        )
+    }
+
+    pub fn for_room(room: &Room, rules: &SpawnRules, live: &HashMap<Role, usize>) -> Self {
+        SpawnQueue::new(&rules.desired(&RoomNeeds::of_room(room)), live)
+    }
+
+    pub fn retain(&mut self, keep: impl FnMut(&Role) -> bool) {
+        self.0.retain(keep);
+    }
+
+    pub fn front(&self) -> Option<Role> {
+        self.0.front().copied()
+    }
+
+    // hands out the front of the queue to up to `spawns` spawns, one queue entry each, so
+    // a role short by more than one can go to several spawns at once. Keeps going for as
+    // long as `pick` finds a body the energy left over from the ones before it can pay
+    // for. Stops at the first one it can't rather than let something less important jump
+    // ahead
+    pub fn assign(
+        &self,
+        spawns: usize,
+        mut energy: u32,
+        pick: impl Fn(Role, u32) -> Option<Vec<Part>>,
+    ) -> Vec<(Role, Vec<Part>)> {
+        let mut assigned = Vec::new();
+        for &role in self.0.iter().take(spawns) {
+            let Some(body) = pick(role, energy) else {
+                break;
+            };
+            energy -= body.sum_parts().min(energy);
+            assigned.push((role, body));
+        }
+        assigned
+    }
+}
+
+// which spawn tier applies to a room with `creeps` creeps around. While there are only a
+// few, the tiers keep bodies cheap so a struggling room recovers quickly. Past the last
+// threshold (only allowed when there's cpu to burn) it sticks with the biggest tier. The
+// level tiers do the same by controller level, so young rooms get plenty of cheap bodies
+// spread over the sources early on, and past the last of those there's no budget but the
+// room's capacity
+pub struct SpawnPlan<'a> {
+    tiers: &'a [(usize, Option<u32>)],
+    creeps: usize,
+    level_tiers: &'a [(u8, Option<u32>)],
+    level: u8,
+    // whether haulers can count on roads, and so get by on half the moves
+    roads: bool,
+}
+
+impl<'a> SpawnPlan<'a> {
+    pub fn new(
+        tiers: &'a [(usize, Option<u32>)],
+        creeps: usize,
+        level_tiers: &'a [(u8, Option<u32>)],
+        level: u8,
+        roads: bool,
+    ) -> Self {
+        SpawnPlan {
+            tiers,
+            creeps,
+            level_tiers,
+            level,
+            roads,
+        }
+    }
+
+    // the body to spawn for `role`, sized off the tier's budget but never past
+    // `capacity`, the most the room's spawns and extensions can hold. Energy banked past
+    // that, say before an extension got destroyed, doesn't make for a bigger body. None
+    // unless the `available` energy covers it right now
+    pub fn pick(&self, role: Role, capacity: u32, available: u32) -> Option<Vec<Part>> {
+        let creep_budget = self
+            .tiers
+            .iter()
+            .find(|(threshold, _)| self.creeps < *threshold)
+            .or(self.tiers.last())
+            .and_then(|(_, budget)| *budget);
+        let level_budget = self
+            .level_tiers
+            .iter()
+            .find(|(level, _)| self.level < *level)
+            .and_then(|(_, budget)| *budget);
+        let budget = creep_budget
+            .into_iter()
+            .chain(level_budget)
+            .fold(capacity, u32::min);
+
+        let body = match role {
+            Role::Hauler => build_hauler_body(budget, self.roads),
+            _ => build_body(role, budget),
+        };
+        (!body.is_empty() && body.sum_parts() <= available).then_some(body)
+    }
+}
+
+// the directions a spawn sends new creeps out in, along with how many structures were in
+// the room when they were worked out
+#[derive(Default)]
+struct SpawnExits {
+    structure_count: usize,
+    directions: Vec<Direction>,
+}
+
+// energy a room is holding back for the body at the front of its queue, and the tick it
+// started waiting on it
+#[derive(Clone, Copy, Debug)]
+struct Reservation {
+    role: Role,
+    cost: u32,
+    since: u32,
+}
+
+thread_local! {
+    // spawn exits only change when something gets built or torn down
+    static SPAWN_EXITS: RefCell<HashMap<ObjectId<StructureSpawn>, SpawnExits>> = RefCell::new(HashMap::new());
+
+    static RESERVATIONS: RefCell<HashMap<RoomName, Reservation>> = RefCell::new(HashMap::new());
+
+    // the tick, and how many of each role have been spawned on it across every room so
+    // far. Spawned creeps don't show up in game::creeps until the next tick
+    static SPAWNED: RefCell<(u32, HashMap<Role, usize>)> = RefCell::new((0, HashMap::new()));
+
+    static STUCK_SPAWNS: RefCell<HashMap<String, StuckSpawn>> = RefCell::new(HashMap::new());
+}
+
+// how long a spawn has been unable to afford the front of its queue
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct StuckSpawn {
+    // the first tick of the current run of ticks it couldn't, and the last one
+    since: u32,
+    last: u32,
+    // whether it's been at it long enough to fall back to the spawn's own store. That
+    // holds until the room shows it can fill past what the spawn holds
+    stuck: bool,
+}
+
+impl StuckSpawn {
+    fn new(now: u32) -> Self {
+        StuckSpawn {
+            since: now,
+            last: now,
+            stuck: false,
+        }
+    }
+
+    // counts another tick of not affording anything, returning whether it's just become
+    // stuck. Until then, any tick it wasn't counted on (spawning, throttled by the bucket,
+    // bootstrapping, an emergency spawn) starts the count over
+    fn count(&mut self, now: u32) -> bool {
+        if !self.stuck && now > self.last + 1 {
+            self.since = now;
+        }
+        self.last = now;
+        if self.stuck || now - self.since < SPAWN_STUCK_TICKS {
+            return false;
+        }
+        self.stuck = true;
+        true
+    }
+}
+
+// counts another tick of `spawn` not affording the front of its queue, returning whether
+// it's been stuck long enough to fall back to what the spawn alone can pay for
+pub fn spawn_stuck(spawn: &StructureSpawn, now: u32) -> bool {
+    let name = String::from(spawn.name());
+    STUCK_SPAWNS.with_borrow_mut(|stuck| {
+        let stuck = stuck.entry(name.clone()).or_insert(StuckSpawn::new(now));
+        if stuck.count(now) {
+            warn!(
+                "{name} hasn't afforded a body in {SPAWN_STUCK_TICKS} ticks, though its room \
+                 reports {} capacity. Its extensions may be out of reach, sticking to what \
+                 the spawn can pay for alone",
+                spawn
+                    .room()
+                    .map_or(0, |room| room.energy_capacity_available())
+            );
+        }
+        stuck.stuck
+    })
+}
+
+// forgets a stuck spawn once its room has `energy_available` past what the spawn holds by
+// itself, so the extensions are being filled after all
+pub fn spawn_unstuck(spawn: &StructureSpawn, energy_available: u32) {
+    let spawn_capacity = spawn.store().get_capacity(Some(ResourceType::Energy));
+    if energy_available > spawn_capacity {
+        STUCK_SPAWNS.with_borrow_mut(|stuck| stuck.remove(&String::from(spawn.name())));
+    }
+}
+
+// how many of each role have started spawning this tick, in any room
+pub fn spawned_roles(now: u32) -> HashMap<Role, usize> {
+    SPAWNED.with_borrow(|(tick, spawned)| {
+        if *tick == now {
+            spawned.clone()
+        } else {
+            HashMap::new()
+        }
+    })
+}
+
+pub fn spawned_this_tick(now: u32) -> usize {
+    spawned_roles(now).values().sum()
+}
+
+pub fn note_spawned(now: u32, role: Role) {
+    SPAWNED.with_borrow_mut(|(tick, spawned)| {
+        if *tick != now {
+            *tick = now;
+            spawned.clear();
+        }
+        *spawned.entry(role).or_default() += 1;
+    });
+
+    let _ = Reflect::set(
+        &screeps::memory::ROOT,
+        &JsValue::from_str("spawn_counter"),
+        &JsValue::from(spawn_counter().wrapping_add(1)),
+    );
+}
+
+// kept in Memory.spawn_counter and only ever counting up, so unlike anything kept on the
+// heap it carries on from where it was after a global reset
+fn spawn_counter() -> u32 {
+    Reflect::get(&screeps::memory::ROOT, &JsValue::from_str("spawn_counter"))
+        .ok()
+        .and_then(|counter| counter.as_f64())
+        .map_or(0, |counter| counter as u32)
+}
+
+// the room a creep was spawned in, kept in its memory by `try_spawn`. Creeps spawned
+// before that are taken to be at home wherever they are
+pub fn home_room(creep: &Creep) -> Option<RoomName> {
+    Reflect::get(&creep.memory(), &JsValue::from_str("home"))
+        .ok()
+        .and_then(|home| home.as_string())
+        .and_then(|home| home.parse().ok())
+        .or_else(|| creep.room().map(|room| room.name()))
+}
+
+// a name no creep has had before, for a new `role`. Spawning one moves the counter on
+pub fn creep_name(role: Role, now: u32) -> String {
+    format!("{}-{}-{}", role.name(), now, spawn_counter())
+}
+
+// holds `cost` energy in the room for `role`, so nothing smaller gets spawned out of it
+// while the room fills up. Returns how long it's been waiting, which starts over whenever
+// the front of the queue changes
+pub fn reserve_spawn_energy(room_name: RoomName, role: Role, cost: u32, now: u32) -> u32 {
+    RESERVATIONS.with_borrow_mut(|reservations| {
+        let reservation = reservations.entry(room_name).or_insert(Reservation {
+            role,
+            cost,
+            since: now,
+        });
+        if reservation.role != role || reservation.cost != cost {
+            *reservation = Reservation {
+                role,
+                cost,
+                since: now,
+            };
+        }
+        if reservation.since == now {
+            debug!("{room_name} reserving {cost} energy for a {}", role.name());
+        }
+        now - reservation.since
+    })
+}
+
+// whether a room with `energy` keeps holding it for a body costing `cost`, having waited
+// `waited` ticks on it so far. Past SPAWN_RESERVATION_TIMEOUT it lets go and settles for
+// whatever it can pay for
+pub fn hold_spawn_energy(energy: u32, cost: u32, waited: u32) -> bool {
+    energy < cost && waited < SPAWN_RESERVATION_TIMEOUT
+}
+
+pub fn release_spawn_energy(room_name: RoomName) {
+    RESERVATIONS.with_borrow_mut(|reservations| reservations.remove(&room_name));
+}
+
+// the energy a room is currently holding back for its next spawn
+pub fn reserved_spawn_energy(room_name: RoomName) -> u32 {
+    RESERVATIONS.with_borrow(|reservations| {
+        reservations
+            .get(&room_name)
+            .map_or(0, |reservation| reservation.cost)
+    })
+}
+
+// the walkable tiles around the spawn, roomiest first, so a fresh creep steps out
+// somewhere it can keep moving from instead of plugging the spawn. Empty if everything's
+// blocked, which leaves the choice to the game
+pub fn spawn_directions(spawn: &StructureSpawn) -> Vec<Direction> {
+    let Some(room) = spawn.room() else {
+        return Vec::new();
+    };
+    let structures = find_cache::structures(&room);
+
+    SPAWN_EXITS.with_borrow_mut(|cache| {
+        let exits = cache.entry(spawn.id()).or_default();
+        if exits.structure_count != structures.len() {
+            *exits = SpawnExits {
+                structure_count: structures.len(),
+                directions: open_directions(spawn, &room, &structures),
+            };
+        }
+        exits.directions.clone()
+    })
+}
+
+fn open_directions(
+    spawn: &StructureSpawn,
+    room: &Room,
+    structures: &[StructureObject],
+) -> Vec<Direction> {
+    let terrain = LocalRoomTerrain::from(room.get_terrain());
+    let blocked: HashSet<RoomXY> = structures
+        .iter()
+        .filter(|structure| {
+            !matches!(
+                structure.structure_type(),
+                StructureType::Road | StructureType::Container | StructureType::Rampart
+            )
+        })
+        .map(|structure| structure.pos().xy())
+        .collect();
+    let open = |xy: &RoomXY| terrain.get(*xy) != Terrain::Wall && !blocked.contains(xy);
+
+    let mut directions: Vec<(Direction, usize)> = DIRECTIONS
+        .into_iter()
+        .filter_map(|direction| {
+            let pos = spawn.pos().checked_add_direction(direction).ok()?;
+            let xy = pos.xy();
+            (pos.room_name() == room.name() && open(&xy))
+                .then(|| (direction, adjacent_tiles(xy).filter(open).count()))
+        })
+        .collect();
+    directions.sort_by_key(|&(_, openness)| std::cmp::Reverse(openness));
+
+    directions
+        .into_iter()
+        .map(|(direction, _)| direction)
+        .collect()
+}
+
+// how many alternative names to try when the one we picked is already taken
+const NAME_RETRIES: u32 = 3;
+
+// spawns the body under `name`, falling back to `name-1`, `name-2`... if it's taken.
+// Comes back with the name actually used, or None when the spawn just can't right now
+// (already spawning, or short on energy). Anything else is a real problem. Bodies the
+// game would refuse are logged and never sent to it
+pub fn try_spawn(
+    spawn: &StructureSpawn,
+    body: &[Part],
+    name: &str,
+) -> Result<Option<String>, ErrorCode> {
+    if let Err(e) = validate_body(body) {
+        error!("spawn {} won't spawn {name}: {e}", spawn.name());
+        return Ok(None);
+    }
+
+    // every creep remembers the room it was spawned for, whichever one it's in
+    let memory = Object::new();
+    let _ = Reflect::set(
+        &memory,
+        &JsValue::from_str("home"),
+        &JsValue::from_str(&spawn.pos().room_name().to_string()),
+    );
+    let mut options = SpawnOptions::new().memory(memory.into());
+    let directions = spawn_directions(spawn);
+    if !directions.is_empty() {
+        options = options.directions(&directions);
+    }
+
+    for retry in 0..=NAME_RETRIES {
+        let name = match retry {
+            0 => name.to_string(),
+            retry => format!("{name}-{retry}"),
+        };
+
+        match spawn.spawn_creep_with_options(body, &name, &options) {
+            Ok(()) => return Ok(Some(name)),
+            Err(ErrorCode::NameExists) => continue,
+            Err(ErrorCode::Busy | ErrorCode::NotEnough) => return Ok(None),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(ErrorCode::NameExists)
+}
+
+pub fn log_spawn_error(spawn: &StructureSpawn, e: ErrorCode) {
+    match e {
+        ErrorCode::InvalidArgs | ErrorCode::RclNotEnough => {
+            error!("spawn {} can't spawn that body: {:?}", spawn.name(), e)
+        }
+        e => warn!("couldn't spawn at {}: {:?}", spawn.name(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_bucket_stops_spawning() {
+        assert!(!should_spawn(BUCKET_SPAWN_FLOOR - 1, 0, 10));
+        assert!(should_spawn(BUCKET_SPAWN_FLOOR, 0, 10));
+    }
+
+    #[test]
+    fn normal_bucket_spawns_up_to_the_cap() {
+        assert!(should_spawn(5_000, 9, 10));
+        assert!(!should_spawn(5_000, 10, 10));
+    }
+
+    #[test]
+    fn full_bucket_goes_past_the_cap() {
+        let cap = 10;
+        assert!(should_spawn(BUCKET_SURPLUS, cap, cap));
+        assert!(should_spawn(
+            BUCKET_SURPLUS,
+            cap + SURPLUS_EXTRA_CREEPS - 1,
+            cap
+        ));
+        assert!(!should_spawn(
+            BUCKET_SURPLUS,
+            cap + SURPLUS_EXTRA_CREEPS,
+            cap
+        ));
+    }
+
+    #[test]
+    fn banked_energy_past_capacity_keeps_the_small_body() {
+        let tiers = [(6, Some(300)), (20, None)];
+        // past the cheap tier, so the budget is whatever the room can hold
+        let plan = SpawnPlan::new(&tiers, 10, &[], 4, false);
+
+        let big = plan.pick(Role::Harvester, 550, 550).unwrap();
+        assert!(big.sum_parts() > 300);
+
+        for role in [Role::Harvester, Role::Upgrader, Role::Builder, Role::Hauler] {
+            let body = plan.pick(role, 300, 550).unwrap();
+            assert!(body.sum_parts() <= 300, "{role:?} got {body:?}");
+        }
+    }
+
+    #[test]
+    fn picks_nothing_until_the_energy_is_there() {
+        let tiers = [(6, Some(300)), (20, None)];
+        let plan = SpawnPlan::new(&tiers, 10, &[], 4, false);
+        let big = plan.pick(Role::Harvester, 550, 550).unwrap();
+        assert!(plan
+            .pick(Role::Harvester, 550, big.sum_parts() - 1)
+            .is_none());
+        assert!(plan.pick(Role::Harvester, 300, 300).is_some());
+    }
+
+    #[test]
+    fn tiers_go_by_creep_count() {
+        let tiers = [(6, Some(300)), (12, Some(800)), (20, None)];
+        let cost = |creeps| {
+            SpawnPlan::new(&tiers, creeps, &[], 8, false)
+                .pick(Role::Builder, 1_300, 1_300)
+                .unwrap()
+                .sum_parts()
+        };
+        assert!(cost(0) <= 300);
+        assert!(cost(5) <= 300);
+        assert!(cost(6) > 300 && cost(6) <= 800);
+        assert!(cost(12) > 800);
+        // past the cap it sticks with the last tier
+        assert!(cost(25) > 800);
+    }
+
+    #[test]
+    fn level_tiers_go_by_controller_level() {
+        let level_tiers = [(3, Some(300)), (5, None), (6, Some(800))];
+        let cost = |level| {
+            SpawnPlan::new(&[], 10, &level_tiers, level, false)
+                .pick(Role::Builder, 1_300, 1_300)
+                .unwrap()
+                .sum_parts()
+        };
+        assert!(cost(1) <= 300);
+        assert!(cost(2) <= 300);
+        assert!(cost(3) > 300);
+        assert!(cost(5) <= 800);
+        // past the last tier there's no budget but the room's capacity
+        assert!(cost(6) > 800);
+    }
+
+    #[test]
+    fn tighter_tier_wins() {
+        let tiers = [(6, Some(300)), (20, None)];
+        let level_tiers = [(3, Some(300))];
+        let cost = |creeps, level| {
+            SpawnPlan::new(&tiers, creeps, &level_tiers, level, false)
+                .pick(Role::Builder, 1_300, 1_300)
+                .unwrap()
+                .sum_parts()
+        };
+        assert!(cost(2, 5) <= 300);
+        assert!(cost(10, 2) <= 300);
+        assert!(cost(10, 5) > 300);
+    }
+
+    #[test]
+    fn builders_only_with_something_to_build() {
+        for rcl in 0..=8 {
+            assert_eq!(desired_counts(rcl, false).builders, 0);
+            assert!(desired_counts(rcl, true).builders > 0);
+        }
+    }
+
+    #[test]
+    fn capped_upgrading_needs_one_upgrader() {
+        assert_eq!(desired_counts(8, true).upgraders, 1);
+        assert!(desired_counts(7, true).upgraders > 1);
+    }
+
+    fn needs(level: u8, controller_store: bool) -> RoomNeeds {
+        RoomNeeds {
+            controller_level: level,
+            sources: 2,
+            container_sources: 1,
+            construction_sites: 0,
+            claim_flags: 0,
+            dismantle_flags: 0,
+            needs_scouting: false,
+            threats: 0,
+            controller_store,
+            energy_adjustment: 0,
+            nuke_incoming: false,
+            defense: ThreatAssessment::default(),
+        }
+    }
+
+    fn wanted(desired: &[(Role, usize)], role: Role) -> usize {
+        desired
+            .iter()
+            .find(|(wanted, _)| *wanted == role)
+            .map_or(0, |&(_, count)| count)
+    }
+
+    #[test]
+    fn static_upgrader_counts_as_the_level_8_upgrader() {
+        let rules = SpawnRules::default();
+        let upgrading = |needs| {
+            let desired = rules.desired(&needs);
+            (
+                wanted(&desired, Role::Upgrader),
+                wanted(&desired, Role::StaticUpgrader),
+            )
+        };
+        assert_eq!(upgrading(needs(8, true)), (0, 1));
+        assert_eq!(upgrading(needs(8, false)), (1, 0));
+        assert_eq!(upgrading(needs(7, false)), (2, 0));
+    }
+
+    #[test]
+    fn harvesters_only_without_drop_mining() {
+        let desired = SpawnRules::default().desired(&needs(3, false));
+        assert_eq!(wanted(&desired, Role::Harvester), 0);
+        assert_eq!(wanted(&desired, Role::Miner), 2);
+
+        let rules = SpawnRules {
+            drop_mining: false,
+            ..SpawnRules::default()
+        };
+        let desired = rules.desired(&needs(3, false));
+        assert_eq!(
+            wanted(&desired, Role::Harvester),
+            rules.harvesters_per_source
+        );
+        assert_eq!(wanted(&desired, Role::Miner), 1);
+    }
+
+    #[test]
+    fn spawns_get_stuck_after_a_run_of_ticks() {
+        let mut stuck = StuckSpawn::new(100);
+        assert!(!stuck.count(100));
+        for now in 101..100 + SPAWN_STUCK_TICKS {
+            assert!(!stuck.count(now));
+        }
+        assert!(stuck.count(100 + SPAWN_STUCK_TICKS));
+        assert!(stuck.stuck);
+        // only reported the once
+        assert!(!stuck.count(101 + SPAWN_STUCK_TICKS));
+    }
+
+    #[test]
+    fn a_gap_starts_the_count_over() {
+        let mut stuck = StuckSpawn::new(100);
+        // skipped over by an early return for a long while, so the old start can't count
+        assert!(!stuck.count(100 + SPAWN_STUCK_TICKS * 2));
+        assert!(!stuck.stuck);
+        assert!(!stuck.count(101 + SPAWN_STUCK_TICKS * 2));
+    }
+
+    #[test]
+    fn stuck_spawns_stay_stuck_through_gaps() {
+        let mut stuck = StuckSpawn::new(0);
+        for now in 0..=SPAWN_STUCK_TICKS {
+            stuck.count(now);
+        }
+        assert!(stuck.stuck);
+        // busy spawning the fallback body in between
+        stuck.count(SPAWN_STUCK_TICKS + 50);
+        assert!(stuck.stuck);
+    }
+
+    #[test]
+    fn levels_past_eight_count_as_eight() {
+        assert_eq!(desired_counts(9, true), desired_counts(8, true));
+        assert_eq!(desired_counts(0, true), LEVEL_COUNTS[0]);
+    }
+
+    #[test]
+    fn energy_is_held_until_the_timeout() {
+        assert!(hold_spawn_energy(300, 550, 0));
+        assert!(hold_spawn_energy(549, 550, SPAWN_RESERVATION_TIMEOUT - 1));
+        assert!(!hold_spawn_energy(300, 550, SPAWN_RESERVATION_TIMEOUT));
+        assert!(!hold_spawn_energy(300, 550, SPAWN_RESERVATION_TIMEOUT + 1));
+    }
+
+    #[test]
+    fn energy_is_released_once_the_body_is_affordable() {
+        assert!(!hold_spawn_energy(550, 550, 0));
+        assert!(!hold_spawn_energy(800, 550, 10));
+    }
+}
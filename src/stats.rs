@@ -0,0 +1,114 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+
+use js_sys::Reflect;
+use log::*;
+use screeps::game;
+use serde::Serialize;
+
+use crate::{config, owned_rooms};
+
+// how many ticks of cpu usage go into the rolling average
+const CPU_HISTORY_LEN: usize = 20;
+
+// how often heap usage gets logged
+pub const HEAP_LOG_INTERVAL: u32 = 60;
+
+thread_local! {
+    static CPU_HISTORY: RefCell<VecDeque<f64>> = RefCell::new(VecDeque::with_capacity(CPU_HISTORY_LEN));
+    // used heap at the last log, to show how much it's grown since
+    static LAST_HEAP_USED: Cell<Option<u32>> = Cell::new(None);
+}
+
+// per-tick metrics, written to Memory.stats for graphing
+#[derive(Serialize, Debug)]
+pub struct Stats {
+    tick: u32,
+    cpu_used: f64,
+    cpu_average: f64,
+    cpu_limit: u32,
+    bucket: i32,
+    gcl_level: u32,
+    gcl_progress: f64,
+    gcl_progress_total: f64,
+    creeps: usize,
+    room_energy: HashMap<String, u32>,
+}
+
+impl Stats {
+    pub fn collect(cpu_used: f64) -> Self {
+        let cpu_average = CPU_HISTORY.with_borrow_mut(|history| {
+            if history.len() == CPU_HISTORY_LEN {
+                history.pop_front();
+            }
+            history.push_back(cpu_used);
+            history.iter().sum::<f64>() / history.len() as f64
+        });
+
+        let room_energy = owned_rooms()
+            .map(|room| (room.name().to_string(), room.energy_available()))
+            .collect();
+
+        Stats {
+            tick: game::time(),
+            cpu_used,
+            cpu_average,
+            cpu_limit: game::cpu::limit(),
+            bucket: game::cpu::bucket(),
+            gcl_level: game::gcl::level(),
+            gcl_progress: game::gcl::progress(),
+            gcl_progress_total: game::gcl::progress_total(),
+            creeps: game::creeps().keys().count(),
+            room_energy,
+        }
+    }
+
+    pub fn cpu_average(&self) -> f64 {
+        self.cpu_average
+    }
+
+    pub fn export(&self) {
+        // maps need to come out as plain objects rather than js Maps to survive Memory's
+        // JSON serialization
+        match self.serialize(&serde_wasm_bindgen::Serializer::json_compatible()) {
+            Ok(value) => {
+                Reflect::set(&screeps::memory::ROOT, &"stats".into(), &value).unwrap();
+            }
+            Err(e) => warn!("couldn't export stats: {}", e),
+        }
+    }
+}
+
+// logs how much of the heap is in use and how that's changed since last time, so a leak
+// shows up as steady growth. Heap statistics only exist on servers running the isolated
+// vm, so there's nothing to log anywhere else
+pub fn log_heap() {
+    let has_heap_stats = Reflect::get(&js_sys::global(), &"Game".into())
+        .and_then(|game| Reflect::get(&game, &"cpu".into()))
+        .and_then(|cpu| Reflect::get(&cpu, &"getHeapStatistics".into()))
+        .is_ok_and(|get| get.is_function());
+    if !has_heap_stats {
+        return;
+    }
+
+    let heap = game::cpu::get_heap_statistics();
+    let used = heap.used_heap_size();
+    let limit = heap.heap_size_limit();
+    let delta = LAST_HEAP_USED
+        .replace(Some(used))
+        .map_or(0, |last| used as i64 - last as i64);
+
+    let mb = |bytes: u32| bytes as f64 / (1024.0 * 1024.0);
+    let message = format!(
+        "heap: {:.1}MB used ({:+.2}MB), {:.1}MB total, {:.1}MB limit",
+        mb(used),
+        delta as f64 / (1024.0 * 1024.0),
+        mb(heap.total_heap_size()),
+        mb(limit)
+    );
+    if limit > 0 && used as f64 > limit as f64 * config::heap_warn_fraction() {
+        warn!("{message}");
+    } else {
+        info!("{message}");
+    }
+}
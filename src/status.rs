@@ -0,0 +1,46 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use screeps::{objects::Creep, prelude::*};
+
+use crate::CreepTarget;
+
+thread_local! {
+    // the last task each creep announced, so it only speaks up when that changes
+    static LAST_SAID: RefCell<HashMap<String, &'static str>> = RefCell::new(HashMap::new());
+}
+
+pub fn task_emoji(target: &CreepTarget) -> &'static str {
+    match target {
+        CreepTarget::Harvest { .. } | CreepTarget::Mine { .. } => "⛏",
+        CreepTarget::Upgrade(_) => "⚡",
+        CreepTarget::Construct(_) => "🔨",
+        CreepTarget::Store { .. } | CreepTarget::Drop(_) => "📦",
+        CreepTarget::Repair(_) => "🔧",
+        CreepTarget::Dismantle(_) => "🪓",
+        CreepTarget::Pickup(_)
+        | CreepTarget::Loot(_)
+        | CreepTarget::LootRuin(_)
+        | CreepTarget::Withdraw(_)
+        | CreepTarget::WithdrawStorage(_) => "🧺",
+        CreepTarget::Attack(_) | CreepTarget::AttackStructure(_) => "⚔",
+        CreepTarget::Reserve(_) | CreepTarget::Claim(_) => "🚩",
+        CreepTarget::Idle(_) => "💤",
+        CreepTarget::Boost { .. } => "🧪",
+        CreepTarget::Recycle(_) => "♻",
+    }
+}
+
+// has the creep say what it's working on, but only when that's changed since last time
+pub fn announce_task(creep: &Creep, target: &CreepTarget) {
+    let emoji = task_emoji(target);
+    let changed = LAST_SAID.with_borrow_mut(|said| said.insert(creep.name(), emoji) != Some(emoji));
+
+    if changed {
+        let _ = creep.say(emoji, false);
+    }
+}
+
+pub fn forget_dead(alive: &HashSet<String>) {
+    LAST_SAID.with_borrow_mut(|said| said.retain(|name, _| alive.contains(name)));
+}
@@ -0,0 +1,110 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use log::*;
+use screeps::{constants::ResourceType, local::RoomName, objects::Room};
+
+use crate::config::room_config;
+
+// which way haulers move energy through a room's storage
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StorageMode {
+    // storage is running low, so haulers only ever bring energy to it, once everything
+    // else is full
+    #[default]
+    Fill,
+    // storage has plenty, so haulers also keep spawns, extensions and towers topped up
+    // from it
+    Drain,
+}
+
+thread_local! {
+    static STORAGE_MODES: RefCell<HashMap<RoomName, StorageMode>> = RefCell::new(HashMap::new());
+}
+
+// the mode after `current`, for storage holding `energy`. It only flips once energy has
+// crossed all the way to the other water mark, so haulers don't switch back and forth
+// while it hovers around either one
+pub fn next_mode(current: StorageMode, energy: u32, low: u32, high: u32) -> StorageMode {
+    match current {
+        StorageMode::Fill if energy >= high => StorageMode::Drain,
+        StorageMode::Drain if energy <= low => StorageMode::Fill,
+        _ => current,
+    }
+}
+
+// what haulers in the room should be doing with its storage, None without one
+pub fn storage_mode(room: &Room) -> Option<StorageMode> {
+    let storage = room.storage()?;
+    let energy = storage
+        .store()
+        .get_used_capacity(Some(ResourceType::Energy));
+    let config = room_config(room.name());
+
+    Some(STORAGE_MODES.with_borrow_mut(|modes| {
+        let mode = modes.entry(room.name()).or_default();
+        let next = next_mode(
+            *mode,
+            energy,
+            config.storage_low_water,
+            config.storage_high_water,
+        );
+        if next != *mode {
+            info!(
+                "{} storage at {energy} energy, haulers switching to {:?}",
+                room.name(),
+                next
+            );
+            *mode = next;
+        }
+        next
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOW: u32 = 100_000;
+    const HIGH: u32 = 300_000;
+
+    // feeds `energy` readings through `next_mode` one tick at a time, starting from `start`
+    fn modes(start: StorageMode, energy: &[u32]) -> Vec<StorageMode> {
+        energy
+            .iter()
+            .scan(start, |mode, &energy| {
+                *mode = next_mode(*mode, energy, LOW, HIGH);
+                Some(*mode)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn holds_inside_the_band() {
+        let band = [LOW + 1, 200_000, HIGH - 1, LOW + 1];
+        assert!(modes(StorageMode::Fill, &band)
+            .iter()
+            .all(|&mode| mode == StorageMode::Fill));
+        assert!(modes(StorageMode::Drain, &band)
+            .iter()
+            .all(|&mode| mode == StorageMode::Drain));
+    }
+
+    #[test]
+    fn fills_until_the_high_mark() {
+        use StorageMode::*;
+        assert_eq!(
+            modes(Fill, &[50_000, HIGH - 1, HIGH, HIGH - 1, 200_000]),
+            [Fill, Fill, Drain, Drain, Drain]
+        );
+    }
+
+    #[test]
+    fn drains_until_the_low_mark() {
+        use StorageMode::*;
+        assert_eq!(
+            modes(Drain, &[400_000, LOW + 1, LOW, LOW + 1, 200_000]),
+            [Drain, Drain, Fill, Fill, Fill]
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use screeps::{
+    constants::{StructureType, Terrain},
+    local::{ObjectId, Position, RoomName, RoomXY},
+    look,
+    objects::{Room, Structure, StructureContainer, StructureLink},
+    prelude::*,
+};
+
+use crate::find_cache;
+
+// anything this close to the controller can feed upgraders standing next to it
+const CONTROLLER_STORE_RANGE: u32 = 3;
+
+// the container and link by each room's controller, along with the ids of every container
+// and link the room had when they were looked for
+#[derive(Default)]
+struct ControllerStores {
+    candidates: Vec<ObjectId<Structure>>,
+    container: Option<ObjectId<StructureContainer>>,
+    link: Option<ObjectId<StructureLink>>,
+}
+
+thread_local! {
+    static CONTROLLER_STORES: RefCell<HashMap<RoomName, ControllerStores>> = RefCell::new(HashMap::new());
+
+    // terrain never changes, so each tile only ever needs looking at once
+    static TERRAIN: RefCell<HashMap<(RoomName, RoomXY), Terrain>> = RefCell::new(HashMap::new());
+}
+
+// the terrain under `pos`, looked up the first time it's asked for and remembered after
+pub fn terrain_at(pos: Position) -> Option<Terrain> {
+    let key = (pos.room_name(), pos.xy());
+    if let Some(terrain) = TERRAIN.with_borrow(|cache| cache.get(&key).copied()) {
+        return Some(terrain);
+    }
+
+    let terrain = pos.look_for(look::TERRAIN).ok()?.into_iter().next()?;
+    TERRAIN.with_borrow_mut(|cache| cache.insert(key, terrain));
+    Some(terrain)
+}
+
+// whichever candidate is closest to `to` without being further than `range`
+pub fn closest_in_range<T>(
+    to: Position,
+    range: u32,
+    candidates: impl IntoIterator<Item = (Position, T)>,
+) -> Option<T> {
+    candidates
+        .into_iter()
+        .filter(|(pos, _)| pos.in_range_to(to, range))
+        .min_by_key(|(pos, _)| pos.get_range_to(to))
+        .map(|(_, candidate)| candidate)
+}
+
+// the container and link within range of a controller at `controller`, out of a room's
+// structures given as their type, position and id
+pub fn stores_near(
+    controller: Position,
+    structures: &[(StructureType, Position, ObjectId<Structure>)],
+) -> (
+    Option<ObjectId<StructureContainer>>,
+    Option<ObjectId<StructureLink>>,
+) {
+    let of_type = |wanted: StructureType| {
+        structures
+            .iter()
+            .filter(move |(structure_type, ..)| *structure_type == wanted)
+            .map(|&(_, pos, id)| (pos, id))
+    };
+    let container = closest_in_range(
+        controller,
+        CONTROLLER_STORE_RANGE,
+        of_type(StructureType::Container),
+    );
+    let link = closest_in_range(
+        controller,
+        CONTROLLER_STORE_RANGE,
+        of_type(StructureType::Link),
+    );
+    (
+        container.map(ObjectId::into_type),
+        link.map(ObjectId::into_type),
+    )
+}
+
+fn controller_stores<T>(room: &Room, pick: impl Fn(&ControllerStores) -> T) -> Option<T> {
+    let controller = room.controller()?;
+    let structures = find_cache::structures(room);
+
+    Some(CONTROLLER_STORES.with_borrow_mut(|stores| {
+        let mut listed: Vec<_> = structures
+            .iter()
+            .filter(|structure| {
+                matches!(
+                    structure.structure_type(),
+                    StructureType::Container | StructureType::Link
+                )
+            })
+            .map(|structure| {
+                (
+                    structure.structure_type(),
+                    structure.pos(),
+                    structure.as_structure().id(),
+                )
+            })
+            .collect();
+        listed.sort_unstable_by_key(|&(_, _, id)| id);
+
+        // one being destroyed as another's built keeps the count the same, but not the ids
+        let cached = stores.entry(room.name()).or_default();
+        if !cached
+            .candidates
+            .iter()
+            .eq(listed.iter().map(|(_, _, id)| id))
+        {
+            let (container, link) = stores_near(controller.pos(), &listed);
+            *cached = ControllerStores {
+                candidates: listed.iter().map(|&(_, _, id)| id).collect(),
+                container,
+                link,
+            };
+        }
+        pick(cached)
+    }))
+}
+
+// the container within upgrade range of the room's controller, if there is one
+pub fn controller_container(room: &Room) -> Option<ObjectId<StructureContainer>> {
+    controller_stores(room, |stores| stores.container)?
+}
+
+// the link within upgrade range of the room's controller, if there is one
+pub fn controller_link(room: &Room) -> Option<ObjectId<StructureLink>> {
+    controller_stores(room, |stores| stores.link)?
+}
+
+#[cfg(test)]
+mod tests {
+    use screeps::local::RoomCoordinate;
+
+    use super::*;
+
+    fn pos(x: u8, y: u8) -> Position {
+        let room: RoomName = "W1N1".parse().unwrap();
+        Position::new(
+            RoomCoordinate::new(x).unwrap(),
+            RoomCoordinate::new(y).unwrap(),
+            room,
+        )
+    }
+
+    fn id(n: u32) -> ObjectId<Structure> {
+        format!("{n:024x}").parse().unwrap()
+    }
+
+    // the container and link stores_near finds for a controller at (20, 20), by id
+    fn near(
+        structures: &[(StructureType, Position, ObjectId<Structure>)],
+    ) -> (Option<ObjectId<Structure>>, Option<ObjectId<Structure>>) {
+        let (container, link) = stores_near(pos(20, 20), structures);
+        (
+            container.map(ObjectId::into_type),
+            link.map(ObjectId::into_type),
+        )
+    }
+
+    #[test]
+    fn closest_of_each_in_range() {
+        let structures = [
+            (StructureType::Spawn, pos(21, 21), id(1)),
+            // out of range, however close the rest are
+            (StructureType::Container, pos(24, 20), id(2)),
+            (StructureType::Container, pos(22, 22), id(3)),
+            (StructureType::Link, pos(21, 19), id(4)),
+            (StructureType::Link, pos(23, 17), id(5)),
+            (StructureType::Extension, pos(20, 21), id(6)),
+        ];
+        assert_eq!(near(&structures), (Some(id(3)), Some(id(4))));
+        assert_eq!(near(&structures[..3]), (Some(id(3)), None));
+        assert_eq!(near(&structures[4..]), (None, Some(id(5))));
+    }
+
+    #[test]
+    fn nothing_in_range_means_no_stores() {
+        let structures = [
+            (StructureType::Container, pos(24, 24), id(2)),
+            (StructureType::Link, pos(16, 20), id(4)),
+            (StructureType::Storage, pos(21, 20), id(6)),
+        ];
+        assert_eq!(near(&structures), (None, None));
+    }
+}
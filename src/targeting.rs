@@ -0,0 +1,616 @@
+use screeps::{
+    local::{ObjectId, Position},
+    objects::{
+        ConstructionSite, Structure, StructureController, StructureExtension, StructureSpawn,
+        StructureStorage, StructureTerminal, StructureTower,
+    },
+};
+
+use crate::body::Role;
+use crate::{CreepTarget, StoreTarget};
+
+// below this many ticks short of its full downgrade timer, the controller gets topped up
+// before anything else
+const DOWNGRADE_MARGIN: u32 = 5_000;
+
+#[derive(Clone, Debug)]
+pub struct ControllerSnapshot {
+    pub id: ObjectId<StructureController>,
+    pub level: u8,
+    pub ticks_to_downgrade: u32,
+}
+
+// what a creep picking a new target needs to know about its room, gathered up front so
+// the choice itself doesn't touch the game
+#[derive(Clone, Debug, Default)]
+pub struct RoomSnapshot {
+    pub controller: Option<ControllerSnapshot>,
+    // the closest of each to the creep that still has room for energy
+    pub spawn: Option<ObjectId<StructureSpawn>>,
+    pub extension: Option<ObjectId<StructureExtension>>,
+    pub tower: Option<ObjectId<StructureTower>>,
+    // under attack, towers are filled emptiest first rather than closest first
+    pub emptiest_tower: Option<ObjectId<StructureTower>>,
+    pub under_attack: bool,
+    // whatever below its repair threshold will decay away soonest
+    pub repair: Option<ObjectId<Structure>>,
+    // the closest of anything below its repair threshold
+    pub closest_repair: Option<ObjectId<Structure>>,
+    pub construction_site: Option<ObjectId<ConstructionSite>>,
+    // only set while it has room for more energy
+    pub storage: Option<ObjectId<StructureStorage>>,
+    pub terminal: Option<ObjectId<StructureTerminal>>,
+    // the tile of the container by the controller, if there is one
+    pub controller_container: Option<Position>,
+    pub parking: Option<Position>,
+    // how many creeps are building and how many upgrading in the room, and the share of
+    // those the config wants building while there's anything to build
+    pub building: usize,
+    pub upgrading: usize,
+    pub build_share: Option<f64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CreepSnapshot {
+    pub role: Role,
+    pub energy: u32,
+}
+
+// the full downgrade timer for a controller at `level`, what upgrading resets it to
+pub fn downgrade_timer(level: u8) -> u32 {
+    match level {
+        1 => 20_000,
+        2 => 10_000,
+        3 => 20_000,
+        4 => 40_000,
+        5 => 80_000,
+        6 => 120_000,
+        7 => 150_000,
+        8 => 200_000,
+        _ => 20_000,
+    }
+}
+
+// what kind of sink a structure is, as far as the sink scan cares
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SinkKind {
+    Controller,
+    Spawn,
+    Extension,
+    Tower,
+    Other,
+}
+
+// one of a room's structures as the sink scan sees it, with `item` standing in for the
+// structure itself
+#[derive(Clone, Copy, Debug)]
+pub struct SinkCandidate<T> {
+    pub item: T,
+    pub id: ObjectId<Structure>,
+    pub kind: SinkKind,
+    // from the creep doing the scan
+    pub range: u32,
+    pub energy: u32,
+    pub free_energy: u32,
+    // (hits, repair threshold, ticks until decay takes it out) for structures we look
+    // after
+    pub repair: Option<(u32, u32, u32)>,
+}
+
+// everything a creep with energy might spend it on, see `scan_sinks`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sinks<T> {
+    pub controller: Option<T>,
+    // the closest of each that still has room for energy
+    pub spawn: Option<T>,
+    pub extension: Option<T>,
+    pub tower: Option<T>,
+    // the tower with the least energy in it, whether or not it's closest
+    pub emptiest_tower: Option<T>,
+    // whatever decay will take out soonest, of everything below its repair threshold.
+    // Walls don't decay, so they go by how far below their threshold they've fallen
+    pub repair: Option<T>,
+    // the closest of anything below its repair threshold at all
+    pub closest_repair: Option<T>,
+}
+
+impl<T> Default for Sinks<T> {
+    fn default() -> Self {
+        Sinks {
+            controller: None,
+            spawn: None,
+            extension: None,
+            tower: None,
+            emptiest_tower: None,
+            repair: None,
+            closest_repair: None,
+        }
+    }
+}
+
+// picks out every sink in one pass over a room's structures. Ties on range go to the
+// lowest object id, so a creep between two equally distant targets doesn't flip between
+// them from tick to tick
+pub fn scan_sinks<T: Copy>(candidates: impl IntoIterator<Item = SinkCandidate<T>>) -> Sinks<T> {
+    type Best<T> = Option<(T, (u32, ObjectId<Structure>))>;
+    let keep_closest = |best: &mut Best<T>, candidate: &SinkCandidate<T>| {
+        let key = (candidate.range, candidate.id);
+        if best.map_or(true, |(_, best)| key < best) {
+            *best = Some((candidate.item, key));
+        }
+    };
+
+    let mut controller = None;
+    let (mut spawn, mut extension, mut tower, mut closest_repair) = (None, None, None, None);
+    let mut emptiest_tower: Option<(T, u32)> = None;
+    let mut repair: Option<(T, (u32, f64))> = None;
+
+    for candidate in candidates {
+        let hungry = candidate.free_energy > 0;
+        match candidate.kind {
+            SinkKind::Controller => {
+                controller.get_or_insert(candidate.item);
+            }
+            SinkKind::Spawn if hungry => keep_closest(&mut spawn, &candidate),
+            SinkKind::Extension if hungry => keep_closest(&mut extension, &candidate),
+            SinkKind::Tower if hungry => {
+                keep_closest(&mut tower, &candidate);
+                if emptiest_tower.map_or(true, |(_, energy)| candidate.energy < energy) {
+                    emptiest_tower = Some((candidate.item, candidate.energy));
+                }
+            }
+            _ => {}
+        }
+
+        if let Some((hits, threshold, ticks)) = candidate.repair {
+            if hits < threshold {
+                let urgency = (ticks, hits as f64 / threshold as f64);
+                if repair.map_or(true, |(_, best)| urgency < best) {
+                    repair = Some((candidate.item, urgency));
+                }
+                keep_closest(&mut closest_repair, &candidate);
+            }
+        }
+    }
+
+    Sinks {
+        controller,
+        spawn: spawn.map(|(item, _)| item),
+        extension: extension.map(|(item, _)| item),
+        tower: tower.map(|(item, _)| item),
+        emptiest_tower: emptiest_tower.map(|(item, _)| item),
+        repair: repair.map(|(item, _)| item),
+        closest_repair: closest_repair.map(|(item, _)| item),
+    }
+}
+
+// how low a controller at `level` can let its downgrade timer get before creeps passing
+// by top it up. That's the full timer for the level, less a margin
+pub fn downgrade_threshold(level: u8) -> u32 {
+    downgrade_timer(level) - DOWNGRADE_MARGIN
+}
+
+// whether a worker joining `building` builders and `upgrading` upgraders should build,
+// going to whichever side leaves the builders' share of the two closest to `build_share`
+pub fn should_build(building: usize, upgrading: usize, build_share: f64) -> bool {
+    let total = building + upgrading + 1;
+    building as f64 + 0.5 < build_share * total as f64
+}
+
+// the next target for a creep without one. So far this only covers creeps carrying
+// energy, the rest are still picked in `run_creep`
+pub fn choose_target(room: &RoomSnapshot, creep: &CreepSnapshot) -> Option<CreepTarget> {
+    if creep.energy == 0 {
+        return None;
+    }
+    let role = creep.role;
+
+    // a tower running dry mid-fight is the worst thing that can happen to a room, so
+    // while it's under attack they come before anything else, emptiest first
+    if room.under_attack {
+        if let Some(id) = room.emptiest_tower {
+            return Some(CreepTarget::store_energy(StoreTarget::Tower(id)));
+        }
+    }
+
+    // if controller needs a timer reset, fill it
+    if let Some(controller) = &room.controller {
+        if role != Role::Hauler
+            && controller.ticks_to_downgrade < downgrade_threshold(controller.level)
+        {
+            return Some(CreepTarget::Upgrade(controller.id));
+        }
+    }
+
+    match role {
+        Role::Upgrader => {
+            if let Some(controller) = &room.controller {
+                return Some(CreepTarget::Upgrade(controller.id));
+            }
+        }
+        // with nothing to build, builders patch up whatever's nearby before they'd
+        // otherwise end up upgrading
+        Role::Builder => {
+            if let Some(id) = room.construction_site {
+                return Some(CreepTarget::Construct(id));
+            }
+            if let Some(id) = room.closest_repair {
+                return Some(CreepTarget::Repair(id));
+            }
+        }
+        _ => {}
+    }
+
+    // fill spawners, then extensions, then towers
+    if let Some(id) = room.spawn {
+        return Some(CreepTarget::store_energy(StoreTarget::Spawn(id)));
+    }
+    if let Some(id) = room.extension {
+        return Some(CreepTarget::store_energy(StoreTarget::Extension(id)));
+    }
+    if let Some(id) = room.tower {
+        return Some(CreepTarget::store_energy(StoreTarget::Tower(id)));
+    }
+
+    // without work parts there's nothing else a hauler can spend energy on, so with
+    // everything full it unloads into storage, or the terminal once that's full too.
+    // Before either is built, the energy goes by the controller for the upgraders
+    if role == Role::Hauler {
+        if let Some(id) = room.storage {
+            return Some(CreepTarget::store_energy(StoreTarget::Storage(id)));
+        }
+        if let Some(id) = room.terminal {
+            return Some(CreepTarget::store_energy(StoreTarget::Terminal(id)));
+        }
+        if let Some(pos) = room.controller_container {
+            return Some(CreepTarget::Drop(pos));
+        }
+        return room.parking.map(CreepTarget::Idle);
+    }
+
+    // repair whatever below its repair threshold will decay away soonest, then build,
+    // then upgrade. With a build share configured, workers go to whichever of building
+    // and upgrading is short of its share instead
+    if let Some(id) = room.repair {
+        return Some(CreepTarget::Repair(id));
+    }
+    if let (Some(share), Some(controller), Some(_)) =
+        (room.build_share, &room.controller, room.construction_site)
+    {
+        if !should_build(room.building, room.upgrading, share) {
+            return Some(CreepTarget::Upgrade(controller.id));
+        }
+    }
+    if let Some(id) = room.construction_site {
+        return Some(CreepTarget::Construct(id));
+    }
+    if let Some(controller) = &room.controller {
+        return Some(CreepTarget::Upgrade(controller.id));
+    }
+
+    room.parking.map(CreepTarget::Idle)
+}
+
+#[cfg(test)]
+mod tests {
+    use screeps::local::{RoomCoordinate, RoomName};
+
+    use super::*;
+
+    fn id<T>(n: u32) -> ObjectId<T> {
+        format!("{n:024x}").parse().unwrap()
+    }
+
+    fn pos(x: u8, y: u8) -> Position {
+        let room: RoomName = "W1N1".parse().unwrap();
+        Position::new(
+            RoomCoordinate::new(x).unwrap(),
+            RoomCoordinate::new(y).unwrap(),
+            room,
+        )
+    }
+
+    fn harvester(energy: u32) -> CreepSnapshot {
+        CreepSnapshot {
+            role: Role::Harvester,
+            energy,
+        }
+    }
+
+    // every combination of what a room can offer a worker, with the controller either
+    // fine or about to downgrade
+    fn fixture_rooms() -> Vec<RoomSnapshot> {
+        (0..1 << 7)
+            .map(|bits: u32| {
+                let has = |bit: u32| bits & (1 << bit) != 0;
+                RoomSnapshot {
+                    controller: has(0).then(|| ControllerSnapshot {
+                        id: id(1),
+                        level: 3,
+                        ticks_to_downgrade: if has(1) { 100 } else { 19_000 },
+                    }),
+                    spawn: has(2).then(|| id(2)),
+                    extension: has(3).then(|| id(3)),
+                    tower: has(4).then(|| id(4)),
+                    repair: has(5).then(|| id(5)),
+                    construction_site: has(6).then(|| id(6)),
+                    parking: Some(pos(20, 20)),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    fn creep(role: Role, energy: u32) -> CreepSnapshot {
+        CreepSnapshot { role, energy }
+    }
+
+    #[test]
+    fn empty_creeps_get_nothing() {
+        for room in fixture_rooms() {
+            assert_eq!(choose_target(&room, &harvester(0)), None);
+        }
+    }
+
+    #[test]
+    fn towers_come_first_under_attack() {
+        let room = RoomSnapshot {
+            controller: Some(ControllerSnapshot {
+                id: id(1),
+                level: 3,
+                ticks_to_downgrade: 100,
+            }),
+            spawn: Some(id(2)),
+            tower: Some(id(4)),
+            emptiest_tower: Some(id(7)),
+            under_attack: true,
+            ..Default::default()
+        };
+        let tower = Some(CreepTarget::store_energy(StoreTarget::Tower(id(7))));
+        assert_eq!(choose_target(&room, &harvester(50)), tower);
+
+        let calm = RoomSnapshot {
+            under_attack: false,
+            ..room
+        };
+        assert_eq!(
+            choose_target(&calm, &harvester(50)),
+            Some(CreepTarget::Upgrade(id(1)))
+        );
+    }
+
+    #[test]
+    fn haulers_never_upgrade() {
+        let mut room = RoomSnapshot {
+            controller: Some(ControllerSnapshot {
+                id: id(1),
+                level: 3,
+                ticks_to_downgrade: 100,
+            }),
+            storage: Some(id(8)),
+            terminal: Some(id(9)),
+            controller_container: Some(pos(30, 30)),
+            parking: Some(pos(20, 20)),
+            ..Default::default()
+        };
+        let hauler = creep(Role::Hauler, 50);
+
+        assert_eq!(
+            choose_target(&room, &hauler),
+            Some(CreepTarget::store_energy(StoreTarget::Storage(id(8))))
+        );
+        room.storage = None;
+        assert_eq!(
+            choose_target(&room, &hauler),
+            Some(CreepTarget::store_energy(StoreTarget::Terminal(id(9))))
+        );
+        room.terminal = None;
+        assert_eq!(
+            choose_target(&room, &hauler),
+            Some(CreepTarget::Drop(pos(30, 30)))
+        );
+        room.controller_container = None;
+        assert_eq!(
+            choose_target(&room, &hauler),
+            Some(CreepTarget::Idle(pos(20, 20)))
+        );
+    }
+
+    #[test]
+    fn builders_build_then_repair_before_filling() {
+        let mut room = RoomSnapshot {
+            spawn: Some(id(2)),
+            closest_repair: Some(id(5)),
+            construction_site: Some(id(6)),
+            ..Default::default()
+        };
+        let builder = creep(Role::Builder, 50);
+
+        assert_eq!(
+            choose_target(&room, &builder),
+            Some(CreepTarget::Construct(id(6)))
+        );
+        room.construction_site = None;
+        assert_eq!(
+            choose_target(&room, &builder),
+            Some(CreepTarget::Repair(id(5)))
+        );
+        room.closest_repair = None;
+        assert_eq!(
+            choose_target(&room, &builder),
+            Some(CreepTarget::store_energy(StoreTarget::Spawn(id(2))))
+        );
+    }
+
+    #[test]
+    fn upgraders_skip_the_fill() {
+        let room = RoomSnapshot {
+            controller: Some(ControllerSnapshot {
+                id: id(1),
+                level: 3,
+                ticks_to_downgrade: 19_000,
+            }),
+            spawn: Some(id(2)),
+            ..Default::default()
+        };
+        assert_eq!(
+            choose_target(&room, &creep(Role::Upgrader, 50)),
+            Some(CreepTarget::Upgrade(id(1)))
+        );
+    }
+
+    #[test]
+    fn build_share_sends_the_surplus_upgrading() {
+        let mut room = RoomSnapshot {
+            controller: Some(ControllerSnapshot {
+                id: id(1),
+                level: 3,
+                ticks_to_downgrade: 19_000,
+            }),
+            construction_site: Some(id(6)),
+            build_share: Some(0.5),
+            building: 2,
+            upgrading: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            choose_target(&room, &harvester(50)),
+            Some(CreepTarget::Upgrade(id(1)))
+        );
+        room.upgrading = 3;
+        assert_eq!(
+            choose_target(&room, &harvester(50)),
+            Some(CreepTarget::Construct(id(6)))
+        );
+    }
+
+    #[test]
+    fn first_worker_builds_with_any_share_past_half() {
+        assert!(should_build(0, 0, 0.7));
+        assert!(should_build(0, 0, 1.0));
+        assert!(!should_build(0, 0, 0.5));
+        assert!(!should_build(0, 0, 0.0));
+    }
+
+    #[test]
+    fn workers_settle_on_the_share() {
+        let (mut building, mut upgrading) = (0, 0);
+        let mut builds = Vec::new();
+        for _ in 0..10 {
+            let build = should_build(building, upgrading, 0.7);
+            builds.push(build);
+            if build {
+                building += 1;
+            } else {
+                upgrading += 1;
+            }
+            // never more than half a worker off the share
+            let total = (building + upgrading) as f64;
+            assert!((building as f64 - 0.7 * total).abs() <= 0.5);
+        }
+        assert_eq!(
+            builds,
+            [true, false, true, true, false, true, true, true, false, true]
+        );
+        assert_eq!((building, upgrading), (7, 3));
+    }
+
+    #[test]
+    fn extreme_shares_go_all_one_way() {
+        for building in 0..10 {
+            for upgrading in 0..10 {
+                assert!(!should_build(building, upgrading, 0.0));
+                assert!(should_build(building, upgrading, 1.0));
+            }
+        }
+    }
+
+    fn candidate(item: u32, kind: SinkKind, range: u32) -> SinkCandidate<u32> {
+        SinkCandidate {
+            item,
+            id: id(item),
+            kind,
+            range,
+            energy: 0,
+            free_energy: 50,
+            repair: None,
+        }
+    }
+
+    #[test]
+    fn closest_sink_of_each_kind() {
+        let sinks = scan_sinks([
+            candidate(1, SinkKind::Controller, 10),
+            candidate(2, SinkKind::Spawn, 8),
+            candidate(3, SinkKind::Spawn, 3),
+            candidate(4, SinkKind::Extension, 6),
+            candidate(5, SinkKind::Extension, 2),
+            candidate(6, SinkKind::Tower, 9),
+            candidate(7, SinkKind::Other, 1),
+        ]);
+        assert_eq!(sinks.controller, Some(1));
+        assert_eq!(sinks.spawn, Some(3));
+        assert_eq!(sinks.extension, Some(5));
+        assert_eq!(sinks.tower, Some(6));
+        assert_eq!(sinks.repair, None);
+    }
+
+    #[test]
+    fn full_sinks_are_passed_over() {
+        let full = SinkCandidate {
+            free_energy: 0,
+            ..candidate(3, SinkKind::Spawn, 1)
+        };
+        let sinks = scan_sinks([full, candidate(2, SinkKind::Spawn, 5)]);
+        assert_eq!(sinks.spawn, Some(2));
+        assert_eq!(scan_sinks([full]).spawn, None);
+    }
+
+    #[test]
+    fn range_ties_go_to_the_lowest_id() {
+        let forwards = scan_sinks([
+            candidate(9, SinkKind::Extension, 4),
+            candidate(4, SinkKind::Extension, 4),
+        ]);
+        let backwards = scan_sinks([
+            candidate(4, SinkKind::Extension, 4),
+            candidate(9, SinkKind::Extension, 4),
+        ]);
+        assert_eq!(forwards.extension, Some(4));
+        assert_eq!(backwards.extension, Some(4));
+    }
+
+    #[test]
+    fn emptiest_tower_ignores_range() {
+        let sinks = scan_sinks([
+            SinkCandidate {
+                energy: 800,
+                ..candidate(1, SinkKind::Tower, 1)
+            },
+            SinkCandidate {
+                energy: 100,
+                ..candidate(2, SinkKind::Tower, 20)
+            },
+        ]);
+        assert_eq!(sinks.tower, Some(1));
+        assert_eq!(sinks.emptiest_tower, Some(2));
+    }
+
+    #[test]
+    fn repair_goes_by_decay_then_by_damage() {
+        let worn = |item, range, hits, ticks| SinkCandidate {
+            repair: Some((hits, 1_000, ticks)),
+            ..candidate(item, SinkKind::Other, range)
+        };
+        let sinks = scan_sinks([
+            worn(1, 2, 900, 5_000),
+            worn(2, 9, 800, 300),
+            // the same time left, but further below its threshold
+            worn(3, 12, 100, 300),
+            // above its threshold, so not in need at all
+            worn(4, 0, 1_000, 1),
+        ]);
+        assert_eq!(sinks.repair, Some(3));
+        assert_eq!(sinks.closest_repair, Some(1));
+    }
+}
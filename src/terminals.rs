@@ -0,0 +1,75 @@
+use js_sys::JsString;
+use log::*;
+use screeps::{constants::ResourceType, game};
+
+use crate::{config::room_config, owned_rooms};
+
+// how often terminals are checked for energy to ship off
+pub const TERMINAL_INTERVAL: u32 = 50;
+// anything smaller isn't worth the cooldown it costs
+const MIN_SEND: u32 = 1_000;
+
+// how much energy to send out of a terminal holding `terminal_energy`, when storage is
+// `excess` over its threshold and every unit delivered costs `cost_per_unit` more. None if
+// the send isn't worth it, including when the cost would outweigh what arrives
+pub fn send_amount(excess: u32, terminal_energy: u32, cost_per_unit: f64) -> Option<u32> {
+    if cost_per_unit >= 1.0 {
+        return None;
+    }
+
+    // the cost comes out of the terminal on top of the amount sent
+    let affordable = (terminal_energy as f64 / (1.0 + cost_per_unit)).floor() as u32;
+    let amount = excess.min(affordable);
+    (amount >= MIN_SEND).then_some(amount)
+}
+
+// ships energy from rooms whose storage is over their configured threshold to the room
+// their config points at
+pub fn run_terminals() {
+    for room in owned_rooms() {
+        let config = room_config(room.name());
+        let Some(target) = config.terminal_target else {
+            continue;
+        };
+        if target == room.name() {
+            continue;
+        }
+        let (Some(terminal), Some(storage)) = (room.terminal(), room.storage()) else {
+            continue;
+        };
+        if terminal.cooldown() > 0 {
+            continue;
+        }
+
+        let stored = storage
+            .store()
+            .get_used_capacity(Some(ResourceType::Energy));
+        let Some(excess) = stored.checked_sub(config.terminal_energy_threshold) else {
+            continue;
+        };
+
+        let from = JsString::from(room.name().to_string());
+        let to = JsString::from(target.to_string());
+        let cost_per_unit =
+            game::market::calc_transaction_cost(MIN_SEND, &from, &to) as f64 / MIN_SEND as f64;
+        let terminal_energy = terminal
+            .store()
+            .get_used_capacity(Some(ResourceType::Energy));
+        let Some(amount) = send_amount(excess, terminal_energy, cost_per_unit) else {
+            continue;
+        };
+
+        match terminal.send(ResourceType::Energy, amount, target, None) {
+            Ok(()) => info!(
+                "sent {amount} energy from {} to {target} (~{:.0} cost)",
+                room.name(),
+                amount as f64 * cost_per_unit
+            ),
+            Err(e) => warn!(
+                "couldn't send energy from {} to {target}: {:?}",
+                room.name(),
+                e
+            ),
+        }
+    }
+}
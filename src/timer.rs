@@ -0,0 +1,119 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use log::*;
+use screeps::game;
+
+thread_local! {
+    // how many TimerLogs are currently alive, used to indent nested timers
+    static DEPTH: Cell<usize> = Cell::new(0);
+
+    // total cpu measured under each timer name this tick
+    static TIMINGS: RefCell<HashMap<String, f64>> = RefCell::new(HashMap::new());
+}
+
+#[cfg(feature = "intent-stats")]
+thread_local! {
+    // how many of each creep action went through and how many errored this tick
+    static INTENTS: RefCell<std::collections::BTreeMap<&'static str, (u32, u32)>> =
+        RefCell::new(std::collections::BTreeMap::new());
+}
+
+// passes `result` straight through, counting it as a successful or failed `action` when
+// built with intent-stats
+#[cfg(feature = "intent-stats")]
+pub fn intent<T, E>(action: &'static str, result: Result<T, E>) -> Result<T, E> {
+    INTENTS.with_borrow_mut(|intents| {
+        let (ok, err) = intents.entry(action).or_default();
+        if result.is_ok() {
+            *ok += 1;
+        } else {
+            *err += 1;
+        }
+    });
+    result
+}
+
+#[cfg(not(feature = "intent-stats"))]
+#[inline(always)]
+pub fn intent<T, E>(_action: &'static str, result: Result<T, E>) -> Result<T, E> {
+    result
+}
+
+// measures the cpu spent between its creation and its drop, logging it when dropped
+pub struct TimerLog {
+    name: String,
+    start: f64,
+    last_lap: f64,
+    depth: usize,
+}
+
+impl TimerLog {
+    pub fn new(name: impl Into<String>) -> Self {
+        let depth = DEPTH.get();
+        DEPTH.set(depth + 1);
+
+        let start = game::cpu::get_used();
+        TimerLog {
+            name: name.into(),
+            start,
+            last_lap: start,
+            depth,
+        }
+    }
+
+    // logs the cpu used since the last lap (or since the timer started) without ending it
+    pub fn lap(&mut self, label: &str) {
+        let now = game::cpu::get_used();
+        info!(
+            "{}{} [{}]: {:.2}",
+            indent(self.depth),
+            self.name,
+            label,
+            now - self.last_lap
+        );
+        self.last_lap = now;
+    }
+}
+
+impl Drop for TimerLog {
+    fn drop(&mut self) {
+        let elapsed = game::cpu::get_used() - self.start;
+        DEPTH.set(self.depth);
+
+        TIMINGS.with_borrow_mut(|timings| {
+            *timings.entry(self.name.clone()).or_default() += elapsed;
+        });
+
+        info!("{}{}: {:.2}", indent(self.depth), self.name, elapsed);
+    }
+}
+
+// logs everything measured this tick, most expensive first, and starts the next tick fresh
+pub fn log_breakdown() {
+    let mut timings: Vec<(String, f64)> = TIMINGS.take().into_iter().collect();
+    timings.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+    for (name, elapsed) in timings {
+        info!("{: >12}: {:.2}", name, elapsed);
+    }
+
+    #[cfg(feature = "intent-stats")]
+    {
+        let intents: Vec<String> = INTENTS
+            .take()
+            .into_iter()
+            .map(|(action, (ok, err))| match err {
+                0 => format!("{action}: {ok} ok"),
+                _ => format!("{action}: {ok} ok / {err} err"),
+            })
+            .collect();
+        if !intents.is_empty() {
+            info!("intents: {}", intents.join(", "));
+        }
+    }
+}
+
+fn indent(depth: usize) -> String {
+    "  ".repeat(depth)
+}
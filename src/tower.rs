@@ -0,0 +1,132 @@
+use log::*;
+use screeps::{
+    constants::{
+        Part, ResourceType, StructureType, TOWER_FALLOFF, TOWER_FALLOFF_RANGE, TOWER_OPTIMAL_RANGE,
+        TOWER_POWER_ATTACK,
+    },
+    find,
+    local::Position,
+    objects::{Creep, StructureTower},
+    prelude::*,
+};
+
+// towers won't heal or repair below this much energy, so a peacetime repair spree
+// can't leave them empty when hostiles finally show up
+pub const TOWER_ENERGY_RESERVE: u32 = 500;
+
+pub fn run_tower(tower: &StructureTower) {
+    let hostiles = tower
+        .room()
+        .map(|room| room.find(find::HOSTILE_CREEPS, None))
+        .unwrap_or_default();
+
+    if let Some(target) = select_tower_target(tower, &hostiles) {
+        tower.attack(target).unwrap_or_else(|e| {
+            warn!("unable to attack target: {:?}", e);
+        });
+        return;
+    }
+
+    if tower.store().get_used_capacity(Some(ResourceType::Energy)) <= TOWER_ENERGY_RESERVE {
+        return;
+    }
+
+    let Some(room) = tower.room() else {
+        return;
+    };
+
+    // heal whichever friendly creep is missing the most hits
+    if let Some(creep) = room
+        .find(find::MY_CREEPS, None)
+        .into_iter()
+        .filter(|creep| creep.hits() < creep.hits_max())
+        .max_by_key(|creep| creep.hits_max() - creep.hits())
+    {
+        tower.heal(&creep).unwrap_or_else(|e| {
+            warn!("unable to heal creep: {:?}", e);
+        });
+        return;
+    }
+
+    // otherwise patch up the most worn down structure, leaving walls and ramparts
+    // alone since their hits pools would soak up every bit of spare energy
+    if let Some(structure) = room
+        .find(find::STRUCTURES, None)
+        .iter()
+        .map(|structure| structure.as_structure())
+        .filter(|structure| {
+            !matches!(
+                structure.structure_type(),
+                StructureType::Wall | StructureType::Rampart
+            )
+        })
+        .filter(|structure| structure.hits() < structure.hits_max())
+        .min_by(|a, b| hits_ratio(a).total_cmp(&hits_ratio(b)))
+    {
+        tower.repair(structure).unwrap_or_else(|e| {
+            warn!("unable to repair structure: {:?}", e);
+        });
+    }
+}
+
+fn hits_ratio<T: HasHits>(object: &T) -> f64 {
+    object.hits() as f64 / object.hits_max() as f64
+}
+
+// healers first, since chipping at a tank is pointless while something undoes the damage,
+// then anything we can finish off this tick, then whatever has the fewest hits left.
+// `damage` is how much we'd deal at a given range
+pub fn select_target(
+    from: Position,
+    hostiles: &[Creep],
+    damage: impl Fn(u32) -> u32,
+) -> Option<&Creep> {
+    hostiles.iter().min_by_key(|hostile| {
+        let range = from.get_range_to(hostile.pos());
+        let killable = hostile.hits() <= damage(range);
+        let healer = hostile.get_active_bodyparts(Part::Heal) > 0;
+
+        (!healer, !killable, hostile.hits())
+    })
+}
+
+// the hostile `tower` should shoot at, going by what its attack does at each range
+pub fn select_tower_target<'a>(tower: &StructureTower, hostiles: &'a [Creep]) -> Option<&'a Creep> {
+    select_target(tower.pos(), hostiles, |range| {
+        tower_power_at_range(TOWER_POWER_ATTACK, range)
+    })
+}
+
+// full power out to TOWER_OPTIMAL_RANGE, then falling off linearly until it bottoms out
+// at TOWER_FALLOFF_RANGE
+pub fn tower_power_at_range(power: u32, range: u32) -> u32 {
+    let range = range.clamp(TOWER_OPTIMAL_RANGE, TOWER_FALLOFF_RANGE);
+    let falloff = TOWER_FALLOFF * (range - TOWER_OPTIMAL_RANGE) as f32
+        / (TOWER_FALLOFF_RANGE - TOWER_OPTIMAL_RANGE) as f32;
+
+    (power as f32 * (1.0 - falloff)).round() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_power_up_close() {
+        assert_eq!(tower_power_at_range(TOWER_POWER_ATTACK, 1), 600);
+        assert_eq!(tower_power_at_range(TOWER_POWER_ATTACK, 5), 600);
+    }
+
+    #[test]
+    fn bottoms_out_at_falloff_range() {
+        assert_eq!(tower_power_at_range(TOWER_POWER_ATTACK, 20), 150);
+        assert_eq!(tower_power_at_range(TOWER_POWER_ATTACK, 40), 150);
+    }
+
+    #[test]
+    fn falls_off_linearly_in_between() {
+        assert_eq!(tower_power_at_range(TOWER_POWER_ATTACK, 6), 570);
+        assert_eq!(tower_power_at_range(TOWER_POWER_ATTACK, 12), 390);
+        assert_eq!(tower_power_at_range(TOWER_POWER_ATTACK, 19), 180);
+    }
+}
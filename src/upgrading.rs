@@ -0,0 +1,229 @@
+use std::collections::{HashMap, HashSet};
+
+use log::*;
+use screeps::{
+    constants::{ErrorCode, Part, ResourceType, Terrain, UPGRADE_CONTROLLER_POWER},
+    enums::StructureObject,
+    find,
+    local::{LocalRoomTerrain, Position, RoomXY},
+    objects::{Creep, Room},
+    prelude::*,
+};
+
+use crate::find_cache;
+use crate::movement::DefaultMove;
+use crate::owned_rooms;
+use crate::sources::adjacent_tiles;
+use crate::structures::{controller_container, controller_link};
+use crate::targeting::downgrade_timer;
+use crate::CreepTarget;
+
+// a controller down to this fraction of its level's downgrade timer has gone well past
+// the top-ups at targeting::downgrade_threshold without anyone coming by, and gets a
+// creep pulled off whatever it was doing
+const DOWNGRADE_EMERGENCY_FRACTION: u32 = 10;
+
+// how few ticks a controller at `level` can have left before it's an emergency
+fn downgrade_emergency_ticks(level: u8) -> u32 {
+    downgrade_timer(level) / DOWNGRADE_EMERGENCY_FRACTION
+}
+
+// whichever of the controller's container and link is closer to it, the container if
+// they're as close as each other
+pub fn closer_store<T>(
+    controller: Position,
+    container: Option<(Position, T)>,
+    link: Option<(Position, T)>,
+) -> Option<T> {
+    container
+        .into_iter()
+        .chain(link)
+        .min_by_key(|(pos, _)| pos.get_range_to(controller))
+        .map(|(_, store)| store)
+}
+
+// the container or link keeping the controller supplied, i.e. the one within upgrade
+// range of it. The closest one wins if there's more than one
+pub fn controller_store(room: &Room) -> Option<StructureObject> {
+    let controller = room.controller()?;
+    let container = controller_container(room)
+        .and_then(|id| id.resolve())
+        .map(|container| {
+            (
+                container.pos(),
+                StructureObject::StructureContainer(container),
+            )
+        });
+    let link = controller_link(room)
+        .and_then(|id| id.resolve())
+        .map(|link| (link.pos(), StructureObject::StructureLink(link)));
+
+    closer_store(controller.pos(), container, link)
+}
+
+// where a static upgrader stands: on top of a container, or on a free tile next to a link
+// that's still within upgrade range
+fn upgrade_post(room: &Room, controller: Position, store: &StructureObject) -> Option<Position> {
+    if let StructureObject::StructureContainer(container) = store {
+        return Some(container.pos());
+    }
+
+    let terrain = LocalRoomTerrain::from(room.get_terrain());
+    let blocked: HashSet<RoomXY> = find_cache::structures(room)
+        .iter()
+        .filter(|structure| {
+            !matches!(
+                structure,
+                StructureObject::StructureRoad(_)
+                    | StructureObject::StructureContainer(_)
+                    | StructureObject::StructureRampart(_)
+            )
+        })
+        .map(|structure| structure.pos().xy())
+        .collect();
+
+    adjacent_tiles(store.pos().xy())
+        .filter(|xy| terrain.get(*xy) != Terrain::Wall && !blocked.contains(xy))
+        .map(|xy| Position::new(xy.x, xy.y, room.name()))
+        .filter(|pos| pos.in_range_to(controller, 3))
+        .min_by_key(|pos| pos.get_range_to(controller))
+}
+
+// parks next to the controller's store for good, topping up from it and upgrading every
+// tick without ever having to walk anywhere
+pub fn run_static_upgrader(creep: &Creep) {
+    let Some(room) = creep.room() else {
+        return;
+    };
+    let Some(controller) = room.controller() else {
+        return;
+    };
+    let Some(store) = controller_store(&room) else {
+        return;
+    };
+    let Some(post) = upgrade_post(&room, controller.pos(), &store) else {
+        return;
+    };
+
+    if creep.pos() != post {
+        let _ = creep.default_move_to(&post);
+        return;
+    }
+
+    // keep enough on hand for the next tick's upgrade, since the withdraw only lands then
+    let per_tick = creep.get_active_bodyparts(Part::Work) as u32 * UPGRADE_CONTROLLER_POWER;
+    let energy = creep.store().get_used_capacity(Some(ResourceType::Energy));
+    if energy <= per_tick {
+        if let Some(withdrawable) = store.as_withdrawable() {
+            match creep.withdraw(withdrawable, ResourceType::Energy, None) {
+                Ok(()) | Err(ErrorCode::NotEnough) => {}
+                Err(e) => warn!("couldn't withdraw for upgrading: {:?}", e),
+            }
+        }
+    }
+
+    if energy > 0 {
+        creep.upgrade_controller(&controller).unwrap_or_else(|e| {
+            warn!("couldn't upgrade: {:?}", e);
+        });
+    }
+}
+
+// for every one of our controllers about to downgrade with nobody upgrading it, takes the
+// closest creep carrying energy off its task and sends it to upgrade instead
+pub fn rescue_controllers(creep_targets: &mut HashMap<String, CreepTarget>) {
+    for room in owned_rooms() {
+        let Some(controller) = room.controller() else {
+            continue;
+        };
+        let ticks = controller.ticks_to_downgrade();
+        if ticks >= downgrade_emergency_ticks(controller.level()) {
+            continue;
+        }
+
+        let id = controller.id();
+        let creeps = room.find(find::MY_CREEPS, None);
+        let upgrading = creeps.iter().any(|creep| {
+            matches!(creep_targets.get(&creep.name()), Some(CreepTarget::Upgrade(target)) if *target == id)
+        });
+        if upgrading {
+            continue;
+        }
+
+        let Some(creep) = creeps
+            .iter()
+            .filter(|creep| {
+                creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0
+                    && creep
+                        .body()
+                        .iter()
+                        .any(|part| part.part() == Part::Work && part.hits() > 0)
+            })
+            .min_by_key(|creep| creep.pos().get_range_to(controller.pos()))
+        else {
+            // this'll keep happening every tick until something changes, so don't flood
+            // the log with it
+            if ticks % 100 == 0 {
+                warn!(
+                    "controller in {} downgrades in {ticks} ticks and nobody can upgrade it",
+                    room.name()
+                );
+            }
+            continue;
+        };
+
+        warn!(
+            "controller in {} downgrades in {ticks} ticks, sending {} to upgrade it",
+            room.name(),
+            creep.name()
+        );
+        creep_targets.insert(creep.name(), CreepTarget::Upgrade(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use screeps::local::{RoomCoordinate, RoomName};
+
+    use super::*;
+    use crate::targeting::downgrade_threshold;
+
+    fn pos(x: u8, y: u8) -> Position {
+        let room: RoomName = "W1N1".parse().unwrap();
+        Position::new(
+            RoomCoordinate::new(x).unwrap(),
+            RoomCoordinate::new(y).unwrap(),
+            room,
+        )
+    }
+
+    #[test]
+    fn emergencies_come_after_the_top_ups() {
+        for level in 1..=8 {
+            let emergency = downgrade_emergency_ticks(level);
+            assert!(emergency > 0);
+            assert!(emergency < downgrade_threshold(level), "level {level}");
+        }
+        assert_eq!(downgrade_emergency_ticks(2), 1_000);
+        assert_eq!(downgrade_emergency_ticks(8), 20_000);
+    }
+
+    #[test]
+    fn closer_store_wins() {
+        let controller = pos(20, 20);
+        let container = Some((pos(22, 22), "container"));
+        let link = Some((pos(21, 19), "link"));
+        assert_eq!(closer_store(controller, container, link), Some("link"));
+        assert_eq!(closer_store(controller, container, None), Some("container"));
+        assert_eq!(closer_store(controller, None, link), Some("link"));
+        assert_eq!(closer_store::<&str>(controller, None, None), None);
+    }
+
+    #[test]
+    fn container_wins_a_tie() {
+        let controller = pos(20, 20);
+        let container = Some((pos(20, 22), "container"));
+        let link = Some((pos(22, 20), "link"));
+        assert_eq!(closer_store(controller, container, link), Some("container"));
+    }
+}
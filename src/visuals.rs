@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+
+use screeps::{
+    find, game,
+    objects::{Room, TextAlign, TextStyle},
+    prelude::*,
+};
+
+use crate::body::Role;
+use crate::spawning;
+use crate::{body_type_shares, text_bar};
+
+// how often the dashboard is drawn. Visuals only last the tick they're drawn in, so it
+// blinks, but that's plenty to keep an eye on things without paying for it every tick
+pub const DASHBOARD_INTERVAL: u32 = 5;
+// text lines are about this far apart at the default font size
+const LINE_HEIGHT: f32 = 0.8;
+
+// a small hud next to the controller: energy, controller progress, cpu and who's alive
+pub fn draw_dashboard(room: &Room) {
+    let Some(controller) = room.controller() else {
+        return;
+    };
+
+    let capacity = room.energy_capacity_available();
+    let energy = room.energy_available();
+    let mut lines = vec![format!(
+        "energy [{}] {energy}/{capacity}",
+        text_bar(energy as f64 / capacity.max(1) as f64)
+    )];
+    let reserved = spawning::reserved_spawn_energy(room.name());
+    if reserved > 0 {
+        lines.push(format!("saving {reserved} for the next spawn"));
+    }
+
+    lines.push(if controller.level() == 8 {
+        "rcl 8".to_string()
+    } else {
+        format!(
+            "rcl {} [{}] {:.0}%",
+            controller.level(),
+            text_bar(controller.progress() as f64 / controller.progress_total().max(1) as f64),
+            100.0 * controller.progress() as f64 / controller.progress_total().max(1) as f64
+        )
+    });
+
+    lines.push(format!(
+        "cpu {:.1}/{} bucket {}",
+        game::cpu::get_used(),
+        game::cpu::limit(),
+        game::cpu::bucket()
+    ));
+
+    let mut roles: BTreeMap<&str, usize> = BTreeMap::new();
+    for creep in room.find(find::MY_CREEPS, None) {
+        *roles
+            .entry(Role::of_creep(&creep.name()).name())
+            .or_default() += 1;
+    }
+    lines.extend(
+        roles
+            .into_iter()
+            .map(|(role, count)| format!("{role} {count}")),
+    );
+
+    let bodies: BTreeMap<String, f64> = body_type_shares().into_iter().collect();
+    lines.extend(
+        bodies
+            .into_iter()
+            .map(|(body, ratio)| format!("{body} [{}]", text_bar(ratio))),
+    );
+
+    // off to whichever side of the controller has more room
+    let pos = controller.pos();
+    let (x, align) = if pos.x().u8() < 25 {
+        (pos.x().u8() as f32 + 2.0, TextAlign::Left)
+    } else {
+        (pos.x().u8() as f32 - 2.0, TextAlign::Right)
+    };
+    let top = (pos.y().u8() as f32).min(49.0 - LINE_HEIGHT * lines.len() as f32);
+
+    let visual = room.visual();
+    for (i, line) in lines.into_iter().enumerate() {
+        visual.text(
+            x,
+            top + LINE_HEIGHT * i as f32,
+            line,
+            Some(
+                TextStyle::default()
+                    .align(align)
+                    .color("#ffffff")
+                    .custom_font("0.6 monospace")
+                    .opacity(0.8),
+            ),
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use js_sys::Reflect;
+use screeps::objects::Creep;
+use wasm_bindgen::JsValue;
+
+// the mode a creep should be in, given the one it's in and what it's carrying. It keeps
+// gathering until its store is full and keeps spending until it's empty, so it never
+// turns back halfway through either
+pub fn next_working(working: bool, used: u32, capacity: u32) -> bool {
+    if capacity == 0 {
+        return working;
+    }
+    if working {
+        used > 0
+    } else {
+        used >= capacity
+    }
+}
+
+// whether the creep is out spending what it carries rather than gathering more, kept in
+// its memory
+pub fn is_working(creep: &Creep) -> bool {
+    Reflect::get(&creep.memory(), &JsValue::from_str("working"))
+        .ok()
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+fn set_working(creep: &Creep, working: bool) {
+    let _ = Reflect::set(
+        &creep.memory(),
+        &JsValue::from_str("working"),
+        &JsValue::from_bool(working),
+    );
+}
+
+// switches the creep's mode the moment its store fills up or runs dry, returning whether
+// it switched
+pub fn update_working(creep: &Creep) -> bool {
+    let store = creep.store();
+    let working = is_working(creep);
+    let next = next_working(
+        working,
+        store.get_used_capacity(None),
+        store.get_capacity(None),
+    );
+    if next != working {
+        set_working(creep, next);
+    }
+    next != working
+}
+
+// when there's nothing left to gather, a creep carrying anything at all goes and spends
+// it instead of waiting around half full. Returns whether it did
+pub fn work_with_what_it_has(creep: &Creep) -> bool {
+    if creep.store().get_used_capacity(None) == 0 {
+        return false;
+    }
+    set_working(creep, true);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_creeps_go_gathering() {
+        assert!(!next_working(true, 0, 100));
+        assert!(!next_working(false, 0, 100));
+    }
+
+    #[test]
+    fn full_creeps_go_working() {
+        assert!(next_working(false, 100, 100));
+        assert!(next_working(true, 100, 100));
+    }
+
+    #[test]
+    fn partly_full_creeps_carry_on() {
+        assert!(next_working(true, 1, 100));
+        assert!(next_working(true, 99, 100));
+        assert!(!next_working(false, 1, 100));
+        assert!(!next_working(false, 99, 100));
+    }
+
+    #[test]
+    fn creeps_without_carry_stay_put() {
+        assert!(next_working(true, 0, 0));
+        assert!(!next_working(false, 0, 0));
+    }
+}